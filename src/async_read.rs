@@ -0,0 +1,90 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use tokio::io::{AsyncRead, ReadBuf};
+
+use types::{Result, Error};
+use generic::{GenericMetadata, guess_format};
+
+/// Number of bytes which are read before the first attempt to load metadata.
+const INITIAL_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Attempts to load metadata for an image contained in the provided asynchronous stream.
+///
+/// This function determines the format of the image automatically, like `load()`, but it
+/// does not block on reading the stream. Data is accumulated in memory and the regular
+/// metadata decoders are run on it; if more data is needed to find the metadata, the buffer
+/// is grown and decoding is restarted. Therefore, only the beginning of the stream is usually
+/// read. Note that PNG chunks which are located beyond the initial buffer, e.g. textual chunks
+/// following the image data, may be missing from the result.
+///
+/// # Examples
+///
+/// ```no_run
+/// # extern crate immeta;
+/// # extern crate tokio;
+/// # fn main() {
+/// let mut data: &[u8] = &[0xff, 0xd8];   // pretend that this is an actual image
+/// let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+/// let metadata = runtime.block_on(immeta::load_from_async_read(&mut data));
+/// # }
+/// ```
+pub fn load_from_async_read<R: ?Sized + AsyncRead + Unpin>(r: &mut R) -> LoadFromAsyncRead<'_, R> {
+    LoadFromAsyncRead {
+        reader: r,
+        buf: Vec::new(),
+        target_len: INITIAL_BUFFER_SIZE,
+        eof: false
+    }
+}
+
+/// A future returned by `load_from_async_read()`.
+pub struct LoadFromAsyncRead<'a, R: ?Sized + 'a> {
+    reader: &'a mut R,
+    buf: Vec<u8>,
+    target_len: usize,
+    eof: bool
+}
+
+impl<'a, R: ?Sized + AsyncRead + Unpin> Future for LoadFromAsyncRead<'a, R> {
+    type Output = Result<GenericMetadata>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<GenericMetadata>> {
+        let this = &mut *self;
+        loop {
+            while !this.eof && this.buf.len() < this.target_len {
+                let mut chunk = [0u8; 8192];
+                let n = {
+                    let mut rb = ReadBuf::new(&mut chunk);
+                    match Pin::new(&mut *this.reader).poll_read(cx, &mut rb) {
+                        Poll::Pending => return Poll::Pending,
+                        Poll::Ready(Err(e)) => return Poll::Ready(Err(e.into())),
+                        Poll::Ready(Ok(())) => rb.filled().len()
+                    }
+                };
+                if n == 0 {
+                    this.eof = true;
+                } else {
+                    this.buf.extend_from_slice(&chunk[..n]);
+                }
+            }
+
+            match load_buffered(&this.buf) {
+                Err(Error::UnexpectedEndOfFile(_)) if !this.eof => this.target_len *= 2,
+                result => return Poll::Ready(result)
+            }
+        }
+    }
+}
+
+/// Loads metadata from the beginning of an image, using the decoder for its format.
+///
+/// Unlike `load_from_buf()`, errors of the decoder are returned, so the caller can tell
+/// whether more data is needed.
+fn load_buffered(buf: &[u8]) -> Result<GenericMetadata> {
+    if guess_format(buf).is_none() && buf.len() < 12 {
+        return Err(unexpected_eof!("when detecting image format"));
+    }
+    GenericMetadata::from_buf(buf)
+}
@@ -0,0 +1,135 @@
+//! Metadata of BMP images.
+
+use std::io::Read;
+
+use byteorder::{ReadBytesExt, LittleEndian};
+
+use types::{Result, Dimensions};
+use traits::LoadableMetadata;
+use utils::ReadExt;
+
+/// Compression method used in an image.
+///
+/// These methods directly correspond to those defined for the `biCompression` field of
+/// Windows bitmap headers.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Compression {
+    /// No compression.
+    Rgb,
+    /// Run-length encoding for 8 bits per pixel images.
+    Rle8,
+    /// Run-length encoding for 4 bits per pixel images.
+    Rle4,
+    /// No compression, pixel format is defined by RGB bit masks.
+    Bitfields,
+    /// Pixel data is a JPEG image.
+    Jpeg,
+    /// Pixel data is a PNG image.
+    Png,
+    /// No compression, pixel format is defined by RGBA bit masks.
+    AlphaBitfields,
+    /// No compression, CMYK pixel data.
+    Cmyk,
+    /// Run-length encoding for 8 bits per pixel CMYK images.
+    CmykRle8,
+    /// Run-length encoding for 4 bits per pixel CMYK images.
+    CmykRle4
+}
+
+impl Compression {
+    fn from_u32(n: u32) -> Option<Compression> {
+        match n {
+            0  => Some(Compression::Rgb),
+            1  => Some(Compression::Rle8),
+            2  => Some(Compression::Rle4),
+            3  => Some(Compression::Bitfields),
+            4  => Some(Compression::Jpeg),
+            5  => Some(Compression::Png),
+            6  => Some(Compression::AlphaBitfields),
+            11 => Some(Compression::Cmyk),
+            12 => Some(Compression::CmykRle8),
+            13 => Some(Compression::CmykRle4),
+            _  => None
+        }
+    }
+}
+
+/// Size of the OS/2 1.x BITMAPCOREHEADER.
+const CORE_HEADER_SIZE: u32 = 12;
+/// Size of the BITMAPINFOHEADER; later versions of the header extend it.
+const INFO_HEADER_SIZE: u32 = 40;
+
+/// Represents metadata of a BMP image.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Metadata {
+    /// Image size.
+    pub dimensions: Dimensions,
+    /// Number of bits per pixel.
+    pub bits_per_pixel: u16,
+    /// Compression method used in the image.
+    pub compression: Compression,
+    /// Whether pixel rows are stored from top to bottom.
+    ///
+    /// Usually BMP images are stored bottom-up; top-down images are indicated by negative
+    /// height in the header.
+    pub top_down: bool
+}
+
+impl LoadableMetadata for Metadata {
+    fn load<R: ?Sized + Read>(r: &mut R) -> Result<Metadata> {
+        let mut signature = [0u8; 2];
+        if try!(r.read_exact_0(&mut signature)) != signature.len() {
+            return Err(unexpected_eof!("when reading BMP signature"));
+        }
+
+        if &signature != b"BM" {
+            return Err(invalid_format!("invalid BMP signature: {:?}", signature));
+        }
+
+        // file size, two reserved fields and pixel data offset
+        if try!(r.skip_exact_0(12)) != 12 {
+            return Err(unexpected_eof!("when reading BMP file header"));
+        }
+
+        let header_size = try_if_eof!(r.read_u32::<LittleEndian>(), "when reading DIB header size");
+        if header_size == CORE_HEADER_SIZE {
+            let width = try_if_eof!(r.read_u16::<LittleEndian>(), "when reading width");
+            let height = try_if_eof!(r.read_u16::<LittleEndian>(), "when reading height");
+            let _planes = try_if_eof!(r.read_u16::<LittleEndian>(), "when reading number of planes");
+            let bits_per_pixel = try_if_eof!(r.read_u16::<LittleEndian>(), "when reading bits per pixel");
+
+            return Ok(Metadata {
+                dimensions: (width, height).into(),
+                bits_per_pixel: bits_per_pixel,
+                compression: Compression::Rgb,
+                top_down: false
+            });
+        }
+
+        if header_size < INFO_HEADER_SIZE {
+            return Err(invalid_format!("unsupported DIB header size: {}", header_size));
+        }
+
+        let width = try_if_eof!(r.read_i32::<LittleEndian>(), "when reading width");
+        let height = try_if_eof!(r.read_i32::<LittleEndian>(), "when reading height");
+        let _planes = try_if_eof!(r.read_u16::<LittleEndian>(), "when reading number of planes");
+        let bits_per_pixel = try_if_eof!(r.read_u16::<LittleEndian>(), "when reading bits per pixel");
+        let compression = try_if_eof!(r.read_u32::<LittleEndian>(), "when reading compression method");
+
+        if width < 0 {
+            return Err(invalid_format!("invalid image width: {}", width));
+        }
+
+        let compression = try!(
+            Compression::from_u32(compression)
+                .ok_or(invalid_format!("invalid compression method: {}", compression))
+        );
+
+        Ok(Metadata {
+            dimensions: (width, (height as i64).abs()).into(),
+            bits_per_pixel: bits_per_pixel,
+            compression: compression,
+            top_down: height < 0
+        })
+    }
+}
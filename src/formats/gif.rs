@@ -3,6 +3,7 @@
 use std::io::{BufReader, Read, BufRead};
 use std::borrow::Cow;
 use std::str;
+use std::time::Duration;
 
 use byteorder::{ReadBytesExt, LittleEndian};
 
@@ -55,6 +56,19 @@ fn skip_blocks<R: ?Sized + BufRead, F>(r: &mut R, on_eof: F) -> Result<()>
     }
 }
 
+fn read_blocks<R: ?Sized + BufRead, F>(r: &mut R, on_eof: F) -> Result<Vec<u8>>
+    where F: Fn() -> Cow<'static, str>
+{
+    let mut data = Vec::new();
+    loop {
+        let n = try_if_eof!(r.read_u8(), on_eof()) as u64;
+        if n == 0 { return Ok(data); }
+        if try!((&mut *r).take(n).read_to_end(&mut data)) as u64 != n {
+            return Err(unexpected_eof!(on_eof()));
+        }
+    }
+}
+
 /// Contains information about a color table (global or local).
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub struct ColorTable {
@@ -378,7 +392,8 @@ impl ApplicationExtension {
         str::from_utf8(&self.authentication_code).ok()
     }
 
-    fn load<R: ?Sized + BufRead>(index: usize, r: &mut R) -> Result<ApplicationExtension> {
+    /// Loads the block, returning it together with the contents of its data sub-blocks.
+    fn load<R: ?Sized + BufRead>(index: usize, r: &mut R) -> Result<(ApplicationExtension, Vec<u8>)> {
         const NAME: &'static str = "application extension block";
 
         let block_size = try_if_eof!(r.read_u8(), "when reading block size of {} {}", NAME, index);
@@ -387,37 +402,36 @@ impl ApplicationExtension {
         }
 
         let mut application_identifier = [0u8; 8];
-        if try!(r.read_exact_0(&mut application_identifier)) != application_identifier.len() {
-            return Err(unexpected_eof!("while reading application identifier in {} {}", NAME, index));
-        }
+        try_if_eof!(r.read_full(&mut application_identifier),
+                    "while reading application identifier in {} {}", NAME, index);
 
         let mut authentication_code = [0u8; 3];
-        if try!(r.read_exact_0(&mut authentication_code)) != authentication_code.len() {
-            return Err(unexpected_eof!("while reading authentication code in {} {}", NAME, index));
-        }
+        try_if_eof!(r.read_full(&mut authentication_code),
+                    "while reading authentication code in {} {}", NAME, index);
 
-        try!(skip_blocks(r, || format!("when reading application data of {} {}", NAME, index).into()));
+        let data = try!(read_blocks(r, || format!("when reading application data of {} {}", NAME, index).into()));
 
-        Ok(ApplicationExtension {
+        Ok((ApplicationExtension {
             application_identifier: application_identifier,
             authentication_code: authentication_code
-        })
+        }, data))
     }
 }
 
 /// Represents a comment extension block.
 ///
 /// Comment block does not contain any metadata, so this struct is used for uniformity
-/// as a placeholder in the enum.
+/// as a placeholder in the enum. The comment text itself is available in `Metadata::comments`.
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub struct CommentExtension;
 
 impl CommentExtension {
-    fn load<R: ?Sized + BufRead>(index: usize, r: &mut R) -> Result<CommentExtension> {
+    /// Loads the block, returning it together with the comment text.
+    fn load<R: ?Sized + BufRead>(index: usize, r: &mut R) -> Result<(CommentExtension, String)> {
         const NAME: &'static str = "comments extension block";
-        try!(skip_blocks(r, || format!("when reading comment data of {} {}", NAME, index).into()));
+        let data = try!(read_blocks(r, || format!("when reading comment data of {} {}", NAME, index).into()));
 
-        Ok(CommentExtension)
+        Ok((CommentExtension, String::from_utf8_lossy(&data).into_owned()))
     }
 }
 
@@ -468,6 +482,28 @@ pub struct Metadata {
     /// See also `pixel_aspect_ratio_approx()` method.
     pub pixel_aspect_ratio: u8,
 
+    /// Number of times the animation should be repeated, from the NETSCAPE2.0 application
+    /// extension.
+    ///
+    /// `Some(0)` means that the animation loops infinitely; `None` means that the extension
+    /// is absent, so the animation should be played once.
+    pub loop_count: Option<u16>,
+
+    /// Text of all comment extension blocks, in the order of their appearance.
+    pub comments: Vec<String>,
+
+    /// Number of frames, i.e. the number of image descriptor blocks.
+    ///
+    /// This is the same value as the one returned by `frames_number()`.
+    pub frame_count: usize,
+
+    /// Delay times of all frames, in one hundredths of a second.
+    ///
+    /// The delay of a frame is taken from the graphic control extension block preceding its
+    /// image descriptor; frames without such block have zero delay. Zero delays are stored
+    /// as they are, even though many viewers display such frames with some default delay.
+    pub frame_delays: Vec<u16>,
+
     /// Metadata for each block in the GIF image.
     pub blocks: Vec<Block>
 }
@@ -494,6 +530,12 @@ impl Metadata {
         }).count()
     }
 
+    /// Computes the total duration of the animation, i.e. the sum of all frame delays.
+    pub fn total_duration(&self) -> Duration {
+        let centiseconds: u64 = self.frame_delays.iter().map(|&d| d as u64).sum();
+        Duration::from_millis(centiseconds * 10)
+    }
+
     /// Returns `true` if the image is animated, `false` otherwise.
     ///
     /// This is currently decided based on the number of frames. If there are more than one frames,
@@ -543,18 +585,40 @@ impl LoadableMetadata for Metadata {
         }
 
         let mut blocks = Vec::new();
+        let mut loop_count = None;
+        let mut comments = Vec::new();
+        let mut frame_delays = Vec::new();
+        let mut delay = 0;
         let mut index = 0usize;
         loop {
             let separator = try!(r.read_u8().map_err(if_eof!("when reading separator of block {}", index)));
             let block = match separator {
-                0x2c => Block::ImageDescriptor(try!(ImageDescriptor::load(index, &mut r))),
+                0x2c => {
+                    frame_delays.push(delay);
+                    delay = 0;
+                    Block::ImageDescriptor(try!(ImageDescriptor::load(index, &mut r)))
+                }
                 0x21 => {
                     let label = try!(r.read_u8().map_err(if_eof!("when reading label of block {}", index)));
                     match label {
                         0x01 => Block::PlainTextExtension(try!(PlainTextExtension::load(index, &mut r))),
-                        0xf9 => Block::GraphicControlExtension(try!(GraphicControlExtension::load(index, &mut r))),
-                        0xfe => Block::CommentExtension(try!(CommentExtension::load(index, &mut r))),
-                        0xff => Block::ApplicationExtension(try!(ApplicationExtension::load(index, &mut r))),
+                        0xf9 => {
+                            let gce = try!(GraphicControlExtension::load(index, &mut r));
+                            delay = gce.delay_time;
+                            Block::GraphicControlExtension(gce)
+                        }
+                        0xfe => {
+                            let (ext, comment) = try!(CommentExtension::load(index, &mut r));
+                            comments.push(comment);
+                            Block::CommentExtension(ext)
+                        }
+                        0xff => {
+                            let (ext, data) = try!(ApplicationExtension::load(index, &mut r));
+                            if loop_count.is_none() {
+                                loop_count = parse_loop_count(&ext, &data);
+                            }
+                            Block::ApplicationExtension(ext)
+                        }
                         _ => return Err(invalid_format!("unknown extension type of block {}: 0x{:X}", index, label))
                     }
                 },
@@ -584,7 +648,28 @@ impl LoadableMetadata for Metadata {
             background_color_index: background_color_index,
             pixel_aspect_ratio: pixel_aspect_ratio,
 
+            loop_count: loop_count,
+            comments: comments,
+
+            frame_count: frame_delays.len(),
+            frame_delays: frame_delays,
+
             blocks: blocks
         })
     }
 }
+
+/// Extracts the loop count from a NETSCAPE2.0 (or the equivalent ANIMEXTS1.0) application
+/// extension.
+///
+/// Its data consists of a single sub-block with 0x01 identifier followed by a two-byte loop count.
+fn parse_loop_count(ext: &ApplicationExtension, data: &[u8]) -> Option<u16> {
+    let known = match (&ext.application_identifier, &ext.authentication_code) {
+        (b"NETSCAPE", b"2.0") | (b"ANIMEXTS", b"1.0") => true,
+        _ => false
+    };
+    if !known || data.len() < 3 || data[0] != 0x01 {
+        return None;
+    }
+    Some(data[1] as u16 | (data[2] as u16) << 8)
+}
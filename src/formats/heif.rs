@@ -0,0 +1,243 @@
+//! Metadata of HEIF and AVIF images.
+//!
+//! Both formats are based on ISO base media file format, which consists of nested boxes.
+//! Image dimensions are stored in the `ispe` property of the primary item, which is
+//! found inside the `meta` box.
+
+use std::io::Read;
+
+use byteorder::{ReadBytesExt, BigEndian};
+
+use types::{Result, Dimensions};
+use traits::LoadableMetadata;
+use utils::ReadExt;
+
+/// Brand of a HEIF file, determined from its `ftyp` box.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Brand {
+    /// AVIF image (`avif` brand).
+    Avif,
+    /// HEVC-coded HEIF image (`heic` or `heix` brand), e.g. a photo from an iPhone.
+    Heic,
+    /// Generic HEIF image (`mif1` brand).
+    Mif1
+}
+
+impl Brand {
+    fn from_fourcc(fourcc: &[u8]) -> Option<Brand> {
+        match fourcc {
+            b"avif" => Some(Brand::Avif),
+            b"heic" | b"heix" => Some(Brand::Heic),
+            b"mif1" => Some(Brand::Mif1),
+            _ => None
+        }
+    }
+}
+
+/// Represents metadata of a HEIF or AVIF image.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Metadata {
+    /// Brand of the file.
+    ///
+    /// The major brand is used if it is known; otherwise the most specific of the compatible
+    /// brands is used.
+    pub brand: Brand,
+    /// Size of the primary image, before any transformations (e.g. rotation) are applied.
+    pub dimensions: Dimensions
+}
+
+/// Maximum size of the `meta` box which is loaded into memory.
+const MAX_META_SIZE: u64 = 16 * 1024 * 1024;
+
+impl LoadableMetadata for Metadata {
+    fn load<R: ?Sized + Read>(r: &mut R) -> Result<Metadata> {
+        let (box_type, size) = match try!(read_box_header(r)) {
+            Some(hdr) => hdr,
+            None => return Err(unexpected_eof!("when reading ftyp box"))
+        };
+        if &box_type != b"ftyp" {
+            return Err(invalid_format!("invalid HEIF header: {:?}", box_type));
+        }
+        let ftyp = try!(read_box_data(r, size, "when reading ftyp box"));
+        let brand = try!(parse_ftyp(&ftyp).ok_or(invalid_format!("unsupported HEIF brand")));
+
+        loop {
+            let (box_type, size) = match try!(read_box_header(r)) {
+                Some(hdr) => hdr,
+                None => return Err(unexpected_eof!("when searching for meta box"))
+            };
+            if &box_type == b"meta" {
+                let meta = try!(read_box_data(r, size, "when reading meta box"));
+                let dimensions = try!(parse_meta(&meta));
+                return Ok(Metadata {
+                    brand: brand,
+                    dimensions: dimensions
+                });
+            }
+            match size {
+                Some(size) => if try!(r.skip_exact_0(size)) != size {
+                    return Err(unexpected_eof!("when skipping {} box",
+                                               String::from_utf8_lossy(&box_type)));
+                },
+                None => return Err(unexpected_eof!("when searching for meta box"))
+            }
+        }
+    }
+}
+
+/// Reads type and body size of the next box.
+///
+/// Body size is `None` if the box extends to the end of the stream. Returns `None` if the
+/// stream ends before the box.
+fn read_box_header<R: ?Sized + Read>(r: &mut R) -> Result<Option<([u8; 4], Option<u64>)>> {
+    let mut hdr = [0u8; 8];
+    match try!(r.read_exact_0(&mut hdr)) {
+        0 => return Ok(None),
+        8 => {}
+        _ => return Err(unexpected_eof!("when reading box header"))
+    }
+
+    let box_type = [hdr[4], hdr[5], hdr[6], hdr[7]];
+    let size = match (&hdr[..4]).read_u32::<BigEndian>().unwrap() {
+        0 => return Ok(Some((box_type, None))),
+        1 => {
+            let size = try_if_eof!(r.read_u64::<BigEndian>(), "when reading box size");
+            if size < 16 {
+                return Err(invalid_format!("invalid box size: {}", size));
+            }
+            size - 16
+        }
+        n if n < 8 => return Err(invalid_format!("invalid box size: {}", n)),
+        n => n as u64 - 8
+    };
+    Ok(Some((box_type, Some(size))))
+}
+
+#[allow(clippy::unnecessary_map_or)]  // `Option::is_some_and()` needs Rust 1.70
+fn read_box_data<R: ?Sized + Read>(r: &mut R, size: Option<u64>, on_eof: &'static str) -> Result<Vec<u8>> {
+    let limit = match size {
+        Some(size) if size > MAX_META_SIZE => return Err(invalid_format!("box is too large: {}", size)),
+        Some(size) => size,
+        None => MAX_META_SIZE
+    };
+    let mut data = Vec::new();
+    try!(r.take(limit).read_to_end(&mut data));
+    if size.map_or(false, |size| data.len() as u64 != size) {
+        return Err(unexpected_eof!(on_eof));
+    }
+    Ok(data)
+}
+
+/// Splits the contents of a box into its child boxes.
+fn children(mut data: &[u8]) -> Result<Vec<([u8; 4], &[u8])>> {
+    let mut result = Vec::new();
+    while !data.is_empty() {
+        let (box_type, size) = match try!(read_box_header(&mut data)) {
+            Some((box_type, Some(size))) if size <= data.len() as u64 => (box_type, size as usize),
+            Some((box_type, None)) => (box_type, data.len()),
+            _ => return Err(invalid_format!("truncated box inside meta box"))
+        };
+        result.push((box_type, &data[..size]));
+        data = &data[size..];
+    }
+    Ok(result)
+}
+
+/// Splits version and flags of a full box from its contents.
+fn full_box(data: &[u8]) -> Result<(u8, u32, &[u8])> {
+    if data.len() < 4 {
+        return Err(invalid_format!("truncated full box"));
+    }
+    let flags = (&data[..4]).read_u32::<BigEndian>().unwrap() & 0xffffff;
+    Ok((data[0], flags, &data[4..]))
+}
+
+fn parse_ftyp(data: &[u8]) -> Option<Brand> {
+    if data.len() < 8 {
+        return None;
+    }
+    if let Some(brand) = Brand::from_fourcc(&data[..4]) {
+        return Some(brand);
+    }
+    // compatible brands follow the major brand and the minor version
+    let compatible: Vec<_> = data[8..].chunks(4).filter_map(Brand::from_fourcc).collect();
+    [Brand::Avif, Brand::Heic, Brand::Mif1].iter().cloned().find(|b| compatible.contains(b))
+}
+
+fn parse_meta(data: &[u8]) -> Result<Dimensions> {
+    let (_, _, data) = try!(full_box(data));
+
+    let mut primary_item = None;
+    let mut properties = Vec::new();
+    let mut associations = Vec::new();
+    for (box_type, data) in try!(children(data)) {
+        match &box_type {
+            b"pitm" => primary_item = Some(try!(parse_pitm(data))),
+            b"iprp" => for (box_type, data) in try!(children(data)) {
+                match &box_type {
+                    b"ipco" => properties = try!(children(data)),
+                    b"ipma" => associations.extend(try!(parse_ipma(data))),
+                    _ => {}
+                }
+            },
+            _ => {}
+        }
+    }
+
+    let primary_item = try!(primary_item.ok_or(invalid_format!("no primary item in meta box")));
+    for (item_id, index) in associations {
+        if item_id != primary_item || index == 0 {
+            continue;
+        }
+        match properties.get(index as usize - 1) {
+            Some(&(ref box_type, data)) if box_type == b"ispe" => return parse_ispe(data),
+            Some(_) => {}
+            None => return Err(invalid_format!("invalid property index: {}", index))
+        }
+    }
+    Err(invalid_format!("no ispe property for primary item {}", primary_item))
+}
+
+fn parse_pitm(data: &[u8]) -> Result<u32> {
+    let (version, _, mut r) = try!(full_box(data));
+    let item_id = if version == 0 {
+        r.read_u16::<BigEndian>().map(|n| n as u32)
+    } else {
+        r.read_u32::<BigEndian>()
+    };
+    item_id.map_err(|_| invalid_format!("truncated pitm box"))
+}
+
+/// Parses an `ipma` box into pairs of item IDs and one-based property indices.
+fn parse_ipma(data: &[u8]) -> Result<Vec<(u32, u16)>> {
+    let (version, flags, mut r) = try!(full_box(data));
+    let mut result = Vec::new();
+    let entry_count = try!(r.read_u32::<BigEndian>().map_err(|_| invalid_format!("truncated ipma box")));
+    for _ in 0..entry_count {
+        let item_id = if version < 1 {
+            r.read_u16::<BigEndian>().map(|n| n as u32)
+        } else {
+            r.read_u32::<BigEndian>()
+        };
+        let item_id = try!(item_id.map_err(|_| invalid_format!("truncated ipma box")));
+        let count = try!(r.read_u8().map_err(|_| invalid_format!("truncated ipma box")));
+        for _ in 0..count {
+            // the highest bit is the "essential" flag
+            let index = if flags & 1 != 0 {
+                r.read_u16::<BigEndian>().map(|n| n & 0x7fff)
+            } else {
+                r.read_u8().map(|n| (n & 0x7f) as u16)
+            };
+            let index = try!(index.map_err(|_| invalid_format!("truncated ipma box")));
+            result.push((item_id, index));
+        }
+    }
+    Ok(result)
+}
+
+fn parse_ispe(data: &[u8]) -> Result<Dimensions> {
+    let (_, _, mut r) = try!(full_box(data));
+    let width = try!(r.read_u32::<BigEndian>().map_err(|_| invalid_format!("truncated ispe box")));
+    let height = try!(r.read_u32::<BigEndian>().map_err(|_| invalid_format!("truncated ispe box")));
+    Ok((width, height).into())
+}
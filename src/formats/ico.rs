@@ -0,0 +1,192 @@
+//! Metadata of ICO and CUR images.
+//!
+//! Icon and cursor files contain a directory of images, usually of different sizes and color
+//! depths. Each image is either a BMP image without the file header or a complete PNG image.
+
+use std::io::Read;
+
+use byteorder::{ReadBytesExt, LittleEndian};
+
+use types::{self, Result, Dimensions};
+use traits::LoadableMetadata;
+use utils::ReadExt;
+
+/// Type of an icon file.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Kind {
+    Icon,
+    Cursor
+}
+
+impl Kind {
+    fn from_u16(n: u16) -> Option<Kind> {
+        match n {
+            1 => Some(Kind::Icon),
+            2 => Some(Kind::Cursor),
+            _ => None
+        }
+    }
+}
+
+/// Describes one image contained in an icon file.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Entry {
+    /// Image width in pixels, as recorded in the directory.
+    pub width: u16,
+    /// Image height in pixels, as recorded in the directory.
+    pub height: u16,
+    /// Number of bits per pixel, as recorded in the header of the image itself.
+    pub bit_count: u16,
+    /// Whether the image is stored in PNG format; otherwise it is a BMP image.
+    pub is_png: bool,
+    /// Coordinates of the cursor hotspot, for cursor files only.
+    pub hotspot: Option<(u16, u16)>
+}
+
+impl Entry {
+    /// Returns image dimensions of this entry.
+    #[inline]
+    pub fn dimensions(&self) -> Dimensions {
+        (self.width, self.height).into()
+    }
+}
+
+/// Represents metadata of an ICO or CUR image.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Metadata {
+    /// Whether this is an icon or a cursor.
+    pub kind: Kind,
+    /// All images contained in the file, in the order of the directory.
+    pub entries: Vec<Entry>
+}
+
+impl Metadata {
+    /// Returns dimensions of the largest image in the file.
+    pub fn dimensions(&self) -> Dimensions {
+        types::max_by_pixels(self.entries.iter().map(Entry::dimensions))
+            .unwrap_or(Dimensions { width: 0, height: 0 })
+    }
+}
+
+const ICONDIR_SIZE: u64 = 6;
+const ICONDIRENTRY_SIZE: u64 = 16;
+
+/// Number of bytes of an image which are needed to determine its format and bit count.
+const IMAGE_PREFIX_SIZE: usize = 26;
+
+const PNG_SIGNATURE: &'static [u8] = b"\x89PNG\r\n\x1a\n";
+
+/// Contains ICONDIRENTRY fields which are needed to load an entry.
+struct DirEntry {
+    width: u16,
+    height: u16,
+    hotspot: Option<(u16, u16)>,
+    size: u32,
+    offset: u32
+}
+
+impl LoadableMetadata for Metadata {
+    fn load<R: ?Sized + Read>(r: &mut R) -> Result<Metadata> {
+        let reserved = try_if_eof!(r.read_u16::<LittleEndian>(), "when reading icon header");
+        let kind = try_if_eof!(r.read_u16::<LittleEndian>(), "when reading icon type");
+        let count = try_if_eof!(r.read_u16::<LittleEndian>(), "when reading number of images");
+
+        let kind = match Kind::from_u16(kind) {
+            Some(kind) if reserved == 0 => kind,
+            _ => return Err(invalid_format!("invalid icon header"))
+        };
+        if count == 0 {
+            return Err(invalid_format!("icon contains no images"));
+        }
+
+        let mut dir = Vec::new();
+        for _ in 0..count {
+            let width = try_if_eof!(r.read_u8(), "when reading image width");
+            let height = try_if_eof!(r.read_u8(), "when reading image height");
+            let _color_count = try_if_eof!(r.read_u8(), "when reading number of colors");
+            let _reserved = try_if_eof!(r.read_u8(), "when reading icon directory entry");
+            // for icons, these are color planes and bit count, which are also recorded
+            // in the image itself
+            let hotspot_x = try_if_eof!(r.read_u16::<LittleEndian>(), "when reading icon directory entry");
+            let hotspot_y = try_if_eof!(r.read_u16::<LittleEndian>(), "when reading icon directory entry");
+            let size = try_if_eof!(r.read_u32::<LittleEndian>(), "when reading image size");
+            let offset = try_if_eof!(r.read_u32::<LittleEndian>(), "when reading image offset");
+
+            // zero in the directory means 256 pixels
+            dir.push(DirEntry {
+                width: if width == 0 { 256 } else { width as u16 },
+                height: if height == 0 { 256 } else { height as u16 },
+                hotspot: if kind == Kind::Cursor { Some((hotspot_x, hotspot_y)) } else { None },
+                size: size,
+                offset: offset
+            });
+        }
+
+        // images are visited in the order of their offsets so the stream is only read forward
+        let mut order: Vec<usize> = (0..dir.len()).collect();
+        order.sort_by_key(|&i| dir[i].offset);
+
+        let mut entries = vec![None; dir.len()];
+        let mut position = ICONDIR_SIZE + ICONDIRENTRY_SIZE * count as u64;
+        for i in order {
+            let d = &dir[i];
+            let offset = d.offset as u64;
+            if offset < position {
+                return Err(invalid_format!("image {} overlaps with other data", i));
+            }
+            if try!(r.skip_exact_0(offset - position)) != offset - position {
+                return Err(unexpected_eof!("when skipping to image {}", i));
+            }
+
+            let mut prefix = vec![0u8; ::std::cmp::min(d.size as usize, IMAGE_PREFIX_SIZE)];
+            if try!(r.read_exact_0(&mut prefix)) != prefix.len() {
+                return Err(unexpected_eof!("when reading image {}", i));
+            }
+            position = offset + prefix.len() as u64;
+
+            let (is_png, bit_count) = try!(parse_image_prefix(&prefix)
+                .ok_or(invalid_format!("image {} is neither a PNG nor a BMP image", i)));
+            entries[i] = Some(Entry {
+                width: d.width,
+                height: d.height,
+                bit_count: bit_count,
+                is_png: is_png,
+                hotspot: d.hotspot
+            });
+        }
+
+        Ok(Metadata {
+            kind: kind,
+            entries: entries.into_iter().map(Option::unwrap).collect()
+        })
+    }
+}
+
+/// Determines the format and the bit count of an image from its first bytes.
+fn parse_image_prefix(prefix: &[u8]) -> Option<(bool, u16)> {
+    if prefix.starts_with(PNG_SIGNATURE) {
+        if prefix.len() < IMAGE_PREFIX_SIZE || &prefix[12..16] != b"IHDR" {
+            return None;
+        }
+        let bit_depth = prefix[24] as u16;
+        let channels = match prefix[25] {
+            0 | 3 => 1,
+            2 => 3,
+            4 => 2,
+            6 => 4,
+            _ => return None
+        };
+        Some((true, bit_depth * channels))
+    } else {
+        // BITMAPINFOHEADER or one of its later versions
+        if prefix.len() < 16 {
+            return None;
+        }
+        let header_size = try_opt!((&prefix[0..4]).read_u32::<LittleEndian>().ok());
+        if header_size < 40 {
+            return None;
+        }
+        let bit_count = try_opt!((&prefix[14..16]).read_u16::<LittleEndian>().ok());
+        Some((false, bit_count))
+    }
+}
@@ -1,64 +1,1648 @@
 //! Metadata of JPEG images.
 
-use std::io::{BufReader, Read};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::collections::HashMap;
+use std::cmp;
 
-use byteorder::{ReadBytesExt, BigEndian};
+use byteorder::{ByteOrder, ReadBytesExt, WriteBytesExt, BigEndian, LittleEndian};
+use num::ToPrimitive;
 
-use types::{Result, Dimensions};
+use types::{Result, Dimensions, Rational, SignedRational, DateTime};
+pub use types::{Orientation, Rotation};
 use traits::LoadableMetadata;
-use utils::BufReadExt;
+use utils::{ReadExt, BufReadExt, CountingReader};
+use formats::tiff::{self, Ifd, Tag};
 
-/// Represents metadata of a JPEG image.
+/// Contains geographical position recorded in the EXIF GPS IFD.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct GpsInfo {
+    /// Latitude in decimal degrees; positive values are north of the equator.
+    pub latitude: f64,
+    /// Longitude in decimal degrees; positive values are east of the prime meridian.
+    pub longitude: f64,
+    /// Altitude in meters relative to the sea level, if available.
+    pub altitude: Option<f64>
+}
+
+/// Coding process used in an image, as determined by the SOF marker.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Coding {
+    /// Baseline sequential DCT, Huffman coding (SOF0).
+    Baseline,
+    /// Extended sequential DCT, Huffman coding (SOF1).
+    ExtendedSequential,
+    /// Progressive DCT, Huffman coding (SOF2).
+    Progressive,
+    /// Lossless (sequential), Huffman coding (SOF3).
+    Lossless,
+    /// Differential sequential DCT, Huffman coding (SOF5).
+    DifferentialSequential,
+    /// Differential progressive DCT, Huffman coding (SOF6).
+    DifferentialProgressive,
+    /// Differential lossless (sequential), Huffman coding (SOF7).
+    DifferentialLossless,
+    /// Extended sequential DCT, arithmetic coding (SOF9).
+    ExtendedSequentialArithmetic,
+    /// Progressive DCT, arithmetic coding (SOF10).
+    ProgressiveArithmetic,
+    /// Lossless (sequential), arithmetic coding (SOF11).
+    LosslessArithmetic,
+    /// Differential sequential DCT, arithmetic coding (SOF13).
+    DifferentialSequentialArithmetic,
+    /// Differential progressive DCT, arithmetic coding (SOF14).
+    DifferentialProgressiveArithmetic,
+    /// Differential lossless (sequential), arithmetic coding (SOF15).
+    DifferentialLosslessArithmetic
+}
+
+impl Coding {
+    fn from_marker(marker: u8) -> Option<Coding> {
+        match marker {
+            0xc0 => Some(Coding::Baseline),
+            0xc1 => Some(Coding::ExtendedSequential),
+            0xc2 => Some(Coding::Progressive),
+            0xc3 => Some(Coding::Lossless),
+            0xc5 => Some(Coding::DifferentialSequential),
+            0xc6 => Some(Coding::DifferentialProgressive),
+            0xc7 => Some(Coding::DifferentialLossless),
+            0xc9 => Some(Coding::ExtendedSequentialArithmetic),
+            0xca => Some(Coding::ProgressiveArithmetic),
+            0xcb => Some(Coding::LosslessArithmetic),
+            0xcd => Some(Coding::DifferentialSequentialArithmetic),
+            0xce => Some(Coding::DifferentialProgressiveArithmetic),
+            0xcf => Some(Coding::DifferentialLosslessArithmetic),
+            _ => None
+        }
+    }
+}
+
+/// Color space of an image, as determined by the number of components and the Adobe
+/// APP14 segment.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ColorSpace {
+    /// One component.
+    Grayscale,
+    /// Three components, Y, Cb and Cr.
+    YCbCr,
+    /// Three components stored without a color transform.
+    Rgb,
+    /// Four components stored without a color transform.
+    Cmyk,
+    /// Four components, Y, Cb, Cr and K.
+    Ycck,
+    /// Any other number of components.
+    Unknown
+}
+
+/// Units of pixel density values in a JFIF segment.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum DensityUnit {
+    /// No units; density values only specify the pixel aspect ratio.
+    None,
+    /// Dots per inch.
+    DotsPerInch,
+    /// Dots per centimeter.
+    DotsPerCm
+}
+
+impl DensityUnit {
+    fn from_u8(n: u8) -> Option<DensityUnit> {
+        match n {
+            0 => Some(DensityUnit::None),
+            1 => Some(DensityUnit::DotsPerInch),
+            2 => Some(DensityUnit::DotsPerCm),
+            _ => None
+        }
+    }
+}
+
+/// Value of an arbitrary EXIF tag.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Clone, PartialEq, Debug)]
+pub enum ExifValue {
+    /// Text of an ASCII tag, up to the first NUL byte.
+    Ascii(String),
+    /// Values of a byte tag or raw data of an undefined tag.
+    Bytes(Vec<u8>),
+    Shorts(Vec<u16>),
+    Longs(Vec<u32>),
+    Rationals(Vec<Rational>),
+    SignedRationals(Vec<SignedRational>)
+}
+
+impl ExifValue {
+    fn from_tag(tag: &Tag) -> Option<ExifValue> {
+        match tag.datatype() {
+            tiff::TagDatatype::Ascii => tag.get_ascii().ok().map(ExifValue::Ascii),
+            tiff::TagDatatype::Byte | tiff::TagDatatype::Undefined =>
+                Some(ExifValue::Bytes(tag.raw().to_vec())),
+            tiff::TagDatatype::Short => tag.get_shorts().ok().map(ExifValue::Shorts),
+            tiff::TagDatatype::Long => tag.get_longs().ok().map(ExifValue::Longs),
+            tiff::TagDatatype::Rational => tag.get_rationals().ok().map(ExifValue::Rationals),
+            tiff::TagDatatype::SignedRational =>
+                tag.get_signed_rationals().ok().map(ExifValue::SignedRationals),
+            _ => None
+        }
+    }
+}
+
+/// Pixel density recorded in a JFIF APP0 segment.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Density {
+    /// Horizontal pixel density.
+    pub x: u16,
+    /// Vertical pixel density.
+    pub y: u16,
+    /// Units of density values.
+    pub unit: DensityUnit
+}
+
+/// Copyright notice recorded in EXIF.
+///
+/// EXIF allows the notice to consist of two parts, for the photographer and for the editor.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Copyright {
+    /// Photographer copyright, if present.
+    pub photographer: Option<String>,
+    /// Editor copyright, if present.
+    pub editor: Option<String>
+}
+
+impl Copyright {
+    /// Parses the value of the Copyright tag, where the two parts are separated by NUL.
+    ///
+    /// An absent photographer part is recorded as a single space.
+    fn from_tag(tag: &Tag) -> Option<Copyright> {
+        if tag.datatype() != tiff::TagDatatype::Ascii {
+            return None;
+        }
+
+        fn part(s: Option<&[u8]>) -> Option<String> {
+            let s = String::from_utf8_lossy(try_opt!(s));
+            if s.trim().is_empty() { None } else { Some(s.into_owned()) }
+        }
+
+        let mut parts = tag.raw().split(|&b| b == 0);
+        let photographer = part(parts.next());
+        let editor = part(parts.next());
+        if photographer.is_none() && editor.is_none() {
+            None
+        } else {
+            Some(Copyright { photographer: photographer, editor: editor })
+        }
+    }
+}
+
+/// Common IPTC-IIM fields, recorded in the Photoshop APP13 segment.
 ///
-/// Currently it is very basic and only provides access to image dimensions.
+/// Text values are decoded as UTF-8; values which are not valid UTF-8 are assumed to be
+/// in Latin-1, which is the most common encoding of older files.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Iptc {
+    /// All keywords, in the order of their appearance.
+    pub keywords: Vec<String>,
+    /// Caption or abstract of the image.
+    pub caption: Option<String>,
+    /// Name of the creator of the image.
+    pub byline: Option<String>,
+    /// Name of the provider of the image.
+    pub credit: Option<String>
+}
+
+impl Iptc {
+    /// Parses IPTC-IIM datasets, keeping values of the application record only.
+    ///
+    /// Each dataset consists of the 0x1C tag marker, record and dataset numbers and
+    /// a two-byte length. Parsing stops at the first malformed dataset.
+    fn parse(mut data: &[u8]) -> Iptc {
+        let mut iptc = Iptc {
+            keywords: Vec::new(),
+            caption: None,
+            byline: None,
+            credit: None
+        };
+        while data.len() >= 5 && data[0] == 0x1c {
+            let (record, dataset) = (data[1], data[2]);
+            let size = (data[3] as usize) << 8 | data[4] as usize;
+            // extended datasets, with the highest bit of the length set, are not used
+            // for text values
+            if size & 0x8000 != 0 || data.len() < 5 + size {
+                break;
+            }
+            let value = &data[5..5 + size];
+            data = &data[5 + size..];
+
+            if record != 2 {
+                continue;
+            }
+            match dataset {
+                IPTC_KEYWORDS => iptc.keywords.push(iptc_string(value)),
+                IPTC_BYLINE if iptc.byline.is_none() => iptc.byline = Some(iptc_string(value)),
+                IPTC_CREDIT if iptc.credit.is_none() => iptc.credit = Some(iptc_string(value)),
+                IPTC_CAPTION if iptc.caption.is_none() => iptc.caption = Some(iptc_string(value)),
+                _ => {}
+            }
+        }
+        iptc
+    }
+}
+
+/// Units of EXIF resolution values.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ResolutionUnit {
+    /// No absolute unit of measurement.
+    None,
+    /// Pixels per inch.
+    Inch,
+    /// Pixels per centimeter.
+    Centimeter
+}
+
+impl ResolutionUnit {
+    fn from_u16(n: u16) -> Option<ResolutionUnit> {
+        match n {
+            1 => Some(ResolutionUnit::None),
+            2 => Some(ResolutionUnit::Inch),
+            3 => Some(ResolutionUnit::Centimeter),
+            _ => None
+        }
+    }
+}
+
+/// Image resolution recorded in EXIF.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Resolution {
+    /// Number of pixels per unit in the horizontal direction.
+    pub x: Rational,
+    /// Number of pixels per unit in the vertical direction.
+    pub y: Rational,
+    /// Units of resolution values.
+    pub unit: ResolutionUnit
+}
+
+/// Exposure program used by the camera, as recorded in the EXIF ExposureProgram tag.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ExposureProgram {
+    NotDefined,
+    Manual,
+    Normal,
+    AperturePriority,
+    ShutterPriority,
+    /// Biased toward depth of field.
+    Creative,
+    /// Biased toward fast shutter speed.
+    Action,
+    Portrait,
+    Landscape,
+    /// A value which is not defined by the EXIF standard.
+    Unknown(u16)
+}
+
+impl ExposureProgram {
+    fn from_u16(n: u16) -> ExposureProgram {
+        match n {
+            0 => ExposureProgram::NotDefined,
+            1 => ExposureProgram::Manual,
+            2 => ExposureProgram::Normal,
+            3 => ExposureProgram::AperturePriority,
+            4 => ExposureProgram::ShutterPriority,
+            5 => ExposureProgram::Creative,
+            6 => ExposureProgram::Action,
+            7 => ExposureProgram::Portrait,
+            8 => ExposureProgram::Landscape,
+            n => ExposureProgram::Unknown(n)
+        }
+    }
+}
+
+/// Metering mode used by the camera, as recorded in the EXIF MeteringMode tag.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum MeteringMode {
+    Average,
+    CenterWeightedAverage,
+    Spot,
+    MultiSpot,
+    /// Also known as matrix or evaluative metering.
+    Pattern,
+    Partial,
+    Other,
+    /// The mode is unknown (0) or the value is not defined by the EXIF standard.
+    Unknown(u16)
+}
+
+impl MeteringMode {
+    fn from_u16(n: u16) -> MeteringMode {
+        match n {
+            1 => MeteringMode::Average,
+            2 => MeteringMode::CenterWeightedAverage,
+            3 => MeteringMode::Spot,
+            4 => MeteringMode::MultiSpot,
+            5 => MeteringMode::Pattern,
+            6 => MeteringMode::Partial,
+            255 => MeteringMode::Other,
+            n => MeteringMode::Unknown(n)
+        }
+    }
+}
+
+/// White balance mode, as recorded in the EXIF WhiteBalance tag.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum WhiteBalance {
+    Auto,
+    Manual,
+    /// A value which is not defined by the EXIF standard.
+    Unknown(u16)
+}
+
+impl WhiteBalance {
+    fn from_u16(n: u16) -> WhiteBalance {
+        match n {
+            0 => WhiteBalance::Auto,
+            1 => WhiteBalance::Manual,
+            n => WhiteBalance::Unknown(n)
+        }
+    }
+}
+
+/// Flash firing mode, a part of the EXIF Flash tag.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum FlashMode {
+    Unknown,
+    /// Compulsory flash firing.
+    On,
+    /// Compulsory flash suppression.
+    Off,
+    Auto
+}
+
+/// Flash status, decoded from the bit field of the EXIF Flash tag.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Flash {
+    /// Whether the flash fired.
+    pub fired: bool,
+    /// Whether strobe return light was detected, if the camera is able to detect it.
+    pub return_detected: Option<bool>,
+    /// Flash firing mode.
+    pub mode: FlashMode,
+    /// Whether the camera has a flash function.
+    pub has_flash_function: bool,
+    /// Whether red-eye reduction was used.
+    pub red_eye_reduction: bool,
+    /// Raw value of the tag.
+    pub raw: u16
+}
+
+impl Flash {
+    /// Decodes the value of the Flash tag.
+    ///
+    /// The bits are, from the least significant one: flash fired, two bits of strobe return
+    /// status, two bits of flash mode, no flash function and red-eye reduction.
+    fn from_u16(n: u16) -> Flash {
+        Flash {
+            fired: n & 0x01 != 0,
+            return_detected: match (n >> 1) & 3 {
+                2 => Some(false),
+                3 => Some(true),
+                _ => None
+            },
+            mode: match (n >> 3) & 3 {
+                1 => FlashMode::On,
+                2 => FlashMode::Off,
+                3 => FlashMode::Auto,
+                _ => FlashMode::Unknown
+            },
+            has_flash_function: n & 0x20 == 0,
+            red_eye_reduction: n & 0x40 != 0,
+            raw: n
+        }
+    }
+}
+
+/// Options for loading JPEG metadata with `Metadata::load_with_options()`.
+///
+/// By default everything is parsed. Disabling parts which are not needed makes loading
+/// faster.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct Options {
+    /// Whether the EXIF segment should be parsed.
+    ///
+    /// If disabled, EXIF segments are skipped without being read, and all fields which come
+    /// from EXIF, e.g. `orientation`, are `None`.
+    pub parse_exif: bool,
+    /// Whether GPS information should be extracted from EXIF.
+    pub parse_gps: bool,
+    /// Whether the EXIF thumbnail should be extracted.
+    pub parse_thumbnail: bool,
+    /// Whether the XMP packet should be extracted.
+    pub parse_xmp: bool
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            parse_exif: true,
+            parse_gps: true,
+            parse_thumbnail: true,
+            parse_xmp: true
+        }
+    }
+}
+
+/// Represents metadata of a JPEG image.
+///
+/// Besides image dimensions, it provides access to some of the EXIF tags, if the image
+/// contains an EXIF segment.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Clone, PartialEq, Debug)]
 pub struct Metadata {
     /// Image size.
     pub dimensions: Dimensions,
-    // TODO: something else?
+    /// Coding process used in the image.
+    pub coding: Coding,
+    /// Number of bits per sample of each component, usually 8.
+    pub bits_per_sample: u8,
+    /// Number of color components in the image.
+    ///
+    /// Usually it is 1 for grayscale images and 3 for color (YCbCr) images.
+    pub num_components: u8,
+    /// Color transform from the Adobe APP14 segment, if available.
+    ///
+    /// 0 means that no transform is used, i.e. RGB or CMYK, 1 means YCbCr and 2 means YCCK.
+    /// Use `color_space()` to interpret it together with the number of components.
+    pub adobe_transform: Option<u8>,
+    /// Pixel density from the JFIF segment, if available.
+    pub density: Option<Density>,
+    /// Image resolution from EXIF, if available.
+    ///
+    /// Both horizontal and vertical resolution tags must be present. If the unit tag is
+    /// absent, inches are assumed, as EXIF specifies.
+    pub resolution: Option<Resolution>,
+    /// Image orientation from EXIF, if available.
+    pub orientation: Option<Orientation>,
+    /// Manufacturer of the camera from EXIF, if available.
+    pub make: Option<String>,
+    /// Model of the camera from EXIF, if available.
+    pub model: Option<String>,
+    /// Name and version of the software which created the image from EXIF, if available.
+    pub software: Option<String>,
+    /// Name of the image creator from EXIF, if available.
+    pub artist: Option<String>,
+    /// Copyright notice from EXIF, if available.
+    pub copyright: Option<Copyright>,
+    /// Date and time of the last image modification from EXIF, if available and valid.
+    pub date_time: Option<DateTime>,
+    /// Raw value of the EXIF date and time tag in "YYYY:MM:DD HH:MM:SS" format, if available.
+    pub date_time_raw: Option<String>,
+    /// Date and time when the image was taken, from the EXIF DateTimeOriginal tag, if
+    /// available and valid.
+    pub date_time_original: Option<DateTime>,
+    /// Date and time when the image was digitized, from the EXIF DateTimeDigitized tag, if
+    /// available and valid.
+    pub date_time_digitized: Option<DateTime>,
+    /// GPS position from EXIF, if available.
+    pub gps: Option<GpsInfo>,
+    /// Exposure time in seconds from EXIF, if available.
+    pub exposure_time: Option<Rational>,
+    /// F-number (aperture) from EXIF, if available.
+    pub f_number: Option<Rational>,
+    /// ISO speed rating from EXIF, if available.
+    pub iso_speed: Option<u16>,
+    /// Exposure bias (compensation) in APEX units from EXIF, if available.
+    pub exposure_bias: Option<SignedRational>,
+    /// Lens focal length in millimeters from EXIF, if available.
+    pub focal_length: Option<Rational>,
+    /// Exposure program from EXIF, if available.
+    pub exposure_program: Option<ExposureProgram>,
+    /// Metering mode from EXIF, if available.
+    pub metering_mode: Option<MeteringMode>,
+    /// Flash status from EXIF, if available.
+    pub flash: Option<Flash>,
+    /// White balance mode from EXIF, if available.
+    pub white_balance: Option<WhiteBalance>,
+    /// Raw contents of the EXIF MakerNote tag, if available.
+    ///
+    /// The format of maker notes is specific to camera manufacturers, so they are not
+    /// decoded. Offsets inside maker notes are often relative to the beginning of the file
+    /// rather than to the maker note itself, so they can't be interpreted without the rest
+    /// of the file anyway.
+    pub maker_note: Option<Vec<u8>>,
+    /// Text of the EXIF UserComment tag, if available.
+    ///
+    /// ASCII and undefined character codes are decoded as UTF-8, and Unicode is decoded as
+    /// UTF-16 in the byte order of EXIF data; invalid sequences are replaced with
+    /// U+FFFD REPLACEMENT CHARACTER. Comments in JIS encoding are not supported.
+    pub user_comment: Option<String>,
+    /// Version of the EXIF standard the data conforms to, e.g. "2.30", if available.
+    pub exif_version: Option<String>,
+    /// Version of the Flashpix format supported, e.g. "1.00", if available.
+    pub flashpix_version: Option<String>,
+    /// Image size recorded in the EXIF PixelXDimension and PixelYDimension tags, if both
+    /// are available.
+    ///
+    /// It should be equal to `dimensions`, but may differ if the image was edited without
+    /// updating EXIF; see `dimensions_consistent()`.
+    pub exif_dimensions: Option<Dimensions>,
+    /// Values of all tags of IFD0 and of the Exif sub-IFD, including those which are not
+    /// exposed by other fields.
+    ///
+    /// GPS tags and thumbnail tags are not included. Tags of datatypes which can't be
+    /// represented by `ExifValue` are omitted as well.
+    pub exif_tags: HashMap<u16, ExifValue>,
+    /// Contents of all comment (COM) segments, in the order of their appearance.
+    ///
+    /// Invalid UTF-8 sequences are replaced with U+FFFD REPLACEMENT CHARACTER.
+    pub comments: Vec<String>,
+    /// Embedded ICC color profile, if available.
+    ///
+    /// The profile is reassembled from all APP2 chunks. It is `None` if some of the chunks
+    /// are missing or do not follow in the order of their sequence numbers.
+    pub icc_profile: Option<Vec<u8>>,
+    /// Raw XMP packet, if available.
+    pub xmp: Option<String>,
+    /// Whether the image contains an EXIF segment, even if none of its tags are recognized
+    /// or its structure is invalid.
+    ///
+    /// APP1 segments are not examined if neither EXIF nor XMP is parsed according to
+    /// `Options`, so this is `false` then.
+    pub has_exif: bool,
+    /// Whether the image contains an XMP segment, even if it is not parsed according to
+    /// `Options`.
+    ///
+    /// The same as for `has_exif`, this is `false` if APP1 segments are not examined.
+    pub has_xmp: bool,
+    /// IPTC-IIM fields from the Photoshop APP13 segment, if available.
+    pub iptc: Option<Iptc>,
+
+    exif: Option<ExifSection>
 }
 
-impl LoadableMetadata for Metadata {
-    fn load<R: ?Sized + Read>(r: &mut R) -> Result<Metadata> {
+impl Metadata {
+    /// Returns `true` if the image has only one color component.
+    #[inline]
+    pub fn is_grayscale(&self) -> bool {
+        self.num_components == 1
+    }
+
+    /// Returns the color space of the image.
+    ///
+    /// Images with three components are assumed to be YCbCr and images with four components
+    /// are assumed to be CMYK, unless the Adobe color transform says otherwise.
+    pub fn color_space(&self) -> ColorSpace {
+        match (self.num_components, self.adobe_transform) {
+            (1, _) => ColorSpace::Grayscale,
+            (3, Some(0)) => ColorSpace::Rgb,
+            (3, _) => ColorSpace::YCbCr,
+            (4, Some(2)) => ColorSpace::Ycck,
+            (4, _) => ColorSpace::Cmyk,
+            _ => ColorSpace::Unknown
+        }
+    }
+
+    /// Returns image dimensions as the image should be displayed, i.e. taking EXIF
+    /// orientation into account.
+    ///
+    /// Width and height are swapped if the orientation requires rotation by 90 or 270 degrees.
+    pub fn oriented_dimensions(&self) -> Dimensions {
+        match self.orientation {
+            Some(Orientation::Rotate90) | Some(Orientation::Rotate270) |
+            Some(Orientation::Transpose) | Some(Orientation::Transverse) => self.dimensions.rotate90(),
+            _ => self.dimensions
+        }
+    }
+
+    /// Returns `false` if the image size recorded in EXIF differs from the actual size, which
+    /// is a sign of editing.
+    ///
+    /// `true` is returned if EXIF does not record the image size.
+    #[inline]
+    #[allow(clippy::unnecessary_map_or)]  // `Option::is_none_or()` needs Rust 1.82
+    pub fn dimensions_consistent(&self) -> bool {
+        self.exif_dimensions.map_or(true, |d| d == self.dimensions)
+    }
+
+    /// Returns raw data of the thumbnail image embedded in EXIF, if available.
+    ///
+    /// The thumbnail is itself a JPEG image.
+    #[inline]
+    pub fn thumbnail(&self) -> Option<&[u8]> {
+        self.exif.as_ref().and_then(|e| e.thumbnail.as_ref()).map(|t| &t[..])
+    }
+
+    /// Returns the raw structure of the EXIF segment, if available.
+    #[inline]
+    pub fn exif(&self) -> Option<&ExifSection> {
+        self.exif.as_ref()
+    }
+}
+
+impl Metadata {
+    /// Loads metadata from the given input stream, taking ownership of it.
+    ///
+    /// The stream is only read forward and is never seeked, so this method can be used with
+    /// non-seekable sources like network streams. It is equivalent to `LoadableMetadata::load()`.
+    #[inline]
+    pub fn load_from_reader<R: Read>(mut r: R) -> Result<Metadata> {
+        Metadata::load_with_options(&mut r, Options::default())
+    }
+
+    /// Loads metadata from the given input stream with the given options.
+    ///
+    /// `LoadableMetadata::load()` is equivalent to this method with default options.
+    pub fn load_with_options<R: ?Sized + Read>(r: &mut R, options: Options) -> Result<Metadata> {
+        try!(read_soi(r));
+
         let mut r = &mut BufReader::new(r);
-        loop {
-            if try!(r.skip_until(0xff)) == 0 {
-                return Err(unexpected_eof!("when searching for a marker"));
+        let mut density = None;
+        let mut adobe_transform = None;
+        let mut exif = None;
+        let mut comments = Vec::new();
+        let mut icc_chunks = Vec::new();
+        let mut xmp = None;
+        let mut has_exif = false;
+        let mut has_xmp = false;
+        let mut photoshop = None;
+        let mut frame = None;
+        while let Some((marker_type, size)) = try!(read_marker(r)) {
+            if let Some(coding) = Coding::from_marker(marker_type) {  // SOFn
+                let data = try!(read_segment(r, size, "when reading SOF segment"));
+                if frame.is_none() {
+                    frame = Some(try!(FrameHeader::parse(coding, &data)));
+                }
+                continue;
             }
-            
-            let marker_type = try_if_eof!(r.read_u8(), "when reading marker type");
-            if marker_type == 0 { continue; }  // skip "stuffed" byte
-
-            let has_size = match marker_type {
-                0xd0...0xd9 => false,
-                _ => true
-            };
-
-            let size = if has_size {
-                try_if_eof!(r.read_u16::<BigEndian>(), "when reading marker payload size") - 2
-            } else { 0 };
-
-            let dimensions = match marker_type {
-                0xc0 | 0xc2 => {  // maybe others?
-                    // skip one byte
-                    let _ = try_if_eof!(r.read_u8(), "when skipping to dimensions data");
-                    let h = try_if_eof!(r.read_u16::<BigEndian>(), "when reading height");
-                    let w = try_if_eof!(r.read_u16::<BigEndian>(), "when reading width");
-                    Some((w, h))
+
+            match marker_type {
+                0xe0 => {  // APP0, may contain JFIF header
+                    let data = try!(read_segment(r, size, "when reading APP0 segment"));
+                    if density.is_none() && data.starts_with(JFIF_IDENTIFIER) {
+                        density = parse_jfif_density(&data[JFIF_IDENTIFIER.len()..]);
+                    }
                 }
-                _ => None
-            };
+                0xe1 if !options.parse_exif && !options.parse_xmp => {
+                    if try!(r.skip_exact(size as u64)) != size as u64 {
+                        return Err(unexpected_eof!("when skipping APP1 segment"));
+                    }
+                }
+                0xe1 => {  // APP1, may contain EXIF or XMP
+                    let data = try!(read_segment(r, size, "when reading APP1 segment"));
+                    if data.starts_with(EXIF_IDENTIFIER) {
+                        has_exif = true;
+                        if exif.is_none() && options.parse_exif {
+                            let data = &data[EXIF_IDENTIFIER.len()..];
+                            // a broken EXIF structure does not make the image itself invalid
+                            exif = ExifSection::parse_with(data, options.parse_gps,
+                                                           options.parse_thumbnail).ok();
+                        }
+                    } else if data.starts_with(XMP_IDENTIFIER) {
+                        has_xmp = true;
+                        if xmp.is_none() && options.parse_xmp {
+                            let packet = &data[XMP_IDENTIFIER.len()..];
+                            xmp = Some(String::from_utf8_lossy(packet).into_owned());
+                        }
+                    }
+                }
+                0xe2 => {  // APP2, may contain a chunk of ICC profile
+                    let data = try!(read_segment(r, size, "when reading APP2 segment"));
+                    if data.len() >= ICC_IDENTIFIER.len() + 2 && data.starts_with(ICC_IDENTIFIER) {
+                        let seq_no = data[ICC_IDENTIFIER.len()];
+                        let count = data[ICC_IDENTIFIER.len() + 1];
+                        icc_chunks.push((seq_no, count, data[ICC_IDENTIFIER.len() + 2..].to_vec()));
+                    }
+                }
+                0xed => {  // APP13, may contain Photoshop image resources
+                    let data = try!(read_segment(r, size, "when reading APP13 segment"));
+                    if data.starts_with(PHOTOSHOP_IDENTIFIER) {
+                        // large resources are split between several segments
+                        photoshop.get_or_insert_with(Vec::new)
+                            .extend_from_slice(&data[PHOTOSHOP_IDENTIFIER.len()..]);
+                    }
+                }
+                0xee => {  // APP14, may contain Adobe color transform
+                    let data = try!(read_segment(r, size, "when reading APP14 segment"));
+                    if adobe_transform.is_none() && data.len() >= 12 && data.starts_with(ADOBE_IDENTIFIER) {
+                        // version, flags0 and flags1 precede the transform
+                        adobe_transform = Some(data[11]);
+                    }
+                }
+                0xfe => {  // COM
+                    let data = try!(read_segment(r, size, "when reading COM segment"));
+                    comments.push(String::from_utf8_lossy(&data).into_owned());
+                }
+                _ => {
+                    let size = size as u64;
+                    if try!(r.skip_exact(size)) != size {
+                        return Err(unexpected_eof!("when skipping marker payload"));
+                    }
+                }
+            }
+        }
+
+        let frame = match frame {
+            Some(frame) => frame,
+            None => return Err(invalid_format!("no SOF segment found, dimensions unknown"))
+        };
+
+        let fields = exif.as_ref().map(ExifFields::from_section).unwrap_or_else(ExifFields::default);
+        let date_time_raw = exif.as_ref().and_then(ExifSection::date_time_raw);
+        let sub_sec_time = fields.sub_sec_time;
+        Ok(Metadata {
+            dimensions: frame.dimensions,
+            coding: frame.coding,
+            bits_per_sample: frame.bits_per_sample,
+            num_components: frame.num_components,
+            adobe_transform: adobe_transform,
+            density: density,
+            resolution: fields.resolution,
+            orientation: exif.as_ref().and_then(ExifSection::orientation),
+            make: exif.as_ref().and_then(ExifSection::make),
+            model: exif.as_ref().and_then(ExifSection::model),
+            software: fields.software,
+            artist: fields.artist,
+            copyright: fields.copyright,
+            date_time: date_time_raw.as_ref().and_then(|s| DateTime::parse(s).ok())
+                .map(|dt| with_sub_sec_time(dt, sub_sec_time)),
+            date_time_raw: date_time_raw,
+            date_time_original: fields.date_time_original,
+            date_time_digitized: fields.date_time_digitized,
+            gps: fields.gps,
+            exposure_time: fields.exposure_time,
+            f_number: fields.f_number,
+            iso_speed: fields.iso_speed,
+            exposure_bias: fields.exposure_bias,
+            focal_length: fields.focal_length,
+            exposure_program: fields.exposure_program,
+            metering_mode: fields.metering_mode,
+            flash: fields.flash,
+            white_balance: fields.white_balance,
+            maker_note: fields.maker_note,
+            user_comment: fields.user_comment,
+            exif_version: fields.exif_version,
+            flashpix_version: fields.flashpix_version,
+            exif_dimensions: fields.exif_dimensions,
+            exif_tags: fields.exif_tags,
+            comments: comments,
+            icc_profile: assemble_icc_profile(icc_chunks),
+            xmp: xmp,
+            has_exif: has_exif,
+            has_xmp: has_xmp,
+            iptc: photoshop.as_ref()
+                .and_then(|data| find_photoshop_resource(data, PHOTOSHOP_IPTC_RESOURCE))
+                .map(Iptc::parse),
+            exif: exif
+        })
+    }
+}
+
+impl LoadableMetadata for Metadata {
+    #[inline]
+    fn load<R: ?Sized + Read>(r: &mut R) -> Result<Metadata> {
+        Metadata::load_with_options(r, Options::default())
+    }
+}
+
+/// Finds the next marker and reads the size of its payload.
+///
+/// Returns `None` when SOS or EOI is found. All metadata segments precede the scan data,
+/// so there is no need to look further than the first SOS; EOI terminates images without scans.
+fn read_marker<R: ?Sized + BufRead>(r: &mut R) -> Result<Option<(u8, u16)>> {
+    let marker_type = try!(find_marker(r));
+    if marker_type == 0xd9 || marker_type == 0xda {
+        return Ok(None);
+    }
+    let size = try!(read_payload_size(r, marker_type));
+    Ok(Some((marker_type, size)))
+}
+
+/// Skips to the next marker and returns its type.
+fn find_marker<R: ?Sized + BufRead>(r: &mut R) -> Result<u8> {
+    loop {
+        if try!(r.skip_until(0xff)) == 0 {
+            return Err(unexpected_eof!("when searching for a marker"));
+        }
+
+        // any number of 0xFF fill bytes may precede the marker type
+        let mut marker_type = 0xff;
+        while marker_type == 0xff {
+            marker_type = try_if_eof!(r.read_u8(), "when reading marker type");
+        }
+        if marker_type != 0 {  // skip "stuffed" byte
+            return Ok(marker_type);
+        }
+    }
+}
+
+/// Returns `true` if the marker stands alone, i.e. it is not followed by a segment.
+///
+/// These are TEM, RST0 to RST7, SOI and EOI. All other markers, including reserved ones,
+/// start a segment with a size field.
+fn is_standalone(marker_type: u8) -> bool {
+    match marker_type {
+        0x01 | 0xd0...0xd9 => true,
+        _ => false
+    }
+}
+
+/// Reads the size of the payload of a marker, excluding the size field itself.
+///
+/// The size of standalone markers is 0.
+fn read_payload_size<R: ?Sized + Read>(r: &mut R, marker_type: u8) -> Result<u16> {
+    if !is_standalone(marker_type) {
+        let size = try_if_eof!(r.read_u16::<BigEndian>(), "when reading marker payload size");
+        if size < 2 {
+            return Err(invalid_format!("segment size too small"));
+        }
+        Ok(size - 2)
+    } else {
+        Ok(0)
+    }
+}
+
+/// A raw JPEG segment.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Segment {
+    /// Marker type, i.e. the byte following 0xFF, e.g. 0xE0 for APP0.
+    pub marker: u8,
+    /// Position of the marker in the stream, i.e. of the 0xFF byte preceding the marker type.
+    pub offset: u64,
+    /// Number of bytes the segment occupies in the stream, including the marker and the size
+    /// field.
+    pub length: usize,
+    /// Segment payload, without the marker and the size field.
+    pub data: Vec<u8>
+}
+
+/// An iterator over raw segments of a JPEG image, returned by `segments()`.
+pub struct Segments<R> {
+    reader: BufReader<CountingReader<R>>,
+    done: bool
+}
+
+impl<R: Read> Segments<R> {
+    /// Returns the current position in the stream.
+    fn current_offset(&self) -> u64 {
+        self.reader.get_ref().count() - self.reader.buffer().len() as u64
+    }
+
+    fn read_next(&mut self) -> Result<Option<Segment>> {
+        let marker = try!(find_marker(&mut self.reader));
+        // fill bytes are not a part of the segment
+        let offset = self.current_offset() - 2;
+        if marker == 0xd9 || marker == 0xda {
+            return Ok(None);
+        }
+        let size = try!(read_payload_size(&mut self.reader, marker));
+        let data = try!(read_segment(&mut self.reader, size, "when reading segment"));
+        Ok(Some(Segment {
+            marker: marker,
+            offset: offset,
+            length: (self.current_offset() - offset) as usize,
+            data: data
+        }))
+    }
+}
+
+impl<R: Read> Iterator for Segments<R> {
+    type Item = Result<Segment>;
+
+    fn next(&mut self) -> Option<Result<Segment>> {
+        if self.done {
+            return None;
+        }
+        match self.read_next() {
+            Ok(Some(segment)) => Some(Ok(segment)),
+            Ok(None) => { self.done = true; None }
+            Err(e) => { self.done = true; Some(Err(e)) }
+        }
+    }
+}
+
+/// Returns an iterator over raw segments of the JPEG image contained in the provided stream.
+///
+/// This allows accessing segments which are not interpreted by `Metadata`, e.g. APPn segments
+/// of particular applications. Segments are returned in the order of their appearance,
+/// including those without payload, like SOI. Iteration stops at the first SOS or EOI marker,
+/// or after the first error.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::fs::File;
+/// use immeta::formats::jpeg;
+///
+/// let f = File::open("kitty.jpg").unwrap();
+/// for segment in jpeg::segments(f) {
+///     let segment = segment.unwrap();
+///     println!("marker {:02X}, {} bytes", segment.marker, segment.data.len());
+/// }
+/// ```
+pub fn segments<R: Read>(r: R) -> Segments<R> {
+    Segments {
+        reader: BufReader::new(CountingReader::new(r)),
+        done: false
+    }
+}
+
+/// Copies the JPEG image from `src` to `dst`, removing segments which may contain private
+/// metadata.
+///
+/// APP1 (EXIF and XMP), APP13 (IPTC and Photoshop) and COM segments are removed; all other
+/// segments and the image data are copied as is. Note that other segments, e.g. APPn segments
+/// of particular applications, may also contain metadata.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::fs::File;
+/// use immeta::formats::jpeg;
+///
+/// let src = File::open("kitty.jpg").unwrap();
+/// let dst = File::create("kitty-stripped.jpg").unwrap();
+/// jpeg::strip_metadata(src, dst).unwrap();
+/// ```
+pub fn strip_metadata<R: Read, W: Write>(src: R, mut dst: W) -> Result<()> {
+    let mut r = BufReader::new(src);
+    let soi = try!(read_soi(&mut r));
+    try!(dst.write_all(&soi));
+
+    loop {
+        let marker_type = try!(find_marker(&mut r));
+        let size = try!(read_payload_size(&mut r, marker_type));
+        match marker_type {
+            0xe1 | 0xed | 0xfe => {
+                if try!(r.skip_exact(size as u64)) != size as u64 {
+                    return Err(unexpected_eof!("when skipping marker payload"));
+                }
+                continue;
+            }
+            _ => {}
+        }
+
+        let data = try!(read_segment(&mut r, size, "when reading segment"));
+        try!(write_segment(&mut dst, marker_type, &data));
+
+        match marker_type {
+            // the scan data and all following segments are copied as is
+            0xda => { try!(io::copy(&mut r, &mut dst)); return Ok(()); }
+            0xd9 => return Ok(()),
+            _ => {}
+        }
+    }
+}
+
+/// Copies the JPEG image from `src` to `dst`, changing the value of the EXIF Orientation tag.
+///
+/// Only the tag value is rewritten; the image data is copied as is, so this is useful when
+/// the pixels have already been transformed in some other way. The image must contain
+/// an EXIF segment with the Orientation tag in IFD0, otherwise an error is returned.
+/// Segments preceding the image data are buffered, so nothing is written to `dst` if the
+/// tag can't be rewritten.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::fs::File;
+/// use immeta::formats::jpeg;
+///
+/// let src = File::open("kitty.jpg").unwrap();
+/// let dst = File::create("kitty-normal.jpg").unwrap();
+/// jpeg::set_orientation(src, dst, jpeg::Orientation::Normal).unwrap();
+/// ```
+pub fn set_orientation<R: Read, W: Write>(src: R, mut dst: W, orientation: Orientation) -> Result<()> {
+    let mut r = BufReader::new(src);
+    let soi = try!(read_soi(&mut r));
+
+    let mut segments = Vec::new();
+    let mut found = false;
+    loop {
+        let marker_type = try!(find_marker(&mut r));
+        let size = try!(read_payload_size(&mut r, marker_type));
+        let mut data = try!(read_segment(&mut r, size, "when reading segment"));
+        if marker_type == 0xe1 && !found && data.starts_with(EXIF_IDENTIFIER) {
+            let value = orientation.to_exif();
+            found = try!(patch_orientation(&mut data[EXIF_IDENTIFIER.len()..], value));
+        }
+        segments.push((marker_type, data));
+        if marker_type == 0xda || marker_type == 0xd9 {
+            break;
+        }
+    }
+    if !found {
+        return Err(invalid_format!("no EXIF Orientation tag to rewrite"));
+    }
 
-            if let Some(dimensions) = dimensions {
-                return Ok(Metadata {
-                    dimensions: dimensions.into()
-                });
+    try!(dst.write_all(&soi));
+    for (marker_type, data) in segments {
+        try!(write_segment(&mut dst, marker_type, &data));
+    }
+    // the scan data and all following segments are copied as is
+    try!(io::copy(&mut r, &mut dst));
+    Ok(())
+}
+
+/// Overwrites the value of the Orientation tag in IFD0 of the given TIFF structure.
+///
+/// Returns `false` if there is no such tag.
+fn patch_orientation(data: &mut [u8], value: u16) -> Result<bool> {
+    fn patch<B: ByteOrder>(data: &mut [u8], value: u16) -> Option<bool> {
+        let offset = try_opt!(try_opt!(data.get(4..8)).read_u32::<B>().ok()) as usize;
+        let count = try_opt!(try_opt!(data.get(offset..offset + 2)).read_u16::<B>().ok()) as usize;
+        for i in 0..count {
+            let entry = offset + 2 + i * 12;
+            let entry = try_opt!(data.get_mut(entry..entry + 12));
+            if (&entry[0..2]).read_u16::<B>().unwrap() != TAG_ORIENTATION {
+                continue;
+            }
+            // the value must be a single SHORT or LONG, which is stored in the entry itself
+            if (&entry[4..8]).read_u32::<B>().unwrap() != 1 {
+                return None;
             }
-            
-            let size = size as u64;
-            if try!(r.skip_exact(size)) != size {
-                return Err(unexpected_eof!("when skipping marker payload"));
+            match (&entry[2..4]).read_u16::<B>().unwrap() {
+                3 => (&mut entry[8..10]).write_u16::<B>(value).unwrap(),
+                4 => (&mut entry[8..12]).write_u32::<B>(value as u32).unwrap(),
+                _ => return None
             }
+            return Some(true);
         }
+        Some(false)
+    }
+
+    let result = match data.get(0..2) {
+        Some(b"II") => patch::<LittleEndian>(data, value),
+        Some(b"MM") => patch::<BigEndian>(data, value),
+        _ => None
+    };
+    result.ok_or(invalid_format!("invalid EXIF structure"))
+}
+
+fn write_segment<W: Write>(dst: &mut W, marker_type: u8, data: &[u8]) -> io::Result<()> {
+    try!(dst.write_all(&[0xff, marker_type]));
+    if !is_standalone(marker_type) {
+        try!(dst.write_all(&[((data.len() + 2) >> 8) as u8, (data.len() + 2) as u8]));
+    }
+    dst.write_all(data)
+}
+
+/// Contains the fields of the first SOF segment which are of interest.
+struct FrameHeader {
+    coding: Coding,
+    bits_per_sample: u8,
+    dimensions: Dimensions,
+    num_components: u8
+}
+
+impl FrameHeader {
+    fn parse(coding: Coding, data: &[u8]) -> Result<FrameHeader> {
+        let mut r = data;
+        let bits_per_sample = try_if_eof!(r.read_u8(), "when reading sample precision");
+        let h = try_if_eof!(r.read_u16::<BigEndian>(), "when reading height");
+        let w = try_if_eof!(r.read_u16::<BigEndian>(), "when reading width");
+        let num_components = try_if_eof!(r.read_u8(), "when reading number of components");
+        Ok(FrameHeader {
+            coding: coding,
+            bits_per_sample: bits_per_sample,
+            dimensions: (w, h).into(),
+            num_components: num_components
+        })
+    }
+}
+
+/// Reads the SOI marker which starts every JPEG image.
+fn read_soi<R: ?Sized + Read>(r: &mut R) -> Result<[u8; 2]> {
+    let mut soi = [0u8; 2];
+    try_if_eof!(r.read_full(&mut soi), "when reading SOI marker");
+    if soi != [0xff, 0xd8] {
+        return Err(invalid_format!("not a JPEG: missing SOI"));
+    }
+    Ok(soi)
+}
+
+fn read_segment<R: ?Sized + Read>(r: &mut R, size: u16, on_eof: &'static str) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    if try!(r.take(size as u64).read_to_end(&mut data)) != size as usize {
+        return Err(unexpected_eof!(on_eof));
+    }
+    Ok(data)
+}
+
+const XMP_IDENTIFIER: &'static [u8] = b"http://ns.adobe.com/xap/1.0/\0";
+
+const ICC_IDENTIFIER: &'static [u8] = b"ICC_PROFILE\0";
+
+const ADOBE_IDENTIFIER: &'static [u8] = b"Adobe";
+
+/// Concatenates ICC profile chunks given as `(sequence number, total count, data)` triples.
+///
+/// Sequence numbers start from 1. `None` is returned if there are no chunks or if they do not
+/// form a complete sequence in order.
+fn assemble_icc_profile(chunks: Vec<(u8, u8, Vec<u8>)>) -> Option<Vec<u8>> {
+    let count = match chunks.first() {
+        Some(&(_, count, _)) => count,
+        None => return None
+    };
+    if chunks.len() != count as usize {
+        return None;
+    }
+
+    let mut profile = Vec::new();
+    for (i, (seq_no, chunk_count, data)) in chunks.into_iter().enumerate() {
+        if seq_no as usize != i + 1 || chunk_count != count {
+            return None;
+        }
+        profile.extend(data);
+    }
+    Some(profile)
+}
+
+const PHOTOSHOP_IDENTIFIER: &'static [u8] = b"Photoshop 3.0\0";
+
+const PHOTOSHOP_IPTC_RESOURCE: u16 = 0x0404;
+
+const IPTC_KEYWORDS: u8 = 25;
+const IPTC_BYLINE: u8 = 80;
+const IPTC_CREDIT: u8 = 110;
+const IPTC_CAPTION: u8 = 120;
+
+/// Finds the data of a Photoshop image resource block with the given identifier.
+///
+/// Each block consists of the `8BIM` signature, a two-byte identifier, a Pascal string name
+/// padded to even size, a four-byte data size and the data, also padded to even size.
+fn find_photoshop_resource(mut data: &[u8], id: u16) -> Option<&[u8]> {
+    while data.len() >= 7 && data.starts_with(b"8BIM") {
+        let block_id = (data[4] as u16) << 8 | data[5] as u16;
+        let name_size = (data[6] as usize + 2) & !1;
+        let header_size = 6 + name_size + 4;
+        if data.len() < header_size {
+            return None;
+        }
+        let size = (&data[header_size - 4..]).read_u32::<BigEndian>().unwrap() as usize;
+        let rest = &data[header_size..];
+        if rest.len() < size {
+            return None;
+        }
+        if block_id == id {
+            return Some(&rest[..size]);
+        }
+        data = &rest[cmp::min(size + (size & 1), rest.len())..];
+    }
+    None
+}
+
+/// Decodes an IPTC text value, which is in UTF-8 or, in older files, in Latin-1.
+fn iptc_string(data: &[u8]) -> String {
+    match ::std::str::from_utf8(data) {
+        Ok(s) => s.to_owned(),
+        Err(_) => data.iter().map(|&b| b as char).collect()
+    }
+}
+
+const JFIF_IDENTIFIER: &'static [u8] = b"JFIF\0";
+
+/// Parses density fields of a JFIF segment following the identifier.
+///
+/// The layout is: two version bytes, units byte, two-byte X and Y densities.
+fn parse_jfif_density(data: &[u8]) -> Option<Density> {
+    if data.len() < 7 {
+        return None;
+    }
+    DensityUnit::from_u8(data[2]).map(|unit| Density {
+        x: (data[3] as u16) << 8 | data[4] as u16,
+        y: (data[5] as u16) << 8 | data[6] as u16,
+        unit: unit
+    })
+}
+
+const EXIF_IDENTIFIER: &'static [u8] = b"Exif\0\0";
+
+const TAG_MAKE: u16 = 0x010f;
+const TAG_MODEL: u16 = 0x0110;
+const TAG_ORIENTATION: u16 = 0x0112;
+const TAG_X_RESOLUTION: u16 = 0x011a;
+const TAG_Y_RESOLUTION: u16 = 0x011b;
+const TAG_RESOLUTION_UNIT: u16 = 0x0128;
+const TAG_SOFTWARE: u16 = 0x0131;
+const TAG_DATE_TIME: u16 = 0x0132;
+const TAG_ARTIST: u16 = 0x013b;
+const TAG_COPYRIGHT: u16 = 0x8298;
+const TAG_JPEG_INTERCHANGE_FORMAT: u16 = 0x0201;
+const TAG_JPEG_INTERCHANGE_FORMAT_LENGTH: u16 = 0x0202;
+const TAG_EXIF_IFD_POINTER: u16 = 0x8769;
+const TAG_GPS_IFD_POINTER: u16 = 0x8825;
+
+const TAG_EXPOSURE_TIME: u16 = 0x829a;
+const TAG_F_NUMBER: u16 = 0x829d;
+const TAG_EXPOSURE_PROGRAM: u16 = 0x8822;
+const TAG_ISO_SPEED_RATINGS: u16 = 0x8827;
+const TAG_EXIF_VERSION: u16 = 0x9000;
+const TAG_DATE_TIME_ORIGINAL: u16 = 0x9003;
+const TAG_DATE_TIME_DIGITIZED: u16 = 0x9004;
+const TAG_EXPOSURE_BIAS: u16 = 0x9204;
+const TAG_METERING_MODE: u16 = 0x9207;
+const TAG_FLASH: u16 = 0x9209;
+const TAG_FOCAL_LENGTH: u16 = 0x920a;
+const TAG_MAKER_NOTE: u16 = 0x927c;
+const TAG_USER_COMMENT: u16 = 0x9286;
+const TAG_SUB_SEC_TIME: u16 = 0x9290;
+const TAG_SUB_SEC_TIME_ORIGINAL: u16 = 0x9291;
+const TAG_SUB_SEC_TIME_DIGITIZED: u16 = 0x9292;
+const TAG_FLASHPIX_VERSION: u16 = 0xa000;
+const TAG_PIXEL_X_DIMENSION: u16 = 0xa002;
+const TAG_PIXEL_Y_DIMENSION: u16 = 0xa003;
+const TAG_WHITE_BALANCE: u16 = 0xa403;
+
+const TAG_GPS_LATITUDE_REF: u16 = 0x0001;
+const TAG_GPS_LATITUDE: u16 = 0x0002;
+const TAG_GPS_LONGITUDE_REF: u16 = 0x0003;
+const TAG_GPS_LONGITUDE: u16 = 0x0004;
+const TAG_GPS_ALTITUDE_REF: u16 = 0x0005;
+const TAG_GPS_ALTITUDE: u16 = 0x0006;
+
+/// Contains the raw structure of an EXIF segment.
+///
+/// This is mostly useful for diagnostics, e.g. to find out why some EXIF values are not
+/// recognized. Known tag values are available directly from `Metadata` fields.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Clone, PartialEq, Debug)]
+pub struct ExifSection {
+    /// Header of the TIFF structure containing EXIF data.
+    header: tiff::Header,
+    /// Main chain of IFDs; IFD0 describes the primary image.
+    ifds: Vec<Ifd>,
+    /// Exif sub-IFD referenced from IFD0.
+    exif_ifd: Option<Ifd>,
+    /// GPS sub-IFD referenced from IFD0.
+    gps_ifd: Option<Ifd>,
+    /// Thumbnail data referenced from IFD1.
+    thumbnail: Option<Vec<u8>>
+}
+
+impl ExifSection {
+    /// Parses EXIF data which starts with a TIFF header.
+    ///
+    /// This is the structure which follows the EXIF identifier in a JPEG APP1 segment; PNG
+    /// eXIf chunks contain it as well.
+    pub fn parse(data: &[u8]) -> Result<ExifSection> {
+        ExifSection::parse_with(data, true, true)
+    }
+
+    /// Parses EXIF data, optionally skipping the GPS sub-IFD and the thumbnail.
+    fn parse_with(data: &[u8], parse_gps: bool, parse_thumbnail: bool) -> Result<ExifSection> {
+        let header = try!(tiff::parse_header(data));
+        let ifds = try!(tiff::parse_ifds(data));
+
+        let (exif_ifd, gps_ifd) = match ifds.first() {
+            Some(ifd0) => (
+                try!(ifd0.sub_ifd(data, TAG_EXIF_IFD_POINTER)),
+                if parse_gps { try!(ifd0.sub_ifd(data, TAG_GPS_IFD_POINTER)) } else { None }
+            ),
+            None => (None, None)
+        };
+
+        let mut section = ExifSection {
+            header: header,
+            ifds: ifds,
+            exif_ifd: exif_ifd,
+            gps_ifd: gps_ifd,
+            thumbnail: None
+        };
+        if parse_thumbnail {
+            let thumbnail = section.thumbnail_ifd().and_then(|ifd1| thumbnail_data(data, ifd1));
+            section.thumbnail = thumbnail;
+        }
+
+        Ok(section)
+    }
+
+    /// Returns the byte order of EXIF data.
+    #[inline]
+    pub fn byte_order(&self) -> tiff::ByteOrder {
+        self.header.byte_order()
+    }
+
+    /// Returns the offset of IFD0 from the beginning of the TIFF structure.
+    #[inline]
+    pub fn zeroth_ifd_offset(&self) -> u64 {
+        self.header.zeroth_ifd_offset()
+    }
+
+    /// Returns the main chain of IFDs, starting from IFD0.
+    #[inline]
+    pub fn ifds(&self) -> &[Ifd] {
+        &self.ifds
+    }
+
+    /// Returns the Exif sub-IFD, if present.
+    #[inline]
+    pub fn exif_ifd(&self) -> Option<&Ifd> {
+        self.exif_ifd.as_ref()
+    }
+
+    /// Returns the GPS sub-IFD, if present.
+    #[inline]
+    pub fn gps_ifd(&self) -> Option<&Ifd> {
+        self.gps_ifd.as_ref()
+    }
+
+    /// Returns image orientation, if available.
+    ///
+    /// Orientation of the primary image is recorded in IFD0, but some writers put it
+    /// elsewhere; then the first valid orientation tag from the Exif sub-IFD or from the rest
+    /// of the main IFD chain (e.g. IFD1 describing the thumbnail) is used.
+    pub fn orientation(&self) -> Option<Orientation> {
+        self.ifds.first().into_iter().chain(self.exif_ifd.iter()).chain(self.ifds.iter().skip(1))
+            .filter_map(|ifd| ifd.get(TAG_ORIENTATION))
+            .filter_map(parse_orientation)
+            .next()
+    }
+
+    /// Returns manufacturer of the camera, if available.
+    pub fn make(&self) -> Option<String> {
+        self.ifd0_ascii(TAG_MAKE)
+    }
+
+    /// Returns model of the camera, if available.
+    pub fn model(&self) -> Option<String> {
+        self.ifd0_ascii(TAG_MODEL)
+    }
+
+    /// Returns raw value of the date and time tag in "YYYY:MM:DD HH:MM:SS" format, if available.
+    pub fn date_time_raw(&self) -> Option<String> {
+        self.ifd0_ascii(TAG_DATE_TIME)
+    }
+
+    fn ifd0_ascii(&self, id: u16) -> Option<String> {
+        self.ifds.first().and_then(|ifd| ifd.get(id)).and_then(|tag| tag.get_ascii().ok())
+    }
+
+    /// Returns fields of IFD0, which describes the primary image.
+    fn ifd0(&self) -> &[Tag] {
+        self.ifds.first().map(Ifd::tags).unwrap_or(&[])
+    }
+
+    /// Returns fields of IFD1, which describes the thumbnail image, if present.
+    fn thumbnail_ifd(&self) -> Option<&[Tag]> {
+        self.ifds.get(1).map(Ifd::tags)
+    }
+}
+
+/// Contains values of the EXIF tags we are interested in.
+#[derive(Default)]
+struct ExifFields {
+    resolution: Option<Resolution>,
+    software: Option<String>,
+    artist: Option<String>,
+    copyright: Option<Copyright>,
+    gps: Option<GpsInfo>,
+    exposure_time: Option<Rational>,
+    f_number: Option<Rational>,
+    iso_speed: Option<u16>,
+    exposure_bias: Option<SignedRational>,
+    focal_length: Option<Rational>,
+    exposure_program: Option<ExposureProgram>,
+    metering_mode: Option<MeteringMode>,
+    flash: Option<Flash>,
+    white_balance: Option<WhiteBalance>,
+    sub_sec_time: Option<u32>,
+    date_time_original: Option<DateTime>,
+    date_time_digitized: Option<DateTime>,
+    maker_note: Option<Vec<u8>>,
+    user_comment: Option<String>,
+    exif_version: Option<String>,
+    flashpix_version: Option<String>,
+    exif_dimensions: Option<Dimensions>,
+    exif_tags: HashMap<u16, ExifValue>
+}
+
+impl ExifFields {
+    /// Extracts known tag values from the EXIF segment.
+    ///
+    /// Tags which are present but have unexpected types or values are ignored.
+    fn from_section(exif: &ExifSection) -> ExifFields {
+        let mut result = ExifFields::default();
+
+        let mut x_resolution = None;
+        let mut y_resolution = None;
+        let mut resolution_unit = Some(ResolutionUnit::Inch);
+        for tag in exif.ifd0() {
+            match tag.id() {
+                TAG_X_RESOLUTION => x_resolution = tag.get_rational().ok(),
+                TAG_Y_RESOLUTION => y_resolution = tag.get_rational().ok(),
+                TAG_RESOLUTION_UNIT =>
+                    resolution_unit = tag.get_short_or_long().ok()
+                        .and_then(|n| n.to_u16()).and_then(ResolutionUnit::from_u16),
+                TAG_SOFTWARE => result.software = tag.get_ascii().ok(),
+                TAG_ARTIST => result.artist = tag.get_ascii().ok(),
+                TAG_COPYRIGHT => result.copyright = Copyright::from_tag(tag),
+                _ => {}
+            }
+        }
+        if let (Some(x), Some(y), Some(unit)) = (x_resolution, y_resolution, resolution_unit) {
+            result.resolution = Some(Resolution { x: x, y: y, unit: unit });
+        }
+
+        let mut sub_sec_time_original = None;
+        let mut sub_sec_time_digitized = None;
+        let mut pixel_x_dimension = None;
+        let mut pixel_y_dimension = None;
+        for tag in exif.exif_ifd.as_ref().map(Ifd::tags).unwrap_or(&[]) {
+            match tag.id() {
+                TAG_EXPOSURE_TIME => result.exposure_time = tag.get_rational().ok(),
+                TAG_F_NUMBER => result.f_number = tag.get_rational().ok(),
+                TAG_ISO_SPEED_RATINGS =>
+                    result.iso_speed = tag.get_short_or_long().ok().and_then(|n| n.to_u16()),
+                TAG_EXPOSURE_BIAS => result.exposure_bias = tag.get_signed_rational().ok(),
+                TAG_FOCAL_LENGTH => result.focal_length = tag.get_rational().ok(),
+                TAG_EXPOSURE_PROGRAM =>
+                    result.exposure_program = tag.get_short().ok().map(ExposureProgram::from_u16),
+                TAG_METERING_MODE => result.metering_mode = tag.get_short().ok().map(MeteringMode::from_u16),
+                TAG_FLASH => result.flash = tag.get_short().ok().map(Flash::from_u16),
+                TAG_WHITE_BALANCE => result.white_balance = tag.get_short().ok().map(WhiteBalance::from_u16),
+                TAG_DATE_TIME_ORIGINAL => result.date_time_original = parse_date_time(tag),
+                TAG_DATE_TIME_DIGITIZED => result.date_time_digitized = parse_date_time(tag),
+                TAG_SUB_SEC_TIME => result.sub_sec_time = parse_sub_sec_time(tag),
+                TAG_SUB_SEC_TIME_ORIGINAL => sub_sec_time_original = parse_sub_sec_time(tag),
+                TAG_SUB_SEC_TIME_DIGITIZED => sub_sec_time_digitized = parse_sub_sec_time(tag),
+                TAG_MAKER_NOTE => result.maker_note = Some(tag.raw().to_vec()),
+                TAG_USER_COMMENT => result.user_comment = parse_user_comment(tag),
+                TAG_EXIF_VERSION => result.exif_version = parse_version(tag),
+                TAG_FLASHPIX_VERSION => result.flashpix_version = parse_version(tag),
+                TAG_PIXEL_X_DIMENSION => pixel_x_dimension = tag.get_short_or_long().ok(),
+                TAG_PIXEL_Y_DIMENSION => pixel_y_dimension = tag.get_short_or_long().ok(),
+                _ => {}
+            }
+        }
+        result.date_time_original = result.date_time_original.map(|dt| with_sub_sec_time(dt, sub_sec_time_original));
+        result.date_time_digitized = result.date_time_digitized.map(|dt| with_sub_sec_time(dt, sub_sec_time_digitized));
+        if let (Some(x), Some(y)) = (pixel_x_dimension, pixel_y_dimension) {
+            result.exif_dimensions = Some((x, y).into());
+        }
+
+        for tag in exif.ifd0().iter().chain(exif.exif_ifd.as_ref().map(Ifd::tags).unwrap_or(&[])) {
+            if let Some(value) = ExifValue::from_tag(tag) {
+                result.exif_tags.entry(tag.id()).or_insert(value);
+            }
+        }
+
+        result.gps = GpsInfo::from_tags(exif.gps_ifd.as_ref().map(Ifd::tags).unwrap_or(&[]));
+
+        result
+    }
+}
+
+fn parse_date_time(tag: &Tag) -> Option<DateTime> {
+    tag.get_ascii().ok().and_then(|s| DateTime::parse(&s).ok())
+}
+
+/// Parses the value of a SubSecTime tag into nanoseconds.
+///
+/// The value contains the digits of the decimal fraction of the second, e.g. "25" means
+/// 0.25 seconds. Digits beyond nanosecond precision are ignored.
+fn parse_sub_sec_time(tag: &Tag) -> Option<u32> {
+    let s = try_opt!(tag.get_ascii().ok());
+    let digits = s.trim_end();
+    if digits.is_empty() || !digits.bytes().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    Some(digits.bytes().chain(::std::iter::repeat(b'0')).take(9)
+        .fold(0, |acc, c| acc*10 + (c - b'0') as u32))
+}
+
+fn with_sub_sec_time(dt: DateTime, nanosecond: Option<u32>) -> DateTime {
+    DateTime { nanosecond: nanosecond.unwrap_or(0), ..dt }
+}
+
+fn parse_orientation(tag: &Tag) -> Option<Orientation> {
+    tag.get_short_or_long().ok().and_then(|n| n.to_u16()).and_then(Orientation::from_exif)
+}
+
+/// Decodes the value of a version tag, which consists of four ASCII digits, e.g. "0230".
+///
+/// The first two digits are the major version and the last two are the minor version,
+/// so "0230" is decoded as "2.30".
+fn parse_version(tag: &Tag) -> Option<String> {
+    let data = tag.raw();
+    if data.len() != 4 || !data.iter().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let major = (data[0] - b'0') * 10 + (data[1] - b'0');
+    Some(format!("{}.{}{}", major, data[2] as char, data[3] as char))
+}
+
+/// Decodes the value of the UserComment tag, which starts with an 8-byte character code.
+fn parse_user_comment(tag: &Tag) -> Option<String> {
+    let data = tag.raw();
+    if data.len() < 8 {
+        return None;
+    }
+    let (code, text) = data.split_at(8);
+
+    let comment = match code {
+        b"ASCII\0\0\0" | b"\0\0\0\0\0\0\0\0" => String::from_utf8_lossy(text).into_owned(),
+        b"UNICODE\0" => {
+            let units: Vec<u16> = text.chunks(2)
+                .filter(|c| c.len() == 2)
+                .map(|c| match tag.byte_order() {
+                    tiff::ByteOrder::LittleEndian => (c[1] as u16) << 8 | c[0] as u16,
+                    tiff::ByteOrder::BigEndian => (c[0] as u16) << 8 | c[1] as u16
+                })
+                .collect();
+            String::from_utf16_lossy(&units)
+        }
+        _ => return None
+    };
+
+    // comments are often padded with NULs or spaces
+    let comment = comment.trim_end_matches(|c: char| c == '\0' || c.is_whitespace());
+    if comment.is_empty() { None } else { Some(comment.to_owned()) }
+}
+
+/// Extracts JPEG thumbnail data referenced by the IFD1 fields.
+fn thumbnail_data(data: &[u8], ifd1: &[Tag]) -> Option<Vec<u8>> {
+    let mut offset = None;
+    let mut length = None;
+    for tag in ifd1 {
+        match tag.id() {
+            TAG_JPEG_INTERCHANGE_FORMAT => offset = tag.get_long().ok(),
+            TAG_JPEG_INTERCHANGE_FORMAT_LENGTH => length = tag.get_long().ok(),
+            _ => {}
+        }
+    }
+
+    let start = try_opt!(offset) as usize;
+    let end = start.saturating_add(try_opt!(length) as usize);
+    if end > data.len() {
+        return None;
+    }
+    Some(data[start..end].to_vec())
+}
+
+impl GpsInfo {
+    fn from_tags(tags: &[Tag]) -> Option<GpsInfo> {
+        let mut latitude = None;
+        let mut latitude_ref = None;
+        let mut longitude = None;
+        let mut longitude_ref = None;
+        let mut altitude = None;
+        let mut altitude_ref = None;
+
+        for tag in tags {
+            match tag.id() {
+                TAG_GPS_LATITUDE_REF => latitude_ref = tag.get_ascii().ok(),
+                TAG_GPS_LATITUDE => latitude = tag.get_rationals().ok().and_then(dms_to_degrees),
+                TAG_GPS_LONGITUDE_REF => longitude_ref = tag.get_ascii().ok(),
+                TAG_GPS_LONGITUDE => longitude = tag.get_rationals().ok().and_then(dms_to_degrees),
+                TAG_GPS_ALTITUDE_REF => altitude_ref = tag.get_byte().ok(),
+                TAG_GPS_ALTITUDE => altitude = tag.get_rational().ok().and_then(rational_to_f64),
+                _ => {}
+            }
+        }
+
+        // references default to the north and east hemispheres and to above the sea level
+        let latitude = match (latitude, latitude_ref.as_ref().map(|s| &s[..])) {
+            (Some(v), Some("S")) => -v,
+            (Some(v), _) => v,
+            (None, _) => return None
+        };
+        let longitude = match (longitude, longitude_ref.as_ref().map(|s| &s[..])) {
+            (Some(v), Some("W")) => -v,
+            (Some(v), _) => v,
+            (None, _) => return None
+        };
+        let altitude = match altitude_ref {
+            Some(1) => altitude.map(|v| -v),
+            _ => altitude
+        };
+
+        Some(GpsInfo {
+            latitude: latitude,
+            longitude: longitude,
+            altitude: altitude
+        })
+    }
+}
+
+fn rational_to_f64(r: Rational) -> Option<f64> {
+    if r.denominator == 0 { None } else { Some(r.as_f64()) }
+}
+
+/// Converts a degrees/minutes/seconds triple of rationals to decimal degrees.
+fn dms_to_degrees(dms: Vec<Rational>) -> Option<f64> {
+    if dms.len() != 3 || dms.iter().any(|r| r.denominator == 0) {
+        return None;
     }
+    Some(dms[0].as_f64() + dms[1].as_f64()/60.0 + dms[2].as_f64()/3600.0)
 }
@@ -4,3 +4,7 @@ pub mod jpeg;
 pub mod png;
 pub mod gif;
 pub mod webp;
+pub mod bmp;
+pub mod ico;
+pub mod heif;
+pub mod tiff;
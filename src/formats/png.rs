@@ -1,12 +1,14 @@
 //! Metadata for PNG images.
 
 use std::io::Read;
+use std::time::Duration;
 
 use byteorder::{ReadBytesExt, BigEndian};
 
-use types::{Result, Dimensions};
+use types::{Result, Error, Dimensions, DateTime, Orientation};
 use traits::LoadableMetadata;
 use utils::ReadExt;
+use formats::jpeg::ExifSection;
 
 /// Color type used in an image.
 ///
@@ -124,8 +126,149 @@ impl InterlaceMethod {
     }
 }
 
-/// Represents metadata of a PNG image.
+/// Unit of physical pixel dimensions.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Unit {
+    /// Unit is unknown; pixel dimensions only specify the pixel aspect ratio.
+    Unknown,
+    /// Unit is the meter.
+    Meter
+}
+
+impl Unit {
+    fn from_u8(n: u8) -> Option<Unit> {
+        match n {
+            0 => Some(Unit::Unknown),
+            1 => Some(Unit::Meter),
+            _ => None
+        }
+    }
+}
+
+/// Intended pixel size or aspect ratio, as stored in the pHYs chunk.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct PhysicalDimensions {
+    /// Pixels per unit, X axis.
+    pub x_ppu: u32,
+    /// Pixels per unit, Y axis.
+    pub y_ppu: u32,
+    /// Unit specifier.
+    pub unit: Unit
+}
+
+impl PhysicalDimensions {
+    /// Returns horizontal and vertical resolution in dots per inch, if the unit is known.
+    pub fn dpi(&self) -> Option<(f64, f64)> {
+        const METERS_PER_INCH: f64 = 0.0254;
+        match self.unit {
+            Unit::Meter => Some((self.x_ppu as f64 * METERS_PER_INCH,
+                                 self.y_ppu as f64 * METERS_PER_INCH)),
+            Unit::Unknown => None
+        }
+    }
+}
+
+/// Rendering intent specified by the sRGB chunk.
+///
+/// These intents directly correspond to those defined by the ICC.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum SrgbIntent {
+    Perceptual,
+    RelativeColorimetric,
+    Saturation,
+    AbsoluteColorimetric
+}
+
+impl SrgbIntent {
+    fn from_u8(n: u8) -> Option<SrgbIntent> {
+        match n {
+            0 => Some(SrgbIntent::Perceptual),
+            1 => Some(SrgbIntent::RelativeColorimetric),
+            2 => Some(SrgbIntent::Saturation),
+            3 => Some(SrgbIntent::AbsoluteColorimetric),
+            _ => None
+        }
+    }
+}
+
+/// CIE 1931 x,y chromaticities of the white point and the primaries, as stored in the
+/// cHRM chunk.
+///
+/// Each value is stored multiplied by 100000.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct Chromaticities {
+    pub white_x: u32,
+    pub white_y: u32,
+    pub red_x: u32,
+    pub red_y: u32,
+    pub green_x: u32,
+    pub green_y: u32,
+    pub blue_x: u32,
+    pub blue_y: u32
+}
+
+/// Gamma which PNG spec recommends to assume for sRGB images.
+const SRGB_GAMMA: f64 = 0.45455;
+
+/// Chromaticities which PNG spec recommends to assume for sRGB images.
+const SRGB_CHROMATICITIES: Chromaticities = Chromaticities {
+    white_x: 31270, white_y: 32900,
+    red_x: 64000, red_y: 33000,
+    green_x: 30000, green_y: 60000,
+    blue_x: 15000, blue_y: 6000
+};
+
+/// Transparency information from the tRNS chunk.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum Transparency {
+    /// Alpha values for the palette entries of an indexed image, starting from the first one.
+    ///
+    /// Entries which are not covered are fully opaque.
+    Palette(Vec<u8>),
+    /// Gray level of pixels which are fully transparent in a grayscale image.
+    Grayscale(u16),
+    /// Red, green and blue levels of pixels which are fully transparent in an RGB image.
+    Rgb(u16, u16, u16)
+}
+
+/// A keyword/value text pair from a tEXt, zTXt or iTXt chunk.
 #[derive(Clone, Eq, PartialEq, Debug)]
+pub struct Text {
+    /// Keyword, e.g. "Title" or "Author".
+    pub keyword: String,
+    /// Text value, decompressed if needed.
+    pub value: String,
+    /// Whether the value was stored compressed.
+    pub compressed: bool,
+    /// Language tag of an iTXt value, if specified.
+    pub language: Option<String>
+}
+
+/// Parameters of an animated PNG (APNG) image.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct AnimationInfo {
+    /// Number of frames, as declared in the acTL chunk.
+    pub num_frames: u32,
+    /// Number of times to loop the animation, 0 means infinitely.
+    pub num_plays: u32,
+    /// Sum of delays of all frames described by fcTL chunks.
+    pub total_duration: Duration
+}
+
+/// Options for loading PNG metadata with `Metadata::load_with_options()`.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default)]
+pub struct Options {
+    /// Whether CRCs of all chunks should be checked.
+    ///
+    /// If enabled, all chunks are read, including those which are otherwise skipped, and
+    /// `Error::InvalidFormat` is returned on the first mismatch. A stream which ends before
+    /// IEND is also an error, while without verification the chunks preceding the end of
+    /// the stream are used.
+    pub verify_crc: bool
+}
+
+/// Represents metadata of a PNG image.
+#[derive(Clone, PartialEq, Debug)]
 pub struct Metadata {
     /// Width and height.
     pub dimensions: Dimensions,
@@ -133,16 +276,102 @@ pub struct Metadata {
     pub color_type: ColorType,
     /// Color depth (bits per pixel) used in the image.
     pub color_depth: u8,
+    /// Bit depth (bits per sample or per palette index) used in the image.
+    pub bit_depth: u8,
     /// Compression method used in the image.
     pub compression_method: CompressionMethod,
     /// Preprocessing method used in the image.
     pub filter_method: FilterMethod,
     /// Transmission order used in the image.
-    pub interlace_method: InterlaceMethod
+    pub interlace_method: InterlaceMethod,
+    /// Physical pixel dimensions from the pHYs chunk, if available.
+    pub physical_dimensions: Option<PhysicalDimensions>,
+    /// Number of palette entries, if the image has a PLTE chunk.
+    ///
+    /// The palette is mandatory for indexed images, but it may also be present in RGB images
+    /// as a suggested palette.
+    pub palette_len: Option<usize>,
+    /// Transparency information from the tRNS chunk, if available.
+    pub transparency: Option<Transparency>,
+    /// Image gamma from the gAMA chunk, if available.
+    ///
+    /// Use `effective_gamma()` to take the sRGB chunk into account.
+    pub gamma: Option<f64>,
+    /// Rendering intent from the sRGB chunk, if available.
+    ///
+    /// Presence of this chunk means that the image is in the sRGB color space.
+    pub srgb_intent: Option<SrgbIntent>,
+    /// Chromaticities from the cHRM chunk, if available.
+    ///
+    /// Use `effective_chromaticities()` to take the sRGB chunk into account.
+    pub chromaticities: Option<Chromaticities>,
+    /// Text pairs from all textual chunks, in the order of their appearance.
+    ///
+    /// Compressed values are only available when the `flate2` feature is enabled (which it is
+    /// by default); otherwise compressed chunks are skipped. Compressed values longer than
+    /// 1 MiB are skipped too.
+    pub text: Vec<Text>,
+    /// Animation parameters, if this is an animated PNG.
+    pub animation: Option<AnimationInfo>,
+    /// Image orientation from the eXIf chunk, if available.
+    pub orientation: Option<Orientation>,
+    /// Manufacturer of the camera from the eXIf chunk, if available.
+    pub make: Option<String>,
+    /// Model of the camera from the eXIf chunk, if available.
+    pub model: Option<String>,
+    /// Date and time of the last image modification from the eXIf chunk, if available
+    /// and valid.
+    pub date_time: Option<DateTime>,
+    /// Raw value of the EXIF date and time tag in "YYYY:MM:DD HH:MM:SS" format, if available.
+    pub date_time_raw: Option<String>
 }
 
-impl LoadableMetadata for Metadata {
-    fn load<R: ?Sized + Read>(r: &mut R) -> Result<Metadata> {
+impl Metadata {
+    /// Returns `true` if the image is interlaced, i.e. uses Adam7 interlace method.
+    #[inline]
+    pub fn is_interlaced(&self) -> bool {
+        self.interlace_method == InterlaceMethod::Adam7
+    }
+
+    /// Returns `true` if the image may contain transparent pixels, i.e. if its color type
+    /// has an alpha channel or if it has a tRNS chunk.
+    pub fn has_transparency(&self) -> bool {
+        match self.color_type {
+            ColorType::GrayscaleAlpha | ColorType::RgbAlpha => true,
+            _ => self.transparency.is_some()
+        }
+    }
+
+    /// Returns gamma which should be used to display the image.
+    ///
+    /// If the image has an sRGB chunk, it overrides the gAMA chunk and the gamma of the sRGB
+    /// color space is returned.
+    pub fn effective_gamma(&self) -> Option<f64> {
+        match self.srgb_intent {
+            Some(_) => Some(SRGB_GAMMA),
+            None => self.gamma
+        }
+    }
+
+    /// Returns chromaticities which should be used to display the image.
+    ///
+    /// If the image has an sRGB chunk, it overrides the cHRM chunk and the chromaticities of
+    /// the sRGB color space are returned.
+    pub fn effective_chromaticities(&self) -> Option<Chromaticities> {
+        match self.srgb_intent {
+            Some(_) => Some(SRGB_CHROMATICITIES),
+            None => self.chromaticities
+        }
+    }
+}
+
+impl Metadata {
+    /// Loads metadata from the given input stream with the given options.
+    ///
+    /// `LoadableMetadata::load()` is equivalent to this method with default options.
+    pub fn load_with_options<R: ?Sized + Read>(r: &mut R, options: Options) -> Result<Metadata> {
+        let verify_crc = options.verify_crc;
+
         let mut signature = [0u8; 8];
         if try!(r.read_exact_0(&mut signature)) != signature.len() {
             return Err(unexpected_eof!("when reading PNG signature"))
@@ -172,6 +401,108 @@ impl LoadableMetadata for Metadata {
         let filter_method = try!(r.read_u8().map_err(if_eof!("when reading filter method")));
         let interlace_method = try!(r.read_u8().map_err(if_eof!("when reading interlace method")));
 
+        let crc = try!(r.read_u32::<BigEndian>().map_err(if_eof!("when reading IHDR chunk CRC")));
+        if verify_crc {
+            let mut data = Vec::new();
+            data.extend(&[(width >> 24) as u8, (width >> 16) as u8, (width >> 8) as u8, width as u8]);
+            data.extend(&[(height >> 24) as u8, (height >> 16) as u8, (height >> 8) as u8, height as u8]);
+            data.extend(&[bit_depth, color_type, compression_method, filter_method, interlace_method]);
+            try!(check_crc(&chunk_type, &data, crc));
+        }
+
+        // textual chunks may also follow image data, so all chunks up to IEND are examined
+        let mut physical_dimensions = None;
+        let mut palette_len = None;
+        let mut transparency = None;
+        let mut gamma = None;
+        let mut srgb_intent = None;
+        let mut chromaticities = None;
+        let mut text = Vec::new();
+        let mut animation = None;
+        let mut total_duration = Duration::new(0, 0);
+        let mut exif = None;
+        let chunks = (|| -> Result<()> {
+            while let Some((length, chunk_type)) = try!(read_chunk_header(r)) {
+                match &chunk_type {
+                    b"IEND" => {
+                        if verify_crc {
+                            try!(read_chunk_data(r, &chunk_type, length, verify_crc, "when reading IEND chunk"));
+                        }
+                        break;
+                    }
+                    b"pHYs" => {
+                        let data = try!(read_chunk_data(r, &chunk_type, length, verify_crc, "when reading pHYs chunk"));
+                        physical_dimensions = parse_phys(&data);
+                    }
+                    b"PLTE" => {
+                        let data = try!(read_chunk_data(r, &chunk_type, length, verify_crc, "when reading PLTE chunk"));
+                        palette_len = Some(data.len() / 3);
+                    }
+                    b"tRNS" => {
+                        let data = try!(read_chunk_data(r, &chunk_type, length, verify_crc, "when reading tRNS chunk"));
+                        transparency = parse_trns(color_type, &data);
+                    }
+                    b"gAMA" => {
+                        let data = try!(read_chunk_data(r, &chunk_type, length, verify_crc, "when reading gAMA chunk"));
+                        if data.len() == 4 {
+                            gamma = Some((&data[..]).read_u32::<BigEndian>().unwrap() as f64 / 100000.0);
+                        }
+                    }
+                    b"sRGB" => {
+                        let data = try!(read_chunk_data(r, &chunk_type, length, verify_crc, "when reading sRGB chunk"));
+                        if data.len() == 1 {
+                            srgb_intent = SrgbIntent::from_u8(data[0]);
+                        }
+                    }
+                    b"cHRM" => {
+                        let data = try!(read_chunk_data(r, &chunk_type, length, verify_crc, "when reading cHRM chunk"));
+                        chromaticities = parse_chrm(&data);
+                    }
+                    b"acTL" => {
+                        let data = try!(read_chunk_data(r, &chunk_type, length, verify_crc, "when reading acTL chunk"));
+                        if data.len() == 8 {
+                            let mut r = &data[..];
+                            animation = Some(AnimationInfo {
+                                num_frames: r.read_u32::<BigEndian>().unwrap(),
+                                num_plays: r.read_u32::<BigEndian>().unwrap(),
+                                total_duration: Duration::new(0, 0)
+                            });
+                        }
+                    }
+                    b"fcTL" => {
+                        let data = try!(read_chunk_data(r, &chunk_type, length, verify_crc, "when reading fcTL chunk"));
+                        if let Some(delay) = parse_frame_delay(&data) {
+                            total_duration += delay;
+                        }
+                    }
+                    b"eXIf" => {
+                        let data = try!(read_chunk_data(r, &chunk_type, length, verify_crc, "when reading eXIf chunk"));
+                        if exif.is_none() {
+                            exif = ExifSection::parse(&data).ok();
+                        }
+                    }
+                    b"tEXt" | b"zTXt" | b"iTXt" => {
+                        let data = try!(read_chunk_data(r, &chunk_type, length, verify_crc, "when reading text chunk"));
+                        text.extend(parse_text(&chunk_type, &data));
+                    }
+                    _ if verify_crc => {
+                        try!(read_chunk_data(r, &chunk_type, length, verify_crc, "when reading chunk"));
+                    }
+                    _ => if try!(r.skip_exact_0(length as u64 + 4)) != length as u64 + 4 {
+                        return Err(unexpected_eof!("when skipping {} chunk",
+                                                   String::from_utf8_lossy(&chunk_type)));
+                    }
+                }
+            }
+            Ok(())
+        })();
+        match chunks {
+            // a truncated image still has valid IHDR, so the chunks read so far are returned
+            Err(Error::UnexpectedEndOfFile(_)) if !verify_crc => {}
+            other => try!(other)
+        }
+
+        let date_time_raw = exif.as_ref().and_then(ExifSection::date_time_raw);
         Ok(Metadata {
             dimensions: (width, height).into(),
             color_type: try!(
@@ -182,6 +513,7 @@ impl LoadableMetadata for Metadata {
                 compute_color_depth(bit_depth, color_type)
                     .ok_or(invalid_format!("invalid bit depth: {}", bit_depth))
             ),
+            bit_depth: bit_depth,
             compression_method: try!(
                 CompressionMethod::from_u8(compression_method)
                     .ok_or(invalid_format!("invalid compression method: {}", compression_method))
@@ -193,7 +525,218 @@ impl LoadableMetadata for Metadata {
             interlace_method: try!(
                 InterlaceMethod::from_u8(interlace_method)
                     .ok_or(invalid_format!("invalid interlace method: {}", interlace_method))
-            )
+            ),
+            physical_dimensions: physical_dimensions,
+            palette_len: palette_len,
+            transparency: transparency,
+            gamma: gamma,
+            srgb_intent: srgb_intent,
+            chromaticities: chromaticities,
+            text: text,
+            animation: animation.map(|a| AnimationInfo { total_duration: total_duration, ..a }),
+            orientation: exif.as_ref().and_then(ExifSection::orientation),
+            make: exif.as_ref().and_then(ExifSection::make),
+            model: exif.as_ref().and_then(ExifSection::model),
+            date_time: date_time_raw.as_ref().and_then(|s| DateTime::parse(s).ok()),
+            date_time_raw: date_time_raw
         })
     }
 }
+
+impl LoadableMetadata for Metadata {
+    #[inline]
+    fn load<R: ?Sized + Read>(r: &mut R) -> Result<Metadata> {
+        Metadata::load_with_options(r, Options::default())
+    }
+}
+
+/// Reads length and type of the next chunk.
+///
+/// Returns `None` if the stream ends before the chunk; the final IEND chunk is sometimes
+/// missing in truncated files, which does not affect metadata.
+fn read_chunk_header<R: ?Sized + Read>(r: &mut R) -> Result<Option<(u32, [u8; 4])>> {
+    let mut hdr = [0u8; 8];
+    match try!(r.read_exact_0(&mut hdr)) {
+        0 => return Ok(None),
+        8 => {}
+        _ => return Err(unexpected_eof!("when reading chunk header"))
+    }
+
+    let length = (&hdr[..4]).read_u32::<BigEndian>().unwrap();
+    let chunk_type = [hdr[4], hdr[5], hdr[6], hdr[7]];
+    Ok(Some((length, chunk_type)))
+}
+
+/// Reads chunk data together with the trailing CRC, returning the data only.
+///
+/// The CRC is checked if `verify_crc` is `true`.
+fn read_chunk_data<R: ?Sized + Read>(r: &mut R, chunk_type: &[u8; 4], length: u32, verify_crc: bool,
+                                     on_eof: &'static str) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    if try!(r.take(length as u64).read_to_end(&mut data)) != length as usize {
+        return Err(unexpected_eof!(on_eof));
+    }
+    let crc = try!(r.read_u32::<BigEndian>().map_err(if_eof!(on_eof)));
+    if verify_crc {
+        try!(check_crc(chunk_type, &data, crc));
+    }
+    Ok(data)
+}
+
+fn check_crc(chunk_type: &[u8; 4], data: &[u8], crc: u32) -> Result<()> {
+    if crc32(chunk_type.iter().chain(data)) != crc {
+        return Err(invalid_format!("CRC mismatch in chunk {}", String::from_utf8_lossy(chunk_type)));
+    }
+    Ok(())
+}
+
+/// Computes CRC-32 as defined in PNG spec (the same one which is used by zlib).
+fn crc32<'a, I: Iterator<Item=&'a u8>>(bytes: I) -> u32 {
+    let mut crc = 0xffffffffu32;
+    for &b in bytes {
+        crc ^= b as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { 0xedb88320 ^ (crc >> 1) } else { crc >> 1 };
+        }
+    }
+    crc ^ 0xffffffff
+}
+
+fn parse_phys(data: &[u8]) -> Option<PhysicalDimensions> {
+    if data.len() != 9 {
+        return None;
+    }
+    let mut r = data;
+    let x_ppu = r.read_u32::<BigEndian>().unwrap();
+    let y_ppu = r.read_u32::<BigEndian>().unwrap();
+    Unit::from_u8(data[8]).map(|unit| PhysicalDimensions {
+        x_ppu: x_ppu,
+        y_ppu: y_ppu,
+        unit: unit
+    })
+}
+
+fn parse_trns(color_type: u8, data: &[u8]) -> Option<Transparency> {
+    let mut r = data;
+    match color_type {
+        CT_INDEXED => Some(Transparency::Palette(data.to_vec())),
+        CT_GRAYSCALE if data.len() == 2 =>
+            Some(Transparency::Grayscale(r.read_u16::<BigEndian>().unwrap())),
+        CT_RGB if data.len() == 6 => Some(Transparency::Rgb(
+            r.read_u16::<BigEndian>().unwrap(),
+            r.read_u16::<BigEndian>().unwrap(),
+            r.read_u16::<BigEndian>().unwrap()
+        )),
+        _ => None
+    }
+}
+
+fn parse_chrm(data: &[u8]) -> Option<Chromaticities> {
+    if data.len() != 32 {
+        return None;
+    }
+    let mut r = data;
+    let mut next = || r.read_u32::<BigEndian>().unwrap();
+    Some(Chromaticities {
+        white_x: next(),
+        white_y: next(),
+        red_x: next(),
+        red_y: next(),
+        green_x: next(),
+        green_y: next(),
+        blue_x: next(),
+        blue_y: next()
+    })
+}
+
+/// Extracts the frame delay from an fcTL chunk.
+fn parse_frame_delay(data: &[u8]) -> Option<Duration> {
+    // sequence number, width, height, x and y offsets precede the delay fraction
+    if data.len() != 26 {
+        return None;
+    }
+    let mut r = &data[20..];
+    let num = r.read_u16::<BigEndian>().unwrap() as u64;
+    let den = match r.read_u16::<BigEndian>().unwrap() {
+        0 => 100,  // zero denominator means hundredths of a second
+        n => n as u64
+    };
+    Some(Duration::new(num / den, ((num % den) * 1_000_000_000 / den) as u32))
+}
+
+/// Parses a tEXt, zTXt or iTXt chunk.
+///
+/// Returns `None` if the chunk is malformed or if it cannot be decompressed.
+fn parse_text(chunk_type: &[u8; 4], data: &[u8]) -> Option<Text> {
+    let (keyword, rest) = try_opt!(split_at_nul(data));
+    let keyword = latin1_to_string(keyword);
+
+    match chunk_type {
+        b"tEXt" => Some(Text {
+            keyword: keyword,
+            value: latin1_to_string(rest),
+            compressed: false,
+            language: None
+        }),
+        b"zTXt" => {
+            // compression method byte, only 0 (zlib) is defined
+            if rest.first() != Some(&0) {
+                return None;
+            }
+            let value = try_opt!(decompress(&rest[1..]));
+            Some(Text {
+                keyword: keyword,
+                value: latin1_to_string(&value),
+                compressed: true,
+                language: None
+            })
+        }
+        _ => {  // iTXt
+            if rest.len() < 2 {
+                return None;
+            }
+            let compressed = rest[0] == 1;
+            if compressed && rest[1] != 0 {
+                return None;
+            }
+            let (language, rest) = try_opt!(split_at_nul(&rest[2..]));
+            let (_translated_keyword, value) = try_opt!(split_at_nul(rest));
+            let value = if compressed { try_opt!(decompress(value)) } else { value.to_vec() };
+            Some(Text {
+                keyword: keyword,
+                value: String::from_utf8_lossy(&value).into_owned(),
+                compressed: compressed,
+                language: if language.is_empty() { None } else { Some(latin1_to_string(language)) }
+            })
+        }
+    }
+}
+
+fn split_at_nul(data: &[u8]) -> Option<(&[u8], &[u8])> {
+    data.iter().position(|&b| b == 0).map(|i| (&data[..i], &data[i+1..]))
+}
+
+/// Converts ISO 8859-1 text, which is used by tEXt and zTXt chunks, to a string.
+fn latin1_to_string(data: &[u8]) -> String {
+    data.iter().map(|&b| b as char).collect()
+}
+
+/// Maximum size of a decompressed text value; larger values are skipped, so a small
+/// compressed chunk can't make the decoder allocate huge amounts of memory.
+#[cfg(feature = "flate2")]
+const MAX_TEXT_LEN: u64 = 1024 * 1024;
+
+#[cfg(feature = "flate2")]
+fn decompress(data: &[u8]) -> Option<Vec<u8>> {
+    use flate2::read::ZlibDecoder;
+    let value = try_opt!(ZlibDecoder::new(data).take(MAX_TEXT_LEN + 1).read_to_vec().ok());
+    if value.len() as u64 > MAX_TEXT_LEN {
+        return None;
+    }
+    Some(value)
+}
+
+#[cfg(not(feature = "flate2"))]
+fn decompress(_data: &[u8]) -> Option<Vec<u8>> {
+    None
+}
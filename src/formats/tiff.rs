@@ -0,0 +1,809 @@
+//! Metadata of TIFF images and parsing of TIFF structures.
+//!
+//! TIFF is not only an image format on its own, it is also used as a container for metadata
+//! in other formats, most notably for EXIF data in JPEG images. Both classic TIFF and BigTIFF,
+//! which uses 64-bit offsets, are supported.
+
+use std::io::{self, Read, Cursor, Seek, SeekFrom};
+
+use byteorder::{self, ReadBytesExt, BigEndian, LittleEndian};
+
+use num::ToPrimitive;
+
+use types::{Result, Dimensions, Rational, SignedRational, Orientation, Rotation};
+use traits::LoadableMetadata;
+use utils::ReadExt;
+
+/// Byte order of a TIFF structure.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ByteOrder {
+    LittleEndian,
+    BigEndian
+}
+
+impl ByteOrder {
+    fn read_u16<R: ?Sized + Read>(self, r: &mut R) -> byteorder::Result<u16> {
+        match self {
+            ByteOrder::LittleEndian => r.read_u16::<LittleEndian>(),
+            ByteOrder::BigEndian => r.read_u16::<BigEndian>()
+        }
+    }
+
+    fn read_u32<R: ?Sized + Read>(self, r: &mut R) -> byteorder::Result<u32> {
+        match self {
+            ByteOrder::LittleEndian => r.read_u32::<LittleEndian>(),
+            ByteOrder::BigEndian => r.read_u32::<BigEndian>()
+        }
+    }
+
+    fn read_u64<R: ?Sized + Read>(self, r: &mut R) -> byteorder::Result<u64> {
+        match self {
+            ByteOrder::LittleEndian => r.read_u64::<LittleEndian>(),
+            ByteOrder::BigEndian => r.read_u64::<BigEndian>()
+        }
+    }
+}
+
+/// Determines the sizes of offsets and counts in a TIFF structure.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+struct Layout {
+    byte_order: ByteOrder,
+    /// Whether this is a BigTIFF structure, with 8-byte offsets and counts.
+    big: bool
+}
+
+impl Layout {
+    /// Reads an offset, which is 4 bytes long in classic TIFF and 8 bytes long in BigTIFF.
+    fn read_offset<R: ?Sized + Read>(self, r: &mut R) -> byteorder::Result<u64> {
+        if self.big {
+            self.byte_order.read_u64(r)
+        } else {
+            self.byte_order.read_u32(r).map(|n| n as u64)
+        }
+    }
+
+    /// Returns the size of the value/offset field of an IFD entry.
+    fn inline_len(self) -> u64 {
+        if self.big { 8 } else { 4 }
+    }
+}
+
+/// The header of a TIFF structure.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Header {
+    layout: Layout,
+    zeroth_ifd_offset: u64
+}
+
+impl Header {
+    fn load<R: ?Sized + Read>(r: &mut R) -> Result<Header> {
+        let mut byte_order = [0u8; 2];
+        try_if_eof!(r.read_full(&mut byte_order), "when reading TIFF byte order");
+
+        let byte_order = match &byte_order {
+            b"II" => ByteOrder::LittleEndian,
+            b"MM" => ByteOrder::BigEndian,
+            _ => return Err(invalid_format!("invalid TIFF byte order: {:?}", byte_order))
+        };
+
+        let magic = try_if_eof!(byte_order.read_u16(r), "when reading TIFF magic number");
+        let layout = match magic {
+            42 => Layout { byte_order: byte_order, big: false },
+            43 => Layout { byte_order: byte_order, big: true },
+            _ => return Err(invalid_format!("invalid TIFF magic number: {}", magic))
+        };
+
+        if layout.big {
+            let offset_size = try_if_eof!(byte_order.read_u16(r), "when reading BigTIFF offset size");
+            let reserved = try_if_eof!(byte_order.read_u16(r), "when reading BigTIFF header");
+            if offset_size != 8 || reserved != 0 {
+                return Err(invalid_format!("invalid BigTIFF offset size: {}", offset_size));
+            }
+        }
+
+        let zeroth_ifd_offset = try_if_eof!(layout.read_offset(r), "when reading zeroth IFD offset");
+
+        Ok(Header {
+            layout: layout,
+            zeroth_ifd_offset: zeroth_ifd_offset
+        })
+    }
+
+    /// Returns the byte order of the TIFF structure.
+    #[inline]
+    pub fn byte_order(&self) -> ByteOrder {
+        self.layout.byte_order
+    }
+
+    /// Returns `true` if this is a BigTIFF structure, with 64-bit offsets.
+    #[inline]
+    pub fn is_big(&self) -> bool {
+        self.layout.big
+    }
+
+    /// Returns the offset of IFD0 from the beginning of the TIFF structure.
+    #[inline]
+    pub fn zeroth_ifd_offset(&self) -> u64 {
+        self.zeroth_ifd_offset
+    }
+}
+
+/// Parses the header of a TIFF structure.
+///
+/// `data` must start with the TIFF header.
+pub fn parse_header(data: &[u8]) -> Result<Header> {
+    Header::load(&mut Cursor::new(data))
+}
+
+/// Parses the TIFF header and all IFDs of the main chain, starting from IFD0.
+///
+/// `data` must start with the TIFF header; all offsets are relative to its beginning.
+/// The chain is followed until a zero next IFD offset is found or until an already visited
+/// IFD is encountered again.
+pub fn parse_ifds(data: &[u8]) -> Result<Vec<Ifd>> {
+    let mut r = Cursor::new(data);
+
+    let header = try!(Header::load(&mut r));
+
+    let mut ifds = Vec::new();
+    let mut visited = Vec::new();
+    let mut offset = header.zeroth_ifd_offset;
+    while offset != 0 && !visited.contains(&offset) {
+        visited.push(offset);
+        let (ifd, next_ifd_offset) = try!(Ifd::load(&mut r, header.layout, offset));
+        ifds.push(ifd);
+        offset = next_ifd_offset;
+    }
+
+    Ok(ifds)
+}
+
+/// An image file directory, i.e. a list of TIFF fields.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Clone, PartialEq, Debug)]
+pub struct Ifd {
+    layout: Layout,
+    tags: Vec<Tag>
+}
+
+impl Ifd {
+    /// Loads all fields of an IFD located at the given offset from the start of the TIFF
+    /// structure.
+    ///
+    /// Returns the IFD and the offset of the next IFD, which is zero if there is none.
+    /// Fields with unknown data types are skipped.
+    fn load(r: &mut Cursor<&[u8]>, layout: Layout, offset: u64) -> Result<(Ifd, u64)> {
+        try!(seek_to(r, offset));
+
+        let num_fields = if layout.big {
+            try_if_eof!(layout.byte_order.read_u64(r), "when reading number of IFD fields")
+        } else {
+            try_if_eof!(layout.byte_order.read_u16(r), "when reading number of IFD fields") as u64
+        };
+
+        // the number of fields is not trusted for preallocation because BigTIFF allows
+        // it to be huge
+        let mut tags = Vec::new();
+        for _ in 0..num_fields {
+            if let Some(tag) = try!(Tag::load(r, layout)) {
+                tags.push(tag);
+            }
+        }
+
+        // some writers omit the next IFD offset of the last IFD
+        let next_ifd_offset = layout.read_offset(r).unwrap_or(0);
+
+        let ifd = Ifd {
+            layout: layout,
+            tags: tags
+        };
+        Ok((ifd, next_ifd_offset))
+    }
+
+    /// Returns the byte order of the TIFF structure this IFD was loaded from.
+    #[inline]
+    pub fn byte_order(&self) -> ByteOrder {
+        self.layout.byte_order
+    }
+
+    /// Returns all fields of this IFD.
+    #[inline]
+    pub fn tags(&self) -> &[Tag] {
+        &self.tags
+    }
+
+    /// Returns the field with the given tag id, if present.
+    pub fn get(&self, id: u16) -> Option<&Tag> {
+        self.tags.iter().find(|t| t.id() == id)
+    }
+
+    /// Loads the sub-IFD referenced by the given pointer field of this IFD, e.g. the Exif IFD.
+    ///
+    /// `data` must be the same TIFF structure this IFD was loaded from. Returns `None` if
+    /// the pointer field is absent or does not contain an offset.
+    pub fn sub_ifd(&self, data: &[u8], pointer_id: u16) -> Result<Option<Ifd>> {
+        let offset = match self.get(pointer_id).and_then(|t| t.get_offset().ok()) {
+            Some(offset) => offset,
+            None => return Ok(None)
+        };
+        let mut r = Cursor::new(data);
+        Ifd::load(&mut r, self.layout, offset).map(|(ifd, _)| Some(ifd))
+    }
+}
+
+/// Data types of TIFF fields.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum TagDatatype {
+    Byte,
+    Ascii,
+    Short,
+    Long,
+    Rational,
+    SignedByte,
+    Undefined,
+    SignedShort,
+    SignedLong,
+    SignedRational,
+    Float,
+    Double,
+    /// Offset of an IFD, stored as a long value.
+    Ifd,
+    /// 64-bit unsigned integer, only in BigTIFF.
+    Long8,
+    /// 64-bit signed integer, only in BigTIFF.
+    SignedLong8,
+    /// Offset of an IFD, stored as a 64-bit value, only in BigTIFF.
+    Ifd8
+}
+
+impl TagDatatype {
+    fn from_u16(n: u16) -> Option<TagDatatype> {
+        match n {
+            1  => Some(TagDatatype::Byte),
+            2  => Some(TagDatatype::Ascii),
+            3  => Some(TagDatatype::Short),
+            4  => Some(TagDatatype::Long),
+            5  => Some(TagDatatype::Rational),
+            6  => Some(TagDatatype::SignedByte),
+            7  => Some(TagDatatype::Undefined),
+            8  => Some(TagDatatype::SignedShort),
+            9  => Some(TagDatatype::SignedLong),
+            10 => Some(TagDatatype::SignedRational),
+            11 => Some(TagDatatype::Float),
+            12 => Some(TagDatatype::Double),
+            13 => Some(TagDatatype::Ifd),
+            16 => Some(TagDatatype::Long8),
+            17 => Some(TagDatatype::SignedLong8),
+            18 => Some(TagDatatype::Ifd8),
+            _  => None
+        }
+    }
+
+    /// Returns the size of one value of this type in bytes.
+    fn len(self) -> usize {
+        match self {
+            TagDatatype::Byte | TagDatatype::Ascii |
+            TagDatatype::SignedByte | TagDatatype::Undefined => 1,
+            TagDatatype::Short | TagDatatype::SignedShort => 2,
+            TagDatatype::Long | TagDatatype::SignedLong | TagDatatype::Float |
+            TagDatatype::Ifd => 4,
+            TagDatatype::Rational | TagDatatype::SignedRational | TagDatatype::Double |
+            TagDatatype::Long8 | TagDatatype::SignedLong8 | TagDatatype::Ifd8 => 8
+        }
+    }
+}
+
+/// A single field of a TIFF image file directory.
+///
+/// # Examples
+///
+/// Reading an arbitrary field from EXIF data of a JPEG image:
+///
+/// ```no_run
+/// use immeta::markers::{MetadataMarker, Jpeg};
+///
+/// let md = Jpeg::load_from_file("kitty.jpg").unwrap();
+/// let exif = md.exif().unwrap();
+/// // ImageDescription field of IFD0
+/// if let Some(tag) = exif.ifds()[0].get(0x010e) {
+///     println!("datatype: {:?}, byte order: {:?}", tag.datatype(), tag.byte_order());
+///     println!("raw value: {:?}", tag.raw());
+///     println!("value: {}", tag.get_ascii().unwrap());
+/// }
+/// ```
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Clone, PartialEq, Debug)]
+pub struct Tag {
+    id: u16,
+    datatype: TagDatatype,
+    byte_order: ByteOrder,
+    data: Vec<u8>
+}
+
+impl Tag {
+    fn load(r: &mut Cursor<&[u8]>, layout: Layout) -> Result<Option<Tag>> {
+        let byte_order = layout.byte_order;
+        let id = try_if_eof!(byte_order.read_u16(r), "when reading tag id");
+        let datatype = try_if_eof!(byte_order.read_u16(r), "when reading datatype of tag 0x{:04x}", id);
+        let count = if layout.big {
+            try_if_eof!(byte_order.read_u64(r), "when reading value count of tag 0x{:04x}", id)
+        } else {
+            try_if_eof!(byte_order.read_u32(r), "when reading value count of tag 0x{:04x}", id) as u64
+        };
+
+        let datatype = match TagDatatype::from_u16(datatype) {
+            Some(datatype) => datatype,
+            None => {
+                // skip the value/offset field
+                try!(seek_to(r, r.position() + layout.inline_len()));
+                return Ok(None);
+            }
+        };
+
+        // fields whose values are out of bounds are skipped, so that one broken field does not
+        // make the rest of the IFD inaccessible
+        let data_len = match (datatype.len() as u64).checked_mul(count) {
+            Some(n) => n,
+            None => {
+                try!(seek_to(r, r.position() + layout.inline_len()));
+                return Ok(None);
+            }
+        };
+
+        // values which fit into the value/offset field are stored inline, otherwise there
+        // is an offset
+        let data = if data_len <= layout.inline_len() {
+            let mut data = vec![0u8; data_len as usize];
+            try_if_eof!(r.read_full(&mut data), "when reading value of tag 0x{:04x}", id);
+            try!(seek_to(r, r.position() + layout.inline_len() - data_len));
+            data
+        } else {
+            let value_offset = try_if_eof!(layout.read_offset(r), "when reading value offset of tag 0x{:04x}", id);
+            let in_bounds = match value_offset.checked_add(data_len) {
+                Some(end) => end <= r.get_ref().len() as u64,
+                None => false
+            };
+            if !in_bounds {
+                return Ok(None);
+            }
+
+            let next_field = r.position();
+            try!(seek_to(r, value_offset));
+            let mut data = vec![0u8; data_len as usize];
+            try_if_eof!(r.read_full(&mut data), "when reading value of tag 0x{:04x}", id);
+            try!(seek_to(r, next_field));
+            data
+        };
+
+        Ok(Some(Tag {
+            id: id,
+            datatype: datatype,
+            byte_order: byte_order,
+            data: data
+        }))
+    }
+
+    /// Returns the tag id of this field.
+    #[inline]
+    pub fn id(&self) -> u16 {
+        self.id
+    }
+
+    /// Returns the data type of the field values.
+    #[inline]
+    pub fn datatype(&self) -> TagDatatype {
+        self.datatype
+    }
+
+    /// Returns the byte order of the field values.
+    #[inline]
+    pub fn byte_order(&self) -> ByteOrder {
+        self.byte_order
+    }
+
+    /// Returns raw bytes of the field values, in the byte order of the TIFF structure.
+    #[inline]
+    pub fn raw(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Returns the first value of a byte field.
+    pub fn get_byte(&self) -> Result<u8> {
+        try!(self.check_datatype(TagDatatype::Byte));
+        self.first(self.data.clone())
+    }
+
+    /// Returns the first value of a short field.
+    pub fn get_short(&self) -> Result<u16> {
+        self.first(try!(self.get_shorts()))
+    }
+
+    /// Returns all values of a short field.
+    pub fn get_shorts(&self) -> Result<Vec<u16>> {
+        try!(self.check_datatype(TagDatatype::Short));
+        let mut r = &self.data[..];
+        let mut values = Vec::with_capacity(self.data.len() / 2);
+        while !r.is_empty() {
+            values.push(try!(self.byte_order.read_u16(&mut r)));
+        }
+        Ok(values)
+    }
+
+    /// Returns the first value of a long field.
+    pub fn get_long(&self) -> Result<u32> {
+        self.first(try!(self.get_longs()))
+    }
+
+    /// Returns all values of a long field.
+    pub fn get_longs(&self) -> Result<Vec<u32>> {
+        try!(self.check_datatype(TagDatatype::Long));
+        let mut r = &self.data[..];
+        let mut values = Vec::with_capacity(self.data.len() / 4);
+        while !r.is_empty() {
+            values.push(try!(self.byte_order.read_u32(&mut r)));
+        }
+        Ok(values)
+    }
+
+    /// Returns the first value of a field which may be either a short or a long field.
+    ///
+    /// Some writers store fields defined as short ones, e.g. orientation, as long fields.
+    pub fn get_short_or_long(&self) -> Result<u32> {
+        match self.datatype {
+            TagDatatype::Short => self.get_short().map(|n| n as u32),
+            _ => self.get_long()
+        }
+    }
+
+    /// Returns the first value of a field holding an offset, i.e. of a long, IFD, long8 or IFD8
+    /// field.
+    pub fn get_offset(&self) -> Result<u64> {
+        let mut r = &self.data[..];
+        let value = match self.datatype {
+            TagDatatype::Long | TagDatatype::Ifd if r.len() >= 4 =>
+                try!(self.byte_order.read_u32(&mut r)) as u64,
+            TagDatatype::Long8 | TagDatatype::Ifd8 if r.len() >= 8 =>
+                try!(self.byte_order.read_u64(&mut r)),
+            TagDatatype::Long | TagDatatype::Ifd | TagDatatype::Long8 | TagDatatype::Ifd8 =>
+                return Err(invalid_format!("tag 0x{:04x} has no values", self.id)),
+            _ => return Err(invalid_format!("tag 0x{:04x} has datatype {:?} instead of an offset",
+                                            self.id, self.datatype))
+        };
+        Ok(value)
+    }
+
+    /// Returns the first value of a rational field.
+    pub fn get_rational(&self) -> Result<Rational> {
+        self.first(try!(self.get_rationals()))
+    }
+
+    /// Returns all values of a rational field.
+    pub fn get_rationals(&self) -> Result<Vec<Rational>> {
+        try!(self.check_datatype(TagDatatype::Rational));
+        let mut r = &self.data[..];
+        let mut values = Vec::with_capacity(self.data.len() / 8);
+        while !r.is_empty() {
+            let numerator = try!(self.byte_order.read_u32(&mut r));
+            let denominator = try!(self.byte_order.read_u32(&mut r));
+            values.push(Rational {
+                numerator: numerator,
+                denominator: denominator
+            });
+        }
+        Ok(values)
+    }
+
+    /// Returns the first value of a signed rational field.
+    pub fn get_signed_rational(&self) -> Result<SignedRational> {
+        self.first(try!(self.get_signed_rationals()))
+    }
+
+    /// Returns all values of a signed rational field.
+    pub fn get_signed_rationals(&self) -> Result<Vec<SignedRational>> {
+        try!(self.check_datatype(TagDatatype::SignedRational));
+        let mut r = &self.data[..];
+        let mut values = Vec::with_capacity(self.data.len() / 8);
+        while !r.is_empty() {
+            let numerator = try!(self.byte_order.read_u32(&mut r)) as i32;
+            let denominator = try!(self.byte_order.read_u32(&mut r)) as i32;
+            values.push(SignedRational {
+                numerator: numerator,
+                denominator: denominator
+            });
+        }
+        Ok(values)
+    }
+
+    /// Returns the value of an ASCII field up to the first NUL byte.
+    pub fn get_ascii(&self) -> Result<String> {
+        try!(self.check_datatype(TagDatatype::Ascii));
+        let end = self.data.iter().position(|&b| b == 0).unwrap_or(self.data.len());
+        Ok(String::from_utf8_lossy(&self.data[..end]).into_owned())
+    }
+
+    /// Returns all NUL-separated strings of an ASCII field.
+    ///
+    /// Some tags, e.g. Copyright, store several strings in one field. Empty strings at the
+    /// end of the field, which result from the terminator and padding, are dropped.
+    #[allow(clippy::unnecessary_map_or)]  // `Option::is_some_and()` needs Rust 1.70
+    pub fn get_ascii_list(&self) -> Result<Vec<String>> {
+        try!(self.check_datatype(TagDatatype::Ascii));
+        let mut values: Vec<String> = self.data.split(|&b| b == 0)
+            .map(|s| String::from_utf8_lossy(s).into_owned())
+            .collect();
+        while values.last().map_or(false, |s| s.is_empty()) {
+            values.pop();
+        }
+        Ok(values)
+    }
+
+    fn check_datatype(&self, datatype: TagDatatype) -> Result<()> {
+        if self.datatype != datatype {
+            return Err(invalid_format!("tag 0x{:04x} has datatype {:?} instead of {:?}",
+                                       self.id, self.datatype, datatype));
+        }
+        Ok(())
+    }
+
+    /// Returns the first value of a multi-valued tag.
+    fn first<T>(&self, mut values: Vec<T>) -> Result<T> {
+        if values.is_empty() {
+            return Err(invalid_format!("tag 0x{:04x} has no values", self.id));
+        }
+        Ok(values.swap_remove(0))
+    }
+}
+
+fn seek_to(r: &mut Cursor<&[u8]>, pos: u64) -> io::Result<()> {
+    r.seek(SeekFrom::Start(pos)).map(|_| ())
+}
+
+const TAG_IMAGE_WIDTH: u16 = 0x0100;
+const TAG_IMAGE_LENGTH: u16 = 0x0101;
+const TAG_ORIENTATION: u16 = 0x0112;
+
+/// Represents metadata of a TIFF image.
+///
+/// Only the first image of the file, described by IFD0, is inspected.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Metadata {
+    /// Image size.
+    pub dimensions: Dimensions,
+    /// Byte order of the file.
+    pub byte_order: ByteOrder,
+    /// Image orientation, if available.
+    pub orientation: Option<Orientation>
+}
+
+impl Metadata {
+    /// Returns image dimensions as the image should be displayed, i.e. taking orientation
+    /// into account.
+    ///
+    /// Width and height are swapped if the orientation requires rotation by 90 or 270 degrees.
+    pub fn oriented_dimensions(&self) -> Dimensions {
+        match self.orientation.map(|o| o.steps().0) {
+            Some(Rotation::Rotate90) | Some(Rotation::Rotate270) => self.dimensions.rotate90(),
+            _ => self.dimensions
+        }
+    }
+}
+
+impl LoadableMetadata for Metadata {
+    /// Loads TIFF metadata from the given input stream.
+    ///
+    /// IFDs may be located anywhere in a TIFF file, so the whole stream is read into memory
+    /// once the TIFF signature is found.
+    fn load<R: ?Sized + Read>(r: &mut R) -> Result<Metadata> {
+        let mut data = vec![0u8; 4];
+        try_if_eof!(r.read_full(&mut data), "when reading TIFF signature");
+        match &data[..] {
+            b"II*\0" | b"MM\0*" | b"II+\0" | b"MM\0+" => {}
+            _ => return Err(invalid_format!("invalid TIFF signature: {:?}", data))
+        }
+        try!(r.read_to_end(&mut data));
+
+        let header = try!(parse_header(&data));
+        let ifds = try!(parse_ifds(&data));
+        let ifd0 = try!(ifds.first().ok_or(invalid_format!("TIFF file contains no images")));
+
+        let dimension = |id, name| ifd0.get(id)
+            .and_then(|t| t.get_short_or_long().ok())
+            .ok_or(invalid_format!("{} is missing", name));
+        let width = try!(dimension(TAG_IMAGE_WIDTH, "image width"));
+        let height = try!(dimension(TAG_IMAGE_LENGTH, "image length"));
+
+        Ok(Metadata {
+            dimensions: (width, height).into(),
+            byte_order: header.byte_order(),
+            orientation: ifd0.get(TAG_ORIENTATION)
+                .and_then(|t| t.get_short_or_long().ok())
+                .and_then(|n| n.to_u16())
+                .and_then(Orientation::from_exif)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_ifds, parse_header, ByteOrder};
+    use types::Error;
+
+    #[test]
+    fn test_header_byte_order() {
+        let header = parse_header(&[b'I', b'I', 0x2a, 0x00, 0x08, 0x00, 0x00, 0x00]).unwrap();
+        assert_eq!(header.byte_order(), ByteOrder::LittleEndian);
+        assert_eq!(header.zeroth_ifd_offset(), 8);
+
+        let header = parse_header(&[b'M', b'M', 0x00, 0x2a, 0x00, 0x00, 0x00, 0x08]).unwrap();
+        assert_eq!(header.byte_order(), ByteOrder::BigEndian);
+        assert_eq!(header.zeroth_ifd_offset(), 8);
+
+        for data in &[b"IM\x2a\x00\x08\x00\x00\x00", b"MI\x00\x2a\x00\x00\x00\x08"] {
+            match parse_header(&data[..]) {
+                Err(Error::InvalidFormat(_)) => {}
+                r => panic!("unexpected result: {:?}", r)
+            }
+        }
+        // the magic number must be in the declared byte order
+        assert!(parse_header(&[b'M', b'M', 0x2a, 0x00, 0x00, 0x00, 0x00, 0x08]).is_err());
+    }
+
+    #[test]
+    fn test_ifd_chain() {
+        let data: &[u8] = &[
+            b'M', b'M', 0x00, 0x2a, 0x00, 0x00, 0x00, 0x08,
+            // IFD0 at offset 8
+            0x00, 0x01,
+            0x01, 0x12, 0x00, 0x03, 0x00, 0x00, 0x00, 0x01, 0x00, 0x06, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x1a,
+            // IFD1 at offset 26
+            0x00, 0x02,
+            0x01, 0x03, 0x00, 0x03, 0x00, 0x00, 0x00, 0x01, 0x00, 0x06, 0x00, 0x00,
+            0x01, 0x12, 0x00, 0x03, 0x00, 0x00, 0x00, 0x01, 0x00, 0x01, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00
+        ];
+
+        let ifds = parse_ifds(data).unwrap();
+        assert_eq!(ifds.len(), 2);
+        assert_eq!(ifds[0].tags().len(), 1);
+        assert_eq!(ifds[0].tags()[0].id(), 0x0112);
+        assert_eq!(ifds[0].tags()[0].get_short().unwrap(), 6);
+
+        assert_eq!(ifds[1].tags().len(), 2);
+        assert_eq!(ifds[1].get(0x0112).unwrap().get_short().unwrap(), 1);
+        assert!(ifds[1].get(0x0110).is_none());
+    }
+
+    #[test]
+    fn test_ifd_loop() {
+        let data: &[u8] = &[
+            b'I', b'I', 0x2a, 0x00, 0x08, 0x00, 0x00, 0x00,
+            // IFD0 at offset 8, pointing to itself as the next IFD
+            0x00, 0x00,
+            0x08, 0x00, 0x00, 0x00
+        ];
+
+        let ifds = parse_ifds(data).unwrap();
+        assert_eq!(ifds.len(), 1);
+    }
+
+    #[test]
+    fn test_sub_ifd() {
+        let data: &[u8] = &[
+            b'I', b'I', 0x2a, 0x00, 0x08, 0x00, 0x00, 0x00,
+            // IFD0 at offset 8 with a pointer to the sub-IFD
+            0x01, 0x00,
+            0x69, 0x87, 0x04, 0x00, 0x01, 0x00, 0x00, 0x00, 0x1a, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00,
+            // sub-IFD at offset 26
+            0x01, 0x00,
+            0x27, 0x88, 0x03, 0x00, 0x01, 0x00, 0x00, 0x00, 0x90, 0x01, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00
+        ];
+
+        let ifds = parse_ifds(data).unwrap();
+        let sub_ifd = ifds[0].sub_ifd(data, 0x8769).unwrap().unwrap();
+        assert_eq!(sub_ifd.get(0x8827).unwrap().get_short().unwrap(), 400);
+
+        assert!(ifds[0].sub_ifd(data, 0x8825).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_ascii_terminator() {
+        let data: &[u8] = &[
+            b'M', b'M', 0x00, 0x2a, 0x00, 0x00, 0x00, 0x08,
+            // IFD0 at offset 8 with three inline ASCII tags
+            0x00, 0x03,
+            // terminated properly
+            0x01, 0x0f, 0x00, 0x02, 0x00, 0x00, 0x00, 0x03, b'a', b'b', 0x00, 0x00,
+            // no terminator
+            0x01, 0x10, 0x00, 0x02, 0x00, 0x00, 0x00, 0x04, b'a', b'b', b'c', b'd',
+            // padded with several NULs
+            0x01, 0x31, 0x00, 0x02, 0x00, 0x00, 0x00, 0x04, b'a', 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00
+        ];
+
+        let ifds = parse_ifds(data).unwrap();
+        assert_eq!(ifds[0].get(0x010f).unwrap().get_ascii().unwrap(), "ab");
+        assert_eq!(ifds[0].get(0x0110).unwrap().get_ascii().unwrap(), "abcd");
+        assert_eq!(ifds[0].get(0x0131).unwrap().get_ascii().unwrap(), "a");
+    }
+
+    #[test]
+    fn test_ascii_list() {
+        let data: &[u8] = &[
+            b'M', b'M', 0x00, 0x2a, 0x00, 0x00, 0x00, 0x08,
+            // IFD0 at offset 8 with a Copyright tag
+            0x00, 0x01,
+            0x82, 0x98, 0x00, 0x02, 0x00, 0x00, 0x00, 0x0c, 0x00, 0x00, 0x00, 0x1a,
+            0x00, 0x00, 0x00, 0x00,
+            // value at offset 26
+            b'J', b'a', b'n', b'e', 0x00, b'E', b'd', b'i', b't', b'o', b'r', 0x00
+        ];
+
+        let ifds = parse_ifds(data).unwrap();
+        let tag = ifds[0].get(0x8298).unwrap();
+        assert_eq!(tag.get_ascii_list().unwrap(), vec!["Jane".to_owned(), "Editor".to_owned()]);
+        assert_eq!(tag.get_ascii().unwrap(), "Jane");
+    }
+
+    #[test]
+    fn test_huge_tag_count() {
+        let data: &[u8] = &[
+            b'M', b'M', 0x00, 0x2a, 0x00, 0x00, 0x00, 0x08,
+            // IFD0 at offset 8 with one LONG tag claiming 0xFFFFFFFF values
+            0x00, 0x01,
+            0x01, 0x10, 0x00, 0x04, 0xff, 0xff, 0xff, 0xff, 0x00, 0x00, 0x00, 0x08,
+            0x00, 0x00, 0x00, 0x00
+        ];
+
+        let ifds = parse_ifds(data).unwrap();
+        assert_eq!(ifds.len(), 1);
+        assert!(ifds[0].tags().is_empty());
+    }
+
+    #[test]
+    fn test_bigtiff() {
+        let data: &[u8] = &[
+            b'I', b'I', 0x2b, 0x00, 0x08, 0x00, 0x00, 0x00,
+            0x10, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            // IFD0 at offset 16 with three fields
+            0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            // ImageWidth, SHORT, 640
+            0x00, 0x01, 0x03, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x80, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            // ImageLength, LONG, 480
+            0x01, 0x01, 0x04, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0xe0, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            // Model, ASCII, 8 bytes stored inline
+            0x10, 0x01, 0x02, 0x00, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            b'O', b'w', b'l', b' ', b'C', b'a', b'm', 0x00,
+            // no next IFD
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00
+        ];
+
+        let ifds = parse_ifds(data).unwrap();
+        assert_eq!(ifds.len(), 1);
+        assert_eq!(ifds[0].get(0x0100).unwrap().get_short().unwrap(), 640);
+        assert_eq!(ifds[0].get(0x0101).unwrap().get_long().unwrap(), 480);
+        assert_eq!(ifds[0].get(0x0110).unwrap().get_ascii().unwrap(), "Owl Cam");
+    }
+
+    #[test]
+    fn test_bigtiff_invalid_offset_size() {
+        let data: &[u8] = &[
+            b'M', b'M', 0x00, 0x2b, 0x00, 0x04, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x10
+        ];
+
+        match parse_ifds(data) {
+            Err(Error::InvalidFormat(_)) => {}
+            r => panic!("unexpected result: {:?}", r.map(|ifds| ifds.len()))
+        }
+    }
+}
@@ -1,7 +1,9 @@
+//! Metadata of WebP images.
+
 use std::io::Read;
 
 use types::{Result, Dimensions};
-use common::riff::{RiffReader, RiffChunk, ChunkId};
+use common::riff::{RiffReader, RiffChunk, RiffListChunk, ChunkId};
 use traits::LoadableMetadata;
 use utils::ReadExt;
 
@@ -26,23 +28,95 @@ pub enum VP8Frame {
     Inter
 }
 
+/// Metadata of a lossless (VP8L) WebP image.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct VP8LMetadata {
+    /// Image size.
+    pub dimensions: Dimensions,
+    /// A hint that some of the pixels may have alpha values other than 255.
+    pub alpha_is_used: bool,
+    /// Version of the lossless bitstream, currently always 0.
+    pub version_number: u8
+}
+
+/// Metadata of an extended (VP8X) WebP image.
 #[derive(Clone, Eq, PartialEq, Debug)]
-pub struct VP8LMetadata;
+pub struct VP8XMetadata {
+    /// Canvas size.
+    pub dimensions: Dimensions,
+    /// Whether the file contains an ICC profile.
+    pub has_icc: bool,
+    /// Whether any of the frames contains transparency information.
+    pub has_alpha: bool,
+    /// Whether the file contains EXIF metadata.
+    pub has_exif: bool,
+    /// Whether the file contains XMP metadata.
+    pub has_xmp: bool,
+    /// Whether the image is animated.
+    pub has_animation: bool,
+    /// Whether the image is compressed losslessly, i.e. uses a VP8L bitstream.
+    ///
+    /// For animated images this is taken from the first frame; other frames may use
+    /// a different compression.
+    pub is_lossless: bool,
+    /// Animation parameters, if the image is animated.
+    pub animation: Option<AnimationInfo>
+}
 
+/// Parameters of an animated WebP image.
 #[derive(Clone, Eq, PartialEq, Debug)]
-pub struct VP8XMetadata;
+pub struct AnimationInfo {
+    /// Default background color of the canvas in [blue, green, red, alpha] byte order.
+    pub background_color: [u8; 4],
+    /// Number of times to loop the animation, 0 means infinitely.
+    pub loop_count: u16,
+    /// Number of frames in the animation.
+    pub frame_count: usize,
+    /// Sum of durations of all frames in milliseconds.
+    pub total_duration_ms: u32
+}
 
 const WEBP_CHUNK_TYPE: ChunkId = ChunkId([b'W', b'E', b'B', b'P']);
-const ALPH_CHUNK_ID: ChunkId   = ChunkId([b'A', b'L', b'P', b'H']);
 const VP8_CHUNK_ID: ChunkId    = ChunkId([b'V', b'P', b'8', b' ']);
 const VP8L_CHUNK_ID: ChunkId   = ChunkId([b'V', b'P', b'8', b'L']);
 const VP8X_CHUNK_ID: ChunkId   = ChunkId([b'V', b'P', b'8', b'X']);
+const ANIM_CHUNK_ID: ChunkId   = ChunkId([b'A', b'N', b'I', b'M']);
+const ANMF_CHUNK_ID: ChunkId   = ChunkId([b'A', b'N', b'M', b'F']);
+const ALPH_CHUNK_ID: ChunkId   = ChunkId([b'A', b'L', b'P', b'H']);
 
 impl Metadata {
     pub fn dimensions(&self) -> Dimensions {
         match *self {
             Metadata::VP8(VP8Metadata { frame: VP8Frame::Key { dimensions, .. }, .. }) => dimensions,
-            _ => unimplemented!()
+            // inter frames do not carry dimensions
+            Metadata::VP8(VP8Metadata { frame: VP8Frame::Inter, .. }) => (0, 0).into(),
+            Metadata::VP8L(ref md) => md.dimensions,
+            Metadata::VP8X(ref md) => md.dimensions
+        }
+    }
+
+    /// Returns `true` if the image is compressed losslessly.
+    ///
+    /// Simple images are lossy (VP8) or lossless (VP8L) depending on their first chunk; for
+    /// extended images the bitstream chunk following the VP8X one is examined, see
+    /// `VP8XMetadata::is_lossless`.
+    pub fn is_lossless(&self) -> bool {
+        match *self {
+            Metadata::VP8(_) => false,
+            Metadata::VP8L(_) => true,
+            Metadata::VP8X(ref md) => md.is_lossless
+        }
+    }
+
+    /// Returns `true` if the image may contain transparent pixels.
+    ///
+    /// Simple lossy images never have alpha; for lossless images the "alpha is used" hint
+    /// of the bitstream header is returned.
+    pub fn has_alpha(&self) -> bool {
+        match *self {
+            Metadata::VP8(_) => false,
+            Metadata::VP8L(ref md) => md.alpha_is_used,
+            Metadata::VP8X(ref md) => md.has_alpha
         }
     }
 }
@@ -56,19 +130,27 @@ impl LoadableMetadata for Metadata {
             return Err(invalid_format!("invalid WEBP signature"));
         }
 
-        loop {
-            let mut chunk = match root.next() {
-                Some(c) => try!(c),
-                None => return Err(unexpected_eof!("when reading first WEBP chunk"))
-            };
-
-            match chunk.chunk_id() {
-                VP8_CHUNK_ID => return read_vp8_chunk(&mut chunk).map(Metadata::VP8),
-                VP8L_CHUNK_ID => unimplemented!(),
-                VP8X_CHUNK_ID => unimplemented!(),
-                ALPH_CHUNK_ID => unimplemented!(),
-                cid => return Err(invalid_format!("invalid WEBP chunk id: {}", cid))
+        // the format of the file is determined by its first chunk
+        let mut chunk = match root.next() {
+            Some(c) => try!(c),
+            None => return Err(unexpected_eof!("when reading first WEBP chunk"))
+        };
+
+        match chunk.chunk_id() {
+            VP8_CHUNK_ID => read_vp8_chunk(&mut chunk).map(Metadata::VP8),
+            VP8L_CHUNK_ID => read_vp8l_chunk(&mut chunk).map(Metadata::VP8L),
+            VP8X_CHUNK_ID => {
+                let mut md = try!(read_vp8x_chunk(&mut chunk));
+                if md.has_animation {
+                    let (animation, is_lossless) = try!(read_animation_info(&mut root));
+                    md.animation = animation;
+                    md.is_lossless = is_lossless;
+                } else {
+                    md.is_lossless = try!(read_still_image_kind(&mut root));
+                }
+                Ok(Metadata::VP8X(md))
             }
+            cid => Err(invalid_format!("invalid WEBP chunk id: {}", cid))
         }
     }
 }
@@ -137,3 +219,137 @@ fn read_vp8_chunk(chunk: &mut RiffChunk) -> Result<VP8Metadata> {
     Ok(result)
 }
 
+fn read_vp8l_chunk(chunk: &mut RiffChunk) -> Result<VP8LMetadata> {
+    let r = chunk.contents();
+
+    let mut hdr = [0u8; 5];
+    if try!(r.read_exact_0(&mut hdr)) != 5 {
+        return Err(unexpected_eof!("when reading VP8L header"));
+    }
+
+    if hdr[0] != 0x2f {
+        return Err(invalid_format!("VP8L signature is invalid: {}", hdr[0]));
+    }
+
+    // the rest is a little-endian bit stream of:
+    //    14 bits  --  width - 1
+    //    14 bits  --  height - 1
+    //    1 bit    --  alpha is used flag
+    //    3 bits   --  version number
+    let bits = (hdr[1] as u32) | (hdr[2] as u32) << 8 | (hdr[3] as u32) << 16 | (hdr[4] as u32) << 24;
+    let width = (bits & 0x3fff) + 1;
+    let height = ((bits >> 14) & 0x3fff) + 1;
+    let alpha_is_used = (bits >> 28) & 1 == 1;
+    let version_number = (bits >> 29) as u8;
+
+    if version_number != 0 {
+        return Err(invalid_format!("VP8L version number is invalid: {}", version_number));
+    }
+
+    Ok(VP8LMetadata {
+        dimensions: (width, height).into(),
+        alpha_is_used: alpha_is_used,
+        version_number: version_number
+    })
+}
+
+fn read_vp8x_chunk(chunk: &mut RiffChunk) -> Result<VP8XMetadata> {
+    let r = chunk.contents();
+
+    let mut hdr = [0u8; 10];
+    if try!(r.read_exact_0(&mut hdr)) != 10 {
+        return Err(unexpected_eof!("when reading VP8X header"));
+    }
+
+    // the first byte contains feature flags:
+    //    rrilexar
+    // where
+    //    i  --  ICC profile
+    //    l  --  alpha
+    //    e  --  EXIF metadata
+    //    x  --  XMP metadata
+    //    a  --  animation
+    //    r  --  reserved
+    // it is followed by three reserved bytes and two 24-bit little-endian numbers,
+    // canvas width - 1 and canvas height - 1
+    let flags = hdr[0];
+    let width = ((hdr[4] as u32) | (hdr[5] as u32) << 8 | (hdr[6] as u32) << 16) + 1;
+    let height = ((hdr[7] as u32) | (hdr[8] as u32) << 8 | (hdr[9] as u32) << 16) + 1;
+
+    Ok(VP8XMetadata {
+        dimensions: (width, height).into(),
+        has_icc: flags & 0x20 != 0,
+        has_alpha: flags & 0x10 != 0,
+        has_exif: flags & 0x08 != 0,
+        has_xmp: flags & 0x04 != 0,
+        has_animation: flags & 0x02 != 0,
+        is_lossless: false,
+        animation: None
+    })
+}
+
+/// Finds the bitstream chunk of a still extended image and returns whether it is lossless.
+///
+/// Lossy bitstreams may be preceded by an ALPH chunk with the alpha channel.
+fn read_still_image_kind(root: &mut RiffListChunk) -> Result<bool> {
+    while let Some(chunk) = root.next() {
+        match try!(chunk).chunk_id() {
+            VP8L_CHUNK_ID => return Ok(true),
+            VP8_CHUNK_ID | ALPH_CHUNK_ID => return Ok(false),
+            _ => {}
+        }
+    }
+    Err(invalid_format!("no image data in extended WEBP"))
+}
+
+/// Walks the chunks following VP8X one and collects animation parameters from ANIM and
+/// ANMF chunks, along with whether the first frame is lossless.
+///
+/// The animation parameters are `None` if there is no ANIM chunk.
+fn read_animation_info(root: &mut RiffListChunk) -> Result<(Option<AnimationInfo>, bool)> {
+    let mut result: Option<AnimationInfo> = None;
+    let mut first_frame = None;
+    while let Some(chunk) = root.next() {
+        let mut chunk = try!(chunk);
+        match chunk.chunk_id() {
+            ANIM_CHUNK_ID => {
+                let mut hdr = [0u8; 6];
+                if try!(chunk.contents().read_exact_0(&mut hdr)) != 6 {
+                    return Err(unexpected_eof!("when reading ANIM chunk"));
+                }
+                result = Some(AnimationInfo {
+                    background_color: [hdr[0], hdr[1], hdr[2], hdr[3]],
+                    loop_count: (hdr[4] as u16) | (hdr[5] as u16) << 8,
+                    frame_count: 0,
+                    total_duration_ms: 0
+                });
+            }
+            ANMF_CHUNK_ID => {
+                // frame X and Y offsets, width - 1 and height - 1, all 24-bit, followed
+                // by 24-bit frame duration and flags byte
+                let mut hdr = [0u8; 16];
+                if try!(chunk.contents().read_exact_0(&mut hdr)) != 16 {
+                    return Err(unexpected_eof!("when reading ANMF chunk header"));
+                }
+                if let Some(ref mut info) = result {
+                    let duration = (hdr[12] as u32) | (hdr[13] as u32) << 8 | (hdr[14] as u32) << 16;
+                    info.frame_count += 1;
+                    info.total_duration_ms = info.total_duration_ms.saturating_add(duration);
+                }
+                if first_frame.is_none() {
+                    // the header is followed by the frame data chunks
+                    let mut chunk_id = [0u8; 4];
+                    if try!(chunk.contents().read_exact_0(&mut chunk_id)) != 4 {
+                        return Err(unexpected_eof!("when reading ANMF frame data"));
+                    }
+                    first_frame = Some(ChunkId(chunk_id) == VP8L_CHUNK_ID);
+                }
+            }
+            _ => {}
+        }
+    }
+    match first_frame {
+        Some(is_lossless) => Ok((result, is_lossless)),
+        None => Err(invalid_format!("no frames in animated WEBP"))
+    }
+}
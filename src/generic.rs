@@ -1,11 +1,13 @@
-use std::io::{Read, Cursor, Seek, SeekFrom};
+use std::io::{Read, BufReader, Cursor, Seek, SeekFrom};
 use std::fs::File;
 use std::path::Path;
 use std::result;
+use std::cmp;
 
-use types::{Result, Dimensions};
+use types::{Result, Error, Dimensions};
 use traits::LoadableMetadata;
-use formats::{jpeg, png, gif, webp};
+use formats::{jpeg, png, gif, webp, bmp, ico, heif, tiff};
+use utils::{ReadExt, LimitedReader};
 use generic::markers::MetadataMarker;
 
 /// Contains metadata marker types.
@@ -43,7 +45,7 @@ pub mod markers {
 
     use generic::GenericMetadata;
     use types::Result;
-    use formats::{jpeg, png, gif, webp};
+    use formats::{jpeg, png, gif, webp, bmp, ico, heif, tiff};
 
     /// A marker trait for specific metadata type.
     pub trait MetadataMarker {
@@ -197,36 +199,123 @@ pub mod markers {
     impl_metadata_marker! { Png, Png, png::Metadata }
     impl_metadata_marker! { Gif, Gif, gif::Metadata }
     impl_metadata_marker! { Webp, Webp, webp::Metadata }
+    impl_metadata_marker! { Bmp, Bmp, bmp::Metadata }
+    impl_metadata_marker! { Ico, Ico, ico::Metadata }
+    impl_metadata_marker! { Heif, Heif, heif::Metadata }
+    impl_metadata_marker! { Tiff, Tiff, tiff::Metadata }
 }
 
 /// Represents metadata loaded from a file whose format was determined automatically.
 ///
 /// Values of this type are obtained via `immeta::load()` function and its derivatives.
+///
+/// New variants are added as new formats are supported, so matching on this enum requires
+/// a wildcard arm. Methods like `dimensions()`, `format()` and `mime_type()` work for all
+/// formats and should be preferred when format-specific metadata is not needed.
+#[non_exhaustive]
 pub enum GenericMetadata {
     Png(png::Metadata),
     Gif(gif::Metadata),
     Jpeg(jpeg::Metadata),
-    Webp(webp::Metadata)
+    Webp(webp::Metadata),
+    Bmp(bmp::Metadata),
+    Ico(ico::Metadata),
+    Heif(heif::Metadata),
+    Tiff(tiff::Metadata)
 }
 
 impl GenericMetadata {
+    /// Loads metadata for an image contained in an in-memory buffer.
+    ///
+    /// Unlike `load_from_buf()`, this method determines the image format from its first bytes
+    /// with `guess_format()` and then runs the decoder of only that format, so errors of that
+    /// decoder are returned as is.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use immeta::GenericMetadata;
+    ///
+    /// let body: Vec<u8> = vec![0xff, 0xd8];   // pretend that this is an uploaded image
+    /// let metadata = GenericMetadata::from_buf(&body);
+    /// ```
+    pub fn from_buf(mut buf: &[u8]) -> Result<GenericMetadata> {
+        let r = &mut buf;
+        match guess_format(r) {
+            Some(Format::Png) => png::Metadata::load(r).map(GenericMetadata::Png),
+            Some(Format::Gif) => gif::Metadata::load(r).map(GenericMetadata::Gif),
+            Some(Format::Jpeg) => jpeg::Metadata::load(r).map(GenericMetadata::Jpeg),
+            Some(Format::Webp) => webp::Metadata::load(r).map(GenericMetadata::Webp),
+            Some(Format::Bmp) => bmp::Metadata::load(r).map(GenericMetadata::Bmp),
+            Some(Format::Ico) => ico::Metadata::load(r).map(GenericMetadata::Ico),
+            Some(Format::Heif) | Some(Format::Avif) => heif::Metadata::load(r).map(GenericMetadata::Heif),
+            Some(Format::Tiff) => tiff::Metadata::load(r).map(GenericMetadata::Tiff),
+            None => Err(Error::UnsupportedFormat { magic: buf[..cmp::min(buf.len(), MAGIC_SIZE)].to_vec() })
+        }
+    }
+
     /// Returns image dimensions from the contained metadata.
     pub fn dimensions(&self) -> Dimensions {
         match *self {
             GenericMetadata::Png(ref md) => md.dimensions,
             GenericMetadata::Gif(ref md) => md.dimensions,
             GenericMetadata::Jpeg(ref md) => md.dimensions,
-            GenericMetadata::Webp(ref md) => md.dimensions()
+            GenericMetadata::Webp(ref md) => md.dimensions(),
+            GenericMetadata::Bmp(ref md) => md.dimensions,
+            GenericMetadata::Ico(ref md) => md.dimensions(),
+            GenericMetadata::Heif(ref md) => md.dimensions,
+            GenericMetadata::Tiff(ref md) => md.dimensions
+        }
+    }
+
+    /// Returns image dimensions as the image should be displayed.
+    ///
+    /// For JPEG images EXIF orientation is taken into account, as in
+    /// `jpeg::Metadata::oriented_dimensions()`, and the same applies to TIFF images.
+    /// For other formats this is the same as `dimensions()`.
+    pub fn display_dimensions(&self) -> Dimensions {
+        match *self {
+            GenericMetadata::Jpeg(ref md) => md.oriented_dimensions(),
+            GenericMetadata::Tiff(ref md) => md.oriented_dimensions(),
+            _ => self.dimensions()
+        }
+    }
+
+    /// Returns the format of the image of the contained metadata.
+    pub fn format(&self) -> Format {
+        match *self {
+            GenericMetadata::Png(_) => Format::Png,
+            GenericMetadata::Gif(_) => Format::Gif,
+            GenericMetadata::Jpeg(_) => Format::Jpeg,
+            GenericMetadata::Webp(_) => Format::Webp,
+            GenericMetadata::Bmp(_) => Format::Bmp,
+            GenericMetadata::Ico(_) => Format::Ico,
+            GenericMetadata::Heif(ref md) => match md.brand {
+                heif::Brand::Avif => Format::Avif,
+                _ => Format::Heif
+            },
+            GenericMetadata::Tiff(_) => Format::Tiff
         }
     }
 
     /// Returns a MIME type string for the image type of the contained metadata.
+    #[inline]
     pub fn mime_type(&self) -> &'static str {
+        self.format().mime_type()
+    }
+
+    /// Returns the embedded thumbnail of the image, if there is one.
+    ///
+    /// Currently only EXIF thumbnails of JPEG images are supported; for other formats `None`
+    /// is always returned.
+    pub fn thumbnail(&self) -> Option<Thumbnail> {
         match *self {
-            GenericMetadata::Png(_) => "image/png",
-            GenericMetadata::Gif(_) => "image/gif",
-            GenericMetadata::Jpeg(_) => "image/jpeg",
-            GenericMetadata::Webp(_) => "image/webp"
+            GenericMetadata::Jpeg(ref md) => md.thumbnail().map(|data| Thumbnail {
+                format: Format::Jpeg,
+                dimensions: jpeg::Metadata::load_from_buf(data).ok().map(|md| md.dimensions),
+                data: data.to_vec()
+            }),
+            _ => None
         }
     }
 
@@ -249,12 +338,109 @@ impl GenericMetadata {
     }
 }
 
+/// Represents an image format supported by this library.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Format {
+    Png,
+    Gif,
+    Jpeg,
+    Webp,
+    Bmp,
+    Ico,
+    Heif,
+    Avif,
+    Tiff
+}
+
+/// A signature of an image format, consisting of byte strings at the given offsets from
+/// the beginning of the file.
+///
+/// A file matches the signature if it contains all of the byte strings.
+pub type Signature = &'static [(usize, &'static [u8])];
+
+impl Format {
+    /// Returns all supported image formats.
+    pub fn all() -> &'static [Format] {
+        const ALL: &'static [Format] = &[
+            Format::Png, Format::Gif, Format::Jpeg, Format::Webp, Format::Bmp, Format::Ico,
+            Format::Heif, Format::Avif, Format::Tiff
+        ];
+        ALL
+    }
+
+    /// Returns signatures of this image format, used by `guess_format()`.
+    ///
+    /// A file is considered to be of this format if it matches any of the signatures.
+    pub fn magic(self) -> &'static [Signature] {
+        match self {
+            Format::Png => &[&[(0, b"\x89PNG\r\n\x1a\n")]],
+            Format::Gif => &[&[(0, b"GIF8")]],
+            Format::Jpeg => &[&[(0, b"\xff\xd8")]],
+            Format::Webp => &[&[(0, b"RIFF"), (8, b"WEBP")]],
+            Format::Bmp => &[&[(0, b"BM")]],
+            Format::Ico => &[&[(0, b"\0\0\x01\0")], &[(0, b"\0\0\x02\0")]],
+            Format::Heif => &[
+                &[(4, b"ftyp"), (8, b"heic")], &[(4, b"ftyp"), (8, b"heix")], &[(4, b"ftyp"), (8, b"mif1")]
+            ],
+            Format::Avif => &[&[(4, b"ftyp"), (8, b"avif")]],
+            // classic TIFF and BigTIFF in both byte orders
+            Format::Tiff => &[&[(0, b"II*\0")], &[(0, b"MM\0*")], &[(0, b"II+\0")], &[(0, b"MM\0+")]]
+        }
+    }
+
+    /// Returns a MIME type string for this image format.
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            Format::Png => "image/png",
+            Format::Gif => "image/gif",
+            Format::Jpeg => "image/jpeg",
+            Format::Webp => "image/webp",
+            Format::Bmp => "image/bmp",
+            Format::Ico => "image/x-icon",
+            Format::Heif => "image/heif",
+            Format::Avif => "image/avif",
+            Format::Tiff => "image/tiff"
+        }
+    }
+}
+
+/// A reduced version of an image which is embedded into its metadata.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Thumbnail {
+    /// Format of the thumbnail image.
+    pub format: Format,
+    /// Size of the thumbnail image, if it could be determined.
+    pub dimensions: Option<Dimensions>,
+    /// Encoded thumbnail image.
+    pub data: Vec<u8>
+}
+
+/// Number of first bytes of an input which are reported in `Error::UnsupportedFormat`.
+const MAGIC_SIZE: usize = 8;
+
+/// Attempts to determine image format from the first bytes of an image.
+///
+/// Only magic bytes are inspected, no metadata is parsed, so a successful result does not
+/// guarantee that the image is valid. At most 12 first bytes are examined; shorter prefixes
+/// are accepted as long as they contain the whole signature of the corresponding format.
+///
+/// HEIF and AVIF are distinguished by the major brand only, so an AVIF image with the generic
+/// `mif1` major brand is reported as HEIF.
+pub fn guess_format(prefix: &[u8]) -> Option<Format> {
+    Format::all().iter().cloned().find(|f| f.magic().iter().any(|sig| {
+        sig.iter().all(|&(offset, bytes)| prefix.get(offset..offset + bytes.len()) == Some(bytes))
+    }))
+}
+
 /// Attempts to load metadata for an image contained in the provided input stream.
 ///
 /// This method automatically determines the format of the contained image. Because it may
 /// need to read the stream from the beginning several times, a `Seek` bound is necessary
 /// on the input stream. This may cause problems only with network streams as they are
 /// naturally not seekable, so one would need to buffer the data from them first.
+///
+/// If none of the decoders accepts the image, `Error::UnsupportedFormat` with the first bytes
+/// of the stream is returned.
 pub fn load<R: ?Sized + Read + Seek>(r: &mut R) -> Result<GenericMetadata> {
     // try png
     try!(r.seek(SeekFrom::Start(0)));
@@ -274,6 +460,30 @@ pub fn load<R: ?Sized + Read + Seek>(r: &mut R) -> Result<GenericMetadata> {
         return Ok(GenericMetadata::Webp(md));
     }
 
+    // try bmp
+    try!(r.seek(SeekFrom::Start(0)));
+    if let Ok(md) = bmp::Metadata::load(r) {
+        return Ok(GenericMetadata::Bmp(md));
+    }
+
+    // try ico
+    try!(r.seek(SeekFrom::Start(0)));
+    if let Ok(md) = ico::Metadata::load(r) {
+        return Ok(GenericMetadata::Ico(md));
+    }
+
+    // try heif
+    try!(r.seek(SeekFrom::Start(0)));
+    if let Ok(md) = heif::Metadata::load(r) {
+        return Ok(GenericMetadata::Heif(md));
+    }
+
+    // try tiff
+    try!(r.seek(SeekFrom::Start(0)));
+    if let Ok(md) = tiff::Metadata::load(r) {
+        return Ok(GenericMetadata::Tiff(md));
+    }
+
     // try jpeg
     // should be the last because JPEG can't be determined from its header (since it has none)
     try!(r.seek(SeekFrom::Start(0)));
@@ -281,16 +491,21 @@ pub fn load<R: ?Sized + Read + Seek>(r: &mut R) -> Result<GenericMetadata> {
         return Ok(GenericMetadata::Jpeg(md));
     }
 
-    Err(invalid_format!("unknown or unsupported file type"))
+    try!(r.seek(SeekFrom::Start(0)));
+    let mut magic = vec![0u8; MAGIC_SIZE];
+    let n = try!(r.read_exact_0(&mut magic));
+    magic.truncate(n);
+    Err(Error::UnsupportedFormat { magic: magic })
 }
 
 /// Attempts to load metadata for an image contained in a file identified by the provided path.
 /// 
 /// This method delegates to `load()` method and, consequently, also determines the image format
-/// automatically.
+/// automatically. The file is read through a buffer, and only the part of it which is needed
+/// to find the metadata is actually read.
 pub fn load_from_file<P: AsRef<Path>>(p: P) -> Result<GenericMetadata> {
-    let mut f = try!(File::open(p));
-    load(&mut f)
+    let f = try!(File::open(p));
+    load(&mut BufReader::new(f))
 }
 
 /// Attempts to load metadata for an image contained in an in-memory buffer.
@@ -300,3 +515,19 @@ pub fn load_from_file<P: AsRef<Path>>(p: P) -> Result<GenericMetadata> {
 pub fn load_from_buf(b: &[u8]) -> Result<GenericMetadata> {
     load(&mut Cursor::new(b))
 }
+
+/// Attempts to load metadata for an image contained in an in-memory buffer, reading at most
+/// `max_bytes` bytes from it.
+///
+/// This method works like `load_from_buf()`, but it fails with `Error::InvalidFormat` as soon
+/// as a metadata decoder tries to consume more than `max_bytes` bytes. The limit applies to each
+/// format decoder separately, and reading up to the very end of a buffer of exactly `max_bytes`
+/// bytes is allowed. This protects against crafted files whose size fields make decoders skip
+/// over huge amounts of data.
+pub fn load_from_buf_limited(b: &[u8], max_bytes: usize) -> Result<GenericMetadata> {
+    let mut r = LimitedReader::new(Cursor::new(b), max_bytes as u64);
+    match load(&mut r) {
+        Err(_) if r.exceeded() => Err(invalid_format!("exceeded read limit")),
+        result => result
+    }
+}
@@ -22,9 +22,19 @@
 //!   * JPEG
 //!   * PNG 1.2
 //!   * GIF (both 87a and 89a)
+//!   * WebP
+//!   * BMP
+//!   * ICO and CUR
+//!   * HEIF (including HEIC) and AVIF
+//!   * TIFF (including BigTIFF)
 //!
-//! Support for more types will come in future versions, as well as support for particular 
-//! metadata kinds (e.g. EXIF tags in JPEG) which are not yet available.
+//! For JPEG images, some of the EXIF tags (e.g. orientation, camera model and GPS position)
+//! are also available. Support for more types will come in future versions, as well as support
+//! for other particular metadata kinds.
+//!
+//! With `serde` feature enabled, `Serialize` and `Deserialize` are implemented for JPEG
+//! metadata and the types it consists of. With `tokio` feature enabled, metadata can be loaded
+//! from asynchronous streams with `load_from_async_read()`.
 //!
 //! **Important note:** this library only allows inspecting image metadata, not the image
 //! contents. That is, it does not perform decoding and does not provide access to pixels
@@ -33,16 +43,21 @@
 
 extern crate byteorder;
 extern crate num;
+#[cfg(feature = "serde")] #[macro_use] extern crate serde;
+#[cfg(feature = "flate2")] extern crate flate2;
+#[cfg(feature = "tokio")] extern crate tokio;
 
 pub use types::*;
 pub use traits::*;
 pub use generic::*;
+#[cfg(feature = "tokio")] pub use async_read::{load_from_async_read, LoadFromAsyncRead};
 
 #[macro_use] mod macros;
 #[macro_use] mod generic;
 mod traits;
 mod types;
 mod utils;
+#[cfg(feature = "tokio")] mod async_read;
 
 pub mod common;
 pub mod formats;
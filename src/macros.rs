@@ -42,3 +42,12 @@ macro_rules! try_if_eof {
         try!($e.map_err(if_eof!($fmt, $($args)*)))
     }
 }
+
+macro_rules! try_opt {
+    ($e:expr) => {
+        match $e {
+            Some(v) => v,
+            None => return None
+        }
+    }
+}
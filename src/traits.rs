@@ -1,4 +1,4 @@
-use std::io::Read;
+use std::io::{BufReader, Read};
 use std::fs::File;
 use std::path::Path;
 
@@ -7,15 +7,19 @@ use types::Result;
 /// Provides several convenience functions for loading metadata from various sources.
 pub trait LoadableMetadata: Sized {
     /// Loads the implementing type from the given input stream.
+    ///
+    /// The stream is only read forward, so it does not need to be seekable; for example,
+    /// it can be a network stream.
     fn load<R: ?Sized + Read>(r: &mut R) -> Result<Self>;
 
     /// Loads the implementing type from a file specified by the given path.
     ///
-    /// Delegates to `load<R: Read>(&mut R)` method.
+    /// Delegates to `load<R: Read>(&mut R)` method. The file is read through a buffer, so
+    /// decoders which read it in small pieces do not cause many system calls.
     #[inline]
     fn load_from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let mut f = try!(File::open(path));
-        LoadableMetadata::load(&mut f)
+        let f = try!(File::open(path));
+        LoadableMetadata::load(&mut BufReader::new(f))
     }
 
     /// Loads the implementing type from an in-memory buffer.
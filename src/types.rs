@@ -3,6 +3,7 @@ use std::result;
 use std::fmt;
 use std::borrow::Cow;
 use std::error;
+use std::cmp::Ordering;
 
 use num::ToPrimitive;
 
@@ -21,6 +22,12 @@ pub enum Error {
     /// loose formats (like JPEG) to a different image type.
     UnexpectedEndOfFile(Option<Cow<'static, str>>),
 
+    /// Returned when image format can't be determined automatically.
+    ///
+    /// `magic` contains the first bytes of the input (up to eight), which usually allow
+    /// to tell what kind of file it actually is.
+    UnsupportedFormat { magic: Vec<u8> },
+
     /// Returned when an I/O error occurs when reading an input stream.
     Io(io::Error)
 }
@@ -31,6 +38,13 @@ impl fmt::Display for Error {
             Error::InvalidFormat(ref s) => write!(f, "invalid image format: {}", s),
             Error::UnexpectedEndOfFile(None) => write!(f, "unexpected end of file"),
             Error::UnexpectedEndOfFile(Some(ref s)) => write!(f, "unexpected end of file: {}", s),
+            Error::UnsupportedFormat { ref magic } => {
+                try!(write!(f, "unsupported format, magic: ["));
+                for (i, b) in magic.iter().enumerate() {
+                    try!(write!(f, "{}{:02x}", if i == 0 { "" } else { ", " }, b));
+                }
+                write!(f, "]")
+            }
             Error::Io(ref e) => write!(f, "I/O error: {}", e)
         }
     }
@@ -41,11 +55,12 @@ impl error::Error for Error {
         match *self {
             Error::InvalidFormat(_) => "invalid image format",
             Error::UnexpectedEndOfFile(_) => "unexpected end of file",
+            Error::UnsupportedFormat { .. } => "unsupported image format",
             Error::Io(_) => "i/o error"
         }
     }
 
-    fn cause(&self) -> Option<&error::Error> {
+    fn source(&self) -> Option<&(error::Error + 'static)> {
         match *self {
             Error::Io(ref e) => Some(e),
             _ => None
@@ -80,6 +95,7 @@ pub type Result<T> = result::Result<T, Error>;
 ///
 /// It is possible to convert pairs of type `(T1, T2)`, where `T1` and `T2` are primitive
 /// number types, to this type, however, this is mostly needed for internal usage.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
 pub struct Dimensions {
     /// Image width in pixels.
@@ -88,6 +104,79 @@ pub struct Dimensions {
     pub height: u32
 }
 
+impl Dimensions {
+    /// Returns the ratio of width to height.
+    ///
+    /// The result is infinite or NaN if height is zero.
+    #[inline]
+    pub fn aspect_ratio(&self) -> f64 {
+        self.width as f64 / self.height as f64
+    }
+
+    /// Returns the number of pixels in an image of these dimensions.
+    #[inline]
+    pub fn area(&self) -> u64 {
+        self.width as u64 * self.height as u64
+    }
+
+    /// Compares these dimensions with other ones by the number of pixels.
+    ///
+    /// Note that dimensions of different shape may compare as equal.
+    #[inline]
+    pub fn pixel_cmp(&self, other: &Dimensions) -> Ordering {
+        self.area().cmp(&other.area())
+    }
+
+    /// Returns dimensions rotated by 90 degrees, i.e. with width and height swapped.
+    #[inline]
+    pub fn rotate90(self) -> Dimensions {
+        Dimensions { width: self.height, height: self.width }
+    }
+
+    /// Returns the largest dimensions which have the same aspect ratio as these ones and fit
+    /// into the given bounding box.
+    ///
+    /// The result may be larger than these dimensions if the bounding box is larger. The
+    /// scaled side is rounded to the nearest integer. Zero dimensions are returned if either
+    /// width or height is zero, since there is no aspect ratio to preserve.
+    pub fn scaled_to_fit(&self, max: Dimensions) -> Dimensions {
+        if self.width == 0 || self.height == 0 {
+            return Dimensions { width: 0, height: 0 };
+        }
+
+        // divides and rounds to the nearest integer; a product of two u32 values plus half
+        // of a u32 value can't overflow
+        fn div_round(a: u64, b: u64) -> u32 {
+            ((a + b / 2) / b) as u32
+        }
+
+        let (w, h) = (self.width as u64, self.height as u64);
+        if w * max.height as u64 <= h * max.width as u64 {
+            Dimensions {
+                width: div_round(w * max.height as u64, h),
+                height: max.height
+            }
+        } else {
+            Dimensions {
+                width: max.width,
+                height: div_round(h * max.width as u64, w)
+            }
+        }
+    }
+}
+
+/// Returns the dimensions with the largest number of pixels, as compared by
+/// `Dimensions::pixel_cmp()`.
+///
+/// If several dimensions have the same largest number of pixels, the first of them is
+/// returned. `None` is returned if the iterator is empty.
+pub fn max_by_pixels<I: IntoIterator<Item=Dimensions>>(iter: I) -> Option<Dimensions> {
+    iter.into_iter().fold(None, |max, d| match max {
+        Some(m) if d.pixel_cmp(&m) != Ordering::Greater => Some(m),
+        _ => Some(d)
+    })
+}
+
 impl<T: ToPrimitive, U: ToPrimitive> From<(T, U)> for Dimensions {
     fn from((w, h): (T, U)) -> Dimensions {
         Dimensions {
@@ -96,3 +185,249 @@ impl<T: ToPrimitive, U: ToPrimitive> From<(T, U)> for Dimensions {
         }
     }
 }
+
+impl fmt::Display for Dimensions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}x{}", self.width, self.height)
+    }
+}
+
+/// Represents an unsigned rational number, as stored in EXIF tags.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Rational {
+    /// Numerator of the fraction.
+    pub numerator: u32,
+    /// Denominator of the fraction.
+    pub denominator: u32
+}
+
+impl Rational {
+    /// Returns the value of this fraction as a floating point number.
+    #[inline]
+    pub fn as_f64(&self) -> f64 {
+        self.numerator as f64 / self.denominator as f64
+    }
+}
+
+impl fmt::Display for Rational {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.numerator, self.denominator)
+    }
+}
+
+/// Represents a signed rational number, as stored in EXIF tags.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct SignedRational {
+    /// Numerator of the fraction.
+    pub numerator: i32,
+    /// Denominator of the fraction.
+    pub denominator: i32
+}
+
+impl SignedRational {
+    /// Returns the value of this fraction as a floating point number.
+    #[inline]
+    pub fn as_f64(&self) -> f64 {
+        self.numerator as f64 / self.denominator as f64
+    }
+}
+
+impl fmt::Display for SignedRational {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.numerator, self.denominator)
+    }
+}
+
+/// Represents date and time as stored in EXIF tags.
+///
+/// EXIF does not record time zone, so these values should be treated as local time.
+///
+/// Values are ordered chronologically.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct DateTime {
+    /// Year, e.g. 2015.
+    pub year: u16,
+    /// Month, between 1 and 12.
+    pub month: u8,
+    /// Day of month, between 1 and 31.
+    pub day: u8,
+    /// Hour, between 0 and 23.
+    pub hour: u8,
+    /// Minute, between 0 and 59.
+    pub minute: u8,
+    /// Second, between 0 and 60 (to allow for leap seconds).
+    pub second: u8,
+    /// Fraction of the second in nanoseconds, from the corresponding EXIF SubSecTime tag.
+    ///
+    /// It is 0 if the sub-second time is not recorded.
+    pub nanosecond: u32
+}
+
+impl DateTime {
+    /// Parses date and time in EXIF format, i.e. "YYYY:MM:DD HH:MM:SS".
+    ///
+    /// Trailing NUL characters and whitespace are ignored. The sub-second part is stored
+    /// separately in EXIF, so `nanosecond` of the result is 0.
+    pub fn parse(s: &str) -> Result<DateTime> {
+        let t = s.trim_end_matches(|c: char| c == '\0' || c.is_whitespace());
+        let b = t.as_bytes();
+
+        let separators_valid = b.len() == 19 &&
+            b[4] == b':' && b[7] == b':' && b[10] == b' ' && b[13] == b':' && b[16] == b':';
+        if !separators_valid {
+            return Err(invalid_format!("invalid date and time: {:?}", s));
+        }
+
+        fn number(s: &str, b: &[u8]) -> Result<u16> {
+            if b.iter().any(|c| !c.is_ascii_digit()) {
+                return Err(invalid_format!("invalid date and time: {:?}", s));
+            }
+            Ok(b.iter().fold(0, |acc, &c| acc*10 + (c - b'0') as u16))
+        }
+
+        let dt = DateTime {
+            year: try!(number(s, &b[0..4])),
+            month: try!(number(s, &b[5..7])) as u8,
+            day: try!(number(s, &b[8..10])) as u8,
+            hour: try!(number(s, &b[11..13])) as u8,
+            minute: try!(number(s, &b[14..16])) as u8,
+            second: try!(number(s, &b[17..19])) as u8,
+            nanosecond: 0
+        };
+
+        if dt.month < 1 || dt.month > 12 || dt.day < 1 || dt.day > 31 ||
+           dt.hour > 23 || dt.minute > 59 || dt.second > 60 {
+            return Err(invalid_format!("date and time is out of range: {:?}", s));
+        }
+
+        Ok(dt)
+    }
+}
+
+/// Image orientation, as recorded in the EXIF Orientation tag.
+///
+/// Each variant describes the transformation which should be applied to the stored image
+/// in order to display it correctly.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Orientation {
+    /// No transformation is needed (EXIF value 1).
+    Normal,
+    /// The image should be mirrored horizontally (EXIF value 2).
+    MirrorHorizontal,
+    /// The image should be rotated by 180 degrees (EXIF value 3).
+    Rotate180,
+    /// The image should be mirrored vertically (EXIF value 4).
+    MirrorVertical,
+    /// The image should be mirrored horizontally and then rotated 270 degrees clockwise
+    /// (EXIF value 5).
+    Transpose,
+    /// The image should be rotated 90 degrees clockwise (EXIF value 6).
+    Rotate90,
+    /// The image should be mirrored horizontally and then rotated 90 degrees clockwise
+    /// (EXIF value 7).
+    Transverse,
+    /// The image should be rotated 270 degrees clockwise (EXIF value 8).
+    Rotate270
+}
+
+/// Clockwise rotation by a multiple of 90 degrees.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Rotation {
+    /// The image is not rotated.
+    None,
+    /// The image is rotated by 90 degrees clockwise.
+    Rotate90,
+    /// The image is rotated by 180 degrees.
+    Rotate180,
+    /// The image is rotated by 270 degrees clockwise, i.e. by 90 degrees counterclockwise.
+    Rotate270
+}
+
+impl Orientation {
+    /// Converts a value of the EXIF Orientation tag to an orientation.
+    ///
+    /// Returns `None` if the value is not in the range 1 to 8.
+    pub fn from_exif(n: u16) -> Option<Orientation> {
+        match n {
+            1 => Some(Orientation::Normal),
+            2 => Some(Orientation::MirrorHorizontal),
+            3 => Some(Orientation::Rotate180),
+            4 => Some(Orientation::MirrorVertical),
+            5 => Some(Orientation::Transpose),
+            6 => Some(Orientation::Rotate90),
+            7 => Some(Orientation::Transverse),
+            8 => Some(Orientation::Rotate270),
+            _ => None
+        }
+    }
+
+    /// Returns the value of the EXIF Orientation tag for this orientation.
+    pub fn to_exif(&self) -> u16 {
+        match *self {
+            Orientation::Normal => 1,
+            Orientation::MirrorHorizontal => 2,
+            Orientation::Rotate180 => 3,
+            Orientation::MirrorVertical => 4,
+            Orientation::Transpose => 5,
+            Orientation::Rotate90 => 6,
+            Orientation::Transverse => 7,
+            Orientation::Rotate270 => 8
+        }
+    }
+
+    /// Decomposes the transformation into a rotation and a flag of horizontal mirroring.
+    ///
+    /// If the flag is `true`, the image should be mirrored horizontally before it is rotated.
+    pub fn steps(&self) -> (Rotation, bool) {
+        match *self {
+            Orientation::Normal => (Rotation::None, false),
+            Orientation::MirrorHorizontal => (Rotation::None, true),
+            Orientation::Rotate180 => (Rotation::Rotate180, false),
+            Orientation::MirrorVertical => (Rotation::Rotate180, true),
+            Orientation::Transpose => (Rotation::Rotate270, true),
+            Orientation::Rotate90 => (Rotation::Rotate90, false),
+            Orientation::Transverse => (Rotation::Rotate90, true),
+            Orientation::Rotate270 => (Rotation::Rotate270, false)
+        }
+    }
+
+    /// Returns the affine transformation which should be applied to the stored image.
+    ///
+    /// The matrix `[a, b, c, d, e, f]` maps a point `(x, y)` of the stored image to the point
+    /// `(a*x + c*y + e, b*x + d*y + f)` of the displayed image, using the same order of
+    /// elements as e.g. CSS and the canvas API. Coordinates are normalized, i.e. `(0, 0)` is
+    /// the top left corner and `(1, 1)` is the bottom right corner of both images; multiply
+    /// the translation by the displayed width and height to get pixel coordinates.
+    pub fn transform_matrix(&self) -> [f32; 6] {
+        match *self {
+            Orientation::Normal => [1.0, 0.0, 0.0, 1.0, 0.0, 0.0],
+            Orientation::MirrorHorizontal => [-1.0, 0.0, 0.0, 1.0, 1.0, 0.0],
+            Orientation::Rotate180 => [-1.0, 0.0, 0.0, -1.0, 1.0, 1.0],
+            Orientation::MirrorVertical => [1.0, 0.0, 0.0, -1.0, 0.0, 1.0],
+            Orientation::Transpose => [0.0, 1.0, 1.0, 0.0, 0.0, 0.0],
+            Orientation::Rotate90 => [0.0, 1.0, -1.0, 0.0, 1.0, 0.0],
+            Orientation::Transverse => [0.0, -1.0, -1.0, 0.0, 1.0, 1.0],
+            Orientation::Rotate270 => [0.0, -1.0, 1.0, 0.0, 0.0, 1.0]
+        }
+    }
+}
+
+impl fmt::Display for Orientation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Orientation::Normal => "normal",
+            Orientation::MirrorHorizontal => "mirror horizontal",
+            Orientation::Rotate180 => "rotate 180°",
+            Orientation::MirrorVertical => "mirror vertical",
+            Orientation::Transpose => "mirror horizontal and rotate 270° CW",
+            Orientation::Rotate90 => "rotate 90° CW",
+            Orientation::Transverse => "mirror horizontal and rotate 90° CW",
+            Orientation::Rotate270 => "rotate 270° CW"
+        })
+    }
+}
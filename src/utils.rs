@@ -1,13 +1,49 @@
-use std::io::{self, Read, BufRead, ErrorKind};
+use std::cmp;
+use std::io::{self, Read, BufRead, Seek, SeekFrom, ErrorKind};
 
+/// Maximum number of bytes which `skip_exact_0()` requests from the underlying reader at once.
+const SKIP_CHUNK_SIZE: u64 = 64 * 1024;
+
+/// Extension methods for readers.
+///
+/// Methods with the `_0` suffix never fail on the end of the stream; instead, they return
+/// the number of bytes actually read or skipped, which callers compare with the requested
+/// number.
 pub trait ReadExt: Read {
+    /// Reads bytes into `buf` until it is full or the stream ends, returning the number of
+    /// bytes read.
     fn read_exact_0(&mut self, mut buf: &mut [u8]) -> io::Result<usize> {
         let orig_len = buf.len() as u64;
         io::copy(&mut self.take(orig_len), &mut buf).map(|r| r as usize)
     }
 
+    /// Fills `buf` completely, failing with `UnexpectedEOF` if the stream ends before that.
+    ///
+    /// The error is the same as the one of `byteorder` methods, so the result can be passed
+    /// to `try_if_eof!` to provide the context of the read.
+    fn read_full(&mut self, buf: &mut [u8]) -> ::byteorder::Result<()> {
+        if try!(self.read_exact_0(buf)) != buf.len() {
+            return Err(::byteorder::Error::UnexpectedEOF);
+        }
+        Ok(())
+    }
+
+    /// Skips `n` bytes, returning the number of bytes actually skipped.
+    ///
+    /// The result is less than `n` only if the stream has ended; callers are expected to
+    /// compare it with `n` to detect a truncated stream. The skip is performed in chunks of
+    /// bounded size, so a huge `n` coming from a corrupted file is harmless.
     fn skip_exact_0(&mut self, n: u64) -> io::Result<u64> {
-        io::copy(&mut self.take(n), &mut io::sink())
+        let mut skipped = 0;
+        while skipped < n {
+            let chunk = cmp::min(n - skipped, SKIP_CHUNK_SIZE);
+            let k = try!(io::copy(&mut (&mut *self).take(chunk), &mut io::sink()));
+            skipped += k;
+            if k < chunk {
+                break;
+            }
+        }
+        Ok(skipped)
     }
 
     fn read_to_vec(&mut self) -> io::Result<Vec<u8>> {
@@ -20,6 +56,10 @@ pub trait ReadExt: Read {
 impl<R: ?Sized + Read> ReadExt for R {}
 
 pub trait BufReadExt: BufRead {
+    /// Skips `n` bytes, returning the number of bytes actually skipped.
+    ///
+    /// The result is less than `n` only if the stream has ended. Only the data which is
+    /// already buffered is consumed at once, so the amount of memory used does not depend on `n`.
     fn skip_exact(&mut self, n: u64) -> io::Result<u64> {
         let mut skipped = 0;
         loop {
@@ -45,6 +85,11 @@ pub trait BufReadExt: BufRead {
         Ok(skipped)
     }
 
+    /// Skips bytes up to and including the first `delim` byte, returning the number of bytes
+    /// skipped.
+    ///
+    /// If the stream ends before `delim` is found, all remaining bytes are skipped. Zero is
+    /// returned only if the stream has already ended.
     fn skip_until(&mut self, delim: u8) -> io::Result<usize> {
         let mut read = 0;
         loop {
@@ -69,3 +114,192 @@ pub trait BufReadExt: BufRead {
 }
 
 impl<R: ?Sized + BufRead> BufReadExt for R {}
+
+/// A reader which counts the number of bytes read from it.
+pub struct CountingReader<R> {
+    inner: R,
+    count: u64
+}
+
+impl<R> CountingReader<R> {
+    pub fn new(inner: R) -> CountingReader<R> {
+        CountingReader {
+            inner: inner,
+            count: 0
+        }
+    }
+
+    /// Returns the total number of bytes read so far.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = try!(self.inner.read(buf));
+        self.count += n as u64;
+        Ok(n)
+    }
+}
+
+/// A reader which fails once more than the given number of bytes is read from it.
+///
+/// Seeking to the start of the stream resets the limit, so when several decoders are tried
+/// one after another, each of them may read up to the limit.
+pub struct LimitedReader<R> {
+    inner: R,
+    limit: u64,
+    remaining: u64,
+    exceeded: bool
+}
+
+impl<R> LimitedReader<R> {
+    pub fn new(inner: R, limit: u64) -> LimitedReader<R> {
+        LimitedReader {
+            inner: inner,
+            limit: limit,
+            remaining: limit,
+            exceeded: false
+        }
+    }
+
+    /// Returns `true` if an attempt to read past the limit has been made while there was
+    /// still data left in the underlying reader.
+    pub fn exceeded(&self) -> bool {
+        self.exceeded
+    }
+}
+
+impl<R: Read> Read for LimitedReader<R> {
+    // `io::Error::other()` would require a much newer compiler
+    #[allow(clippy::io_other_error)]
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if self.remaining == 0 {
+            // reading exactly up to the limit is fine as long as nothing is left unread
+            let mut probe = [0u8; 1];
+            if try!(self.inner.read(&mut probe)) == 0 {
+                return Ok(0);
+            }
+            self.exceeded = true;
+            return Err(io::Error::new(ErrorKind::Other, "exceeded read limit"));
+        }
+        let max = cmp::min(buf.len() as u64, self.remaining) as usize;
+        let n = try!(self.inner.read(&mut buf[..max]));
+        self.remaining -= n as u64;
+        Ok(n)
+    }
+}
+
+impl<R: Seek> Seek for LimitedReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let result = try!(self.inner.seek(pos));
+        if let SeekFrom::Start(0) = pos {
+            self.remaining = self.limit;
+        }
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Read, Seek, SeekFrom, BufReader};
+
+    use super::{ReadExt, BufReadExt, LimitedReader, SKIP_CHUNK_SIZE};
+
+    /// A reader which returns at most one byte at a time.
+    struct ByteReader<'a>(&'a [u8]);
+
+    impl<'a> Read for ByteReader<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let len = if buf.is_empty() { 0 } else { 1 };
+            self.0.read(&mut buf[..len])
+        }
+    }
+
+    #[test]
+    fn test_read_full() {
+        let data = [1u8, 2, 3, 4, 5];
+
+        let mut r = ByteReader(&data);
+        let mut buf = [0u8; 3];
+        r.read_full(&mut buf).unwrap();
+        assert_eq!(buf, [1, 2, 3]);
+
+        // only two bytes are left
+        match r.read_full(&mut buf) {
+            Err(::byteorder::Error::UnexpectedEOF) => {}
+            r => panic!("unexpected result: {:?}", r)
+        }
+
+        let mut r = &data[..];
+        r.read_full(&mut []).unwrap();
+        let mut buf = [0u8; 5];
+        r.read_full(&mut buf).unwrap();
+        assert_eq!(buf, data);
+    }
+
+    #[test]
+    fn test_skip_exact_0() {
+        let data = vec![7u8; SKIP_CHUNK_SIZE as usize * 2 + 10];
+
+        let mut r = &data[..];
+        assert_eq!(r.skip_exact_0(5).unwrap(), 5);
+        assert_eq!(r.len(), data.len() - 5);
+        assert_eq!(r.skip_exact_0(SKIP_CHUNK_SIZE * 2).unwrap(), SKIP_CHUNK_SIZE * 2);
+        assert_eq!(r.len(), 5);
+        assert_eq!(r.skip_exact_0(0).unwrap(), 0);
+
+        // the stream ends in the middle of a skip
+        assert_eq!(r.skip_exact_0(8).unwrap(), 5);
+        assert_eq!(r.skip_exact_0(8).unwrap(), 0);
+
+        let mut r = &data[..];
+        assert_eq!(r.skip_exact_0(u64::MAX).unwrap(), data.len() as u64);
+
+        let mut r = ByteReader(&data[..10]);
+        assert_eq!(r.skip_exact_0(4).unwrap(), 4);
+        assert_eq!(r.skip_exact_0(100).unwrap(), 6);
+    }
+
+    #[test]
+    fn test_skip_exact() {
+        let data = [1u8, 2, 3, 4, 5, 6, 7, 8, 9, 10];
+
+        let mut r = BufReader::with_capacity(3, ByteReader(&data));
+        assert_eq!(r.skip_exact(4).unwrap(), 4);
+        assert_eq!(r.read_to_vec().unwrap(), &data[4..]);
+
+        // the stream ends in the middle of a skip
+        let mut r = BufReader::with_capacity(3, &data[..]);
+        assert_eq!(r.skip_exact(2).unwrap(), 2);
+        assert_eq!(r.skip_exact(100).unwrap(), 8);
+        assert_eq!(r.skip_exact(1).unwrap(), 0);
+
+        let mut r = &data[..];
+        assert_eq!(r.skip_exact(u64::MAX).unwrap(), data.len() as u64);
+    }
+
+    #[test]
+    fn test_limited_reader() {
+        let data = [1u8, 2, 3, 4, 5];
+
+        // reading exactly up to the limit is not an error
+        let mut r = LimitedReader::new(io::Cursor::new(&data[..]), 5);
+        assert_eq!(r.read_to_vec().unwrap(), &data[..]);
+        assert!(!r.exceeded());
+
+        let mut r = LimitedReader::new(io::Cursor::new(&data[..]), 3);
+        assert!(r.read_to_vec().is_err());
+        assert!(r.exceeded());
+
+        // seeking to the start resets the limit
+        r.seek(SeekFrom::Start(0)).unwrap();
+        let mut buf = [0u8; 3];
+        r.read_full(&mut buf).unwrap();
+        assert_eq!(buf, [1, 2, 3]);
+    }
+}
@@ -1,8 +1,20 @@
 extern crate immeta;
+#[cfg(feature = "serde")] extern crate serde_json;
+#[cfg(feature = "tokio")] extern crate tokio;
 
-use immeta::Dimensions;
-use immeta::formats::{png, gif};
-use immeta::markers::{Png, Gif, Jpeg, Webp};
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Cursor};
+use std::time::Duration;
+
+use immeta::{Dimensions, Rational, SignedRational, DateTime, Error, LoadableMetadata};
+use immeta::formats::{jpeg, png, gif, webp, tiff, bmp, ico, heif};
+use immeta::markers::{MetadataMarker, Png, Gif, Jpeg, Webp, Bmp, Ico, Heif, Tiff};
+
+fn read_file(path: &str) -> Vec<u8> {
+    let mut data = Vec::new();
+    File::open(path).unwrap().read_to_end(&mut data).unwrap();
+    data
+}
 
 const OWLET_DIM: Dimensions = Dimensions {
     width: 1280,
@@ -29,6 +41,96 @@ fn test_jpeg() {
     // let md = Jpeg::from(md).ok()
     let md = md.into::<Jpeg>().ok().expect("not JPEG metadata");
     assert_eq!(md.dimensions, OWLET_DIM);
+    assert_eq!(md.coding, jpeg::Coding::Baseline);
+    assert_eq!(md.bits_per_sample, 8);
+    assert_eq!(md.num_components, 3);
+    assert!(!md.is_grayscale());
+    assert_eq!(md.density, Some(jpeg::Density { x: 1, y: 1, unit: jpeg::DensityUnit::None }));
+}
+
+#[test]
+fn test_load_concrete_from_file() {
+    let md = jpeg::Metadata::load_from_file("tests/images/owlet.jpg").unwrap();
+    assert_eq!(md.dimensions, OWLET_DIM);
+    assert_eq!(md.coding, jpeg::Coding::Baseline);
+
+    match jpeg::Metadata::load_from_file("tests/images/owlet.png") {
+        Err(Error::InvalidFormat(_)) => {}
+        r => panic!("unexpected result: {:?}", r)
+    }
+    match jpeg::Metadata::load_from_file("tests/images/missing.jpg") {
+        Err(Error::Io(ref e)) if e.kind() == io::ErrorKind::NotFound => {}
+        r => panic!("unexpected result: {:?}", r)
+    }
+}
+
+#[test]
+fn test_jpeg_segments() {
+    let f = File::open("tests/images/owlet.jpg").unwrap();
+    let segments: Vec<_> = jpeg::segments(f).map(|s| s.unwrap()).collect();
+    let markers: Vec<_> = segments.iter().map(|s| s.marker).collect();
+    assert_eq!(markers, vec![0xd8, 0xe0, 0xdb, 0xdb, 0xc0, 0xc4, 0xc4, 0xc4, 0xc4]);
+    assert!(segments[0].data.is_empty());
+    assert!(segments[1].data.starts_with(b"JFIF\0"));
+    assert_eq!(segments[4].data.len(), 15);
+
+    let file = read_file("tests/images/owlet.jpg");
+    assert_eq!((segments[0].offset, segments[0].length), (0, 2));
+    for w in segments.windows(2) {
+        assert_eq!(w[1].offset, w[0].offset + w[0].length as u64);
+    }
+    for s in &segments {
+        assert_eq!(&file[s.offset as usize..s.offset as usize + 2], &[0xff, s.marker]);
+        assert_eq!(s.length, if s.marker == 0xd8 { 2 } else { s.data.len() + 4 });
+    }
+
+    // fill bytes before a marker are not included in the segment
+    let comment = segment(0xfe, b"hello");
+    let data = build_jpeg(&[comment.clone(), vec![0xff, 0xff]]);
+    let segments: Vec<_> = jpeg::segments(&data[..]).map(|s| s.unwrap()).collect();
+    assert_eq!(segments[1].marker, 0xfe);
+    assert_eq!((segments[1].offset, segments[1].length), (2, comment.len()));
+    assert_eq!(segments[2].marker, 0xc0);
+    assert_eq!(segments[2].offset, 2 + comment.len() as u64 + 2);
+    assert_eq!(segments[2].length, 19);
+
+    let data = build_jpeg(&[]);
+    let mut segments = jpeg::segments(&data[..data.len() - 10]);
+    assert_eq!(segments.next().unwrap().unwrap().marker, 0xd8);
+    assert!(segments.next().unwrap().is_err());
+    assert!(segments.next().is_none());
+}
+
+#[test]
+fn test_jpeg_missing_soi() {
+    let png = read_file("tests/images/owlet.png");
+    for data in &[&png[..], b"random bytes \xff\xc0 with a marker"] {
+        match Jpeg::load_from_buf(data) {
+            Err(Error::InvalidFormat(ref msg)) if msg == "not a JPEG: missing SOI" => {}
+            r => panic!("unexpected result: {:?}", r)
+        }
+    }
+
+    // nothing is read past the first two bytes
+    let mut r = (&b"\x89P"[..]).chain(FailingReader);
+    match Jpeg::load(&mut r) {
+        Err(Error::InvalidFormat(_)) => {}
+        r => panic!("unexpected result: {:?}", r)
+    }
+}
+
+#[test]
+fn test_jpeg_without_sof() {
+    let mut data = read_file("tests/images/owlet.jpg");
+
+    // SOF0 segment of owlet.jpg: marker + 17 bytes of payload
+    assert_eq!(&data[158..160], &[0xff, 0xc0]);
+    data.drain(158..158 + 2 + 17);
+
+    match Jpeg::load_from_buf(&data) {
+        Err(Error::InvalidFormat(_)) => {}
+        r => panic!("unexpected result: {:?}", r)
+    }
 }
 
 #[test]
@@ -42,11 +144,40 @@ fn test_png() {
     assert_eq!(md.dimensions, OWLET_DIM);
     assert_eq!(md.color_type, png::ColorType::Rgb);
     assert_eq!(md.color_depth, 24);
+    assert_eq!(md.bit_depth, 8);
+    assert!(!md.is_interlaced());
     assert_eq!(md.compression_method, png::CompressionMethod::DeflateInflate);
     assert_eq!(md.filter_method, png::FilterMethod::AdaptiveFiltering);
     assert_eq!(md.interlace_method, png::InterlaceMethod::Disabled);
 }
 
+#[test]
+fn test_png_truncated() {
+    let data = read_file("tests/images/owlet.png");
+    for &len in &[100, 1000, data.len() / 2] {
+        let md = immeta::load_from_buf(&data[..len]).unwrap();
+        assert_eq!(md.format(), immeta::Format::Png);
+        assert_eq!(md.dimensions(), OWLET_DIM);
+    }
+
+    // all chunks are needed to verify them
+    let verify = png::Options { verify_crc: true };
+    match png::Metadata::load_with_options(&mut &data[..data.len() / 2], verify) {
+        Err(Error::UnexpectedEndOfFile(_)) => {}
+        r => panic!("unexpected result: {:?}", r)
+    }
+
+    // chunks preceding the truncated IDAT are kept
+    let mut data = build_png(&[png_chunk(b"tEXt", b"Title\0Owlet")]);
+    data.truncate(data.len() - 12 - 6);
+    let md = Png::load_from_buf(&data).unwrap();
+    assert_eq!(md.dimensions, JPEG_DIM);
+    assert_eq!(md.text[0].value, "Owlet");
+
+    // IHDR itself must be complete
+    assert!(Png::load_from_buf(&data[..20]).is_err());
+}
+
 
 #[test]
 fn test_gif_plain() {
@@ -66,7 +197,8 @@ fn test_gif_plain() {
     assert_eq!(md.background_color_index, 0);
     assert_eq!(md.pixel_aspect_ratio, 0);
     assert_eq!(md.frames_number(), 1);
-    assert_eq!(md.is_animated(), false);
+    assert!(!md.is_animated());
+    assert_eq!(md.loop_count, None);
     assert_eq!(md.blocks, vec![
         gif::Block::GraphicControlExtension(gif::GraphicControlExtension {
             disposal_method: gif::DisposalMethod::None,
@@ -105,7 +237,11 @@ fn test_gif_animated() {
     assert_eq!(md.background_color_index, 255);
     assert_eq!(md.pixel_aspect_ratio, 0);
     assert_eq!(md.frames_number(), 30);
-    assert_eq!(md.is_animated(), true);
+    assert!(md.is_animated());
+    assert_eq!(md.loop_count, Some(0));
+    assert_eq!(md.frame_count, 30);
+    assert_eq!(md.frame_delays, vec![7; 30]);
+    assert_eq!(md.comments, vec!["Created with GIMP on a Mac".to_owned()]);
 
     let mut blocks = md.blocks.iter();
 
@@ -123,8 +259,8 @@ fn test_gif_animated() {
     );
 
     for i in 0..30 {
-        match blocks.next() {
-            Some(&gif::Block::GraphicControlExtension(ref gce)) => {
+        match *blocks.next().unwrap() {
+            gif::Block::GraphicControlExtension(ref gce) => {
                 assert_eq!(
                     gce,
                     &gif::GraphicControlExtension {
@@ -166,6 +302,2680 @@ fn test_webp() {
     assert_eq!(md.dimensions(), CHERRY_DIM);
 
     let md = md.into::<Webp>().ok().expect("not WEBP metadata");
+    assert!(!md.is_lossless());
+    assert!(!md.has_alpha());
+
+    match md {
+        webp::Metadata::VP8(_) => {}
+        md => panic!("unexpected metadata: {:?}", md)
+    }
+}
+
+fn put_u32_le(out: &mut Vec<u8>, n: u32) {
+    out.extend(&[n as u8, (n >> 8) as u8, (n >> 16) as u8, (n >> 24) as u8]);
+}
+
+fn build_webp(chunks: &[(&[u8], &[u8])]) -> Vec<u8> {
+    let mut body = b"WEBP".to_vec();
+    for &(chunk_id, payload) in chunks {
+        body.extend(chunk_id);
+        put_u32_le(&mut body, payload.len() as u32);
+        body.extend(payload);
+    }
+
+    let mut out = b"RIFF".to_vec();
+    put_u32_le(&mut out, body.len() as u32);
+    out.extend(body);
+    out
+}
+
+#[test]
+fn test_webp_lossless() {
+    // 640x480, alpha is used, version 0
+    let bits: u32 = 639 | 479 << 14 | 1 << 28;
+    let data = build_webp(&[(b"VP8L", &[
+        0x2f, bits as u8, (bits >> 8) as u8, (bits >> 16) as u8, (bits >> 24) as u8, 0, 0
+    ])]);
+
+    let md = immeta::load_from_buf(&data).unwrap();
+    assert_eq!(md.dimensions(), Dimensions { width: 640, height: 480 });
+
+    let md = md.into::<Webp>().ok().expect("not WEBP metadata");
+    assert!(md.is_lossless());
+    assert!(md.has_alpha());
+    match md {
+        webp::Metadata::VP8L(md) => {
+            assert!(md.alpha_is_used);
+            assert_eq!(md.version_number, 0);
+        }
+        md => panic!("unexpected metadata: {:?}", md)
+    }
+
+    // alpha is not used
+    let bits: u32 = 639 | 479 << 14;
+    let data = build_webp(&[(b"VP8L", &[
+        0x2f, bits as u8, (bits >> 8) as u8, (bits >> 16) as u8, (bits >> 24) as u8, 0, 0
+    ])]);
+    let md = Webp::load_from_buf(&data).unwrap();
+    assert!(md.is_lossless());
+    assert!(!md.has_alpha());
+}
+
+#[test]
+fn test_webp_extended() {
+    // alpha flag, 1024x772 canvas
+    let vp8x: &[u8] = &[0x10, 0, 0, 0, 0xff, 0x03, 0x00, 0x03, 0x03, 0x00];
+    // lossy image data, with the alpha channel in a separate chunk
+    let data = build_webp(&[(b"VP8X", vp8x), (b"ALPH", &[0, 0]), (b"VP8 ", &[0; 10])]);
+
+    let md = immeta::load_from_buf(&data).unwrap();
+    assert_eq!(md.dimensions(), CHERRY_DIM);
+
+    let md = md.into::<Webp>().ok().expect("not WEBP metadata");
+    assert!(!md.is_lossless());
+    assert!(md.has_alpha());
+    match md {
+        webp::Metadata::VP8X(md) => {
+            assert!(md.has_alpha);
+            assert!(!md.has_animation);
+            assert!(!md.has_icc);
+            assert!(!md.has_exif);
+            assert!(!md.has_xmp);
+            assert!(!md.is_lossless);
+        }
+        md => panic!("unexpected metadata: {:?}", md)
+    }
+
+    // lossless image data, preceded by an ICC profile
+    let data = build_webp(&[(b"VP8X", vp8x), (b"ICCP", &[0; 4]), (b"VP8L", &[0x2f, 0, 0, 0, 0])]);
+    assert!(Webp::load_from_buf(&data).unwrap().is_lossless());
+
+    // the image data must be present
+    assert!(Webp::load_from_buf(&build_webp(&[(b"VP8X", vp8x)])).is_err());
+}
+
+/// Values of IFD entries used to build synthetic EXIF data.
+#[allow(dead_code)]
+enum Value {
+    Byte(Vec<u8>),
+    Ascii(&'static str),
+    Short(Vec<u16>),
+    Long(Vec<u32>),
+    Rational(Vec<(u32, u32)>),
+    SignedRational(Vec<(i32, i32)>),
+    Undefined(Vec<u8>),
+    /// A pointer to a sub-IFD, stored as a long value.
+    Ifd(Vec<(u16, Value)>),
+    /// A pointer to arbitrary data, stored as a long value.
+    Offset(Vec<u8>)
+}
+
+fn put_u16(out: &mut Vec<u8>, n: u16) {
+    out.extend(&[(n >> 8) as u8, n as u8]);
+}
+
+fn put_u32(out: &mut Vec<u8>, n: u32) {
+    out.extend(&[(n >> 24) as u8, (n >> 16) as u8, (n >> 8) as u8, n as u8]);
+}
+
+fn patch_u32(out: &mut [u8], pos: usize, n: u32) {
+    let mut buf = Vec::new();
+    put_u32(&mut buf, n);
+    out[pos..pos + 4].copy_from_slice(&buf);
+}
+
+/// Returns datatype, count and encoded data of a value.
+fn encode_value(value: &Value) -> (u16, u32, Vec<u8>) {
+    let mut data = Vec::new();
+    match *value {
+        Value::Byte(ref v) => { data.extend(v); (1, v.len() as u32, data) }
+        Value::Ascii(s) => { data.extend(s.as_bytes()); data.push(0); (2, s.len() as u32 + 1, data) }
+        Value::Short(ref v) => { for &n in v { put_u16(&mut data, n); } (3, v.len() as u32, data) }
+        Value::Long(ref v) => { for &n in v { put_u32(&mut data, n); } (4, v.len() as u32, data) }
+        Value::Rational(ref v) => {
+            for &(n, d) in v { put_u32(&mut data, n); put_u32(&mut data, d); }
+            (5, v.len() as u32, data)
+        }
+        Value::Undefined(ref v) => { data.extend(v); (7, v.len() as u32, data) }
+        Value::SignedRational(ref v) => {
+            for &(n, d) in v { put_u32(&mut data, n as u32); put_u32(&mut data, d as u32); }
+            (10, v.len() as u32, data)
+        }
+        Value::Ifd(_) => (4, 1, vec![0; 4]),
+        Value::Offset(ref v) => (4, 1, v.clone())
+    }
+}
+
+/// Writes an IFD with all its out-of-line values and sub-IFDs at the end of `out`.
+///
+/// Returns the position of the "next IFD offset" field.
+fn write_ifd(out: &mut Vec<u8>, entries: &[(u16, Value)]) -> usize {
+    put_u16(out, entries.len() as u16);
+    let mut deferred = Vec::new();
+    for &(id, ref value) in entries {
+        let (datatype, count, mut data) = encode_value(value);
+        put_u16(out, id);
+        put_u16(out, datatype);
+        put_u32(out, count);
+        match *value {
+            Value::Ifd(_) | Value::Offset(_) => { deferred.push((out.len(), value, data)); put_u32(out, 0); }
+            _ if data.len() > 4 => { deferred.push((out.len(), value, data)); put_u32(out, 0); }
+            _ => { data.resize(4, 0); out.extend(&data); }
+        }
+    }
+    let next_ifd_pos = out.len();
+    put_u32(out, 0);
+
+    for (pos, value, data) in deferred {
+        let offset = out.len() as u32;
+        patch_u32(out, pos, offset);
+        match *value {
+            Value::Ifd(ref sub) => { write_ifd(out, sub); }
+            _ => {
+                out.extend(&data);
+                if data.len() % 2 == 1 { out.push(0); }
+            }
+        }
+    }
+
+    next_ifd_pos
+}
+
+/// Builds a big-endian TIFF structure containing the given chain of IFDs.
+fn build_tiff(ifds: &[Vec<(u16, Value)>]) -> Vec<u8> {
+    let mut out = b"MM\x00\x2a".to_vec();
+    put_u32(&mut out, 8);
+    let mut next_ifd_pos = None;
+    for ifd in ifds {
+        if let Some(pos) = next_ifd_pos {
+            let offset = out.len() as u32;
+            patch_u32(&mut out, pos, offset);
+        }
+        next_ifd_pos = Some(write_ifd(&mut out, ifd));
+    }
+    out
+}
+
+fn segment(marker: u8, payload: &[u8]) -> Vec<u8> {
+    let mut out = vec![0xff, marker];
+    put_u16(&mut out, payload.len() as u16 + 2);
+    out.extend(payload);
+    out
+}
+
+/// Builds a minimal JPEG with a 640x480 frame header of the given SOF marker type and the
+/// given segments before it.
+fn build_jpeg_with_sof(sof: u8, segments: &[Vec<u8>]) -> Vec<u8> {
+    let mut out = vec![0xff, 0xd8];
+    for s in segments {
+        out.extend(s);
+    }
+    out.extend(segment(sof, &[8, 0x01, 0xe0, 0x02, 0x80, 3, 1, 0x22, 0, 2, 0x11, 1, 3, 0x11, 1]));
+    out.extend(&[0xff, 0xd9]);
+    out
+}
+
+fn build_jpeg(segments: &[Vec<u8>]) -> Vec<u8> {
+    build_jpeg_with_sof(0xc0, segments)
+}
+
+fn exif_segment(ifds: &[Vec<(u16, Value)>]) -> Vec<u8> {
+    let mut payload = b"Exif\0\0".to_vec();
+    payload.extend(build_tiff(ifds));
+    segment(0xe1, &payload)
+}
+
+const JPEG_DIM: Dimensions = Dimensions {
+    width: 640,
+    height: 480
+};
+
+#[test]
+fn test_jpeg_exif_gps() {
+    let data = build_jpeg(&[exif_segment(&[vec![
+        (0x010f, Value::Ascii("Canon")),
+        (0x8825, Value::Ifd(vec![
+            (0x0001, Value::Ascii("N")),
+            (0x0002, Value::Rational(vec![(55, 1), (45, 1), (1803, 100)])),
+            (0x0003, Value::Ascii("W")),
+            (0x0004, Value::Rational(vec![(37, 1), (37, 1), (1530, 100)])),
+            (0x0005, Value::Byte(vec![0])),
+            (0x0006, Value::Rational(vec![(1565, 10)]))
+        ]))
+    ]])]);
+
+    let md = Jpeg::load_from_buf(&data).unwrap();
+    assert_eq!(md.dimensions, JPEG_DIM);
+    assert_eq!(md.make, Some("Canon".to_owned()));
+
+    let gps = md.gps.expect("no GPS info");
+    assert!((gps.latitude - 55.755008).abs() < 1e-6);
+    assert!((gps.longitude - -37.620917).abs() < 1e-6);
+    assert_eq!(gps.altitude, Some(156.5));
+}
+
+#[test]
+fn test_jpeg_exif_gps_missing() {
+    let data = build_jpeg(&[exif_segment(&[vec![
+        (0x8825, Value::Ifd(vec![
+            (0x0001, Value::Ascii("S"))
+        ]))
+    ]])]);
+
+    let md = Jpeg::load_from_buf(&data).unwrap();
+    assert_eq!(md.gps, None);
+}
+
+#[test]
+fn test_jpeg_exif_exposure() {
+    let data = build_jpeg(&[exif_segment(&[vec![
+        (0x0110, Value::Ascii("EOS 5D")),
+        (0x8769, Value::Ifd(vec![
+            (0x829a, Value::Rational(vec![(1, 250)])),
+            (0x829d, Value::Rational(vec![(28, 10)])),
+            (0x8827, Value::Short(vec![400])),
+            (0x9204, Value::SignedRational(vec![(-2, 3)])),
+            (0x920a, Value::Rational(vec![(50, 1)]))
+        ]))
+    ]])]);
+
+    let md = Jpeg::load_from_buf(&data).unwrap();
+    assert_eq!(md.model, Some("EOS 5D".to_owned()));
+    assert_eq!(md.exposure_time, Some(Rational { numerator: 1, denominator: 250 }));
+    assert_eq!(md.exposure_time.unwrap().as_f64(), 0.004);
+    assert_eq!(md.f_number.unwrap().as_f64(), 2.8);
+    assert_eq!(md.iso_speed, Some(400));
+    assert_eq!(md.exposure_bias, Some(SignedRational { numerator: -2, denominator: 3 }));
+    assert_eq!(md.focal_length, Some(Rational { numerator: 50, denominator: 1 }));
+}
+
+#[test]
+fn test_rational() {
+    let r = Rational { numerator: 1, denominator: 8 };
+    assert_eq!(r.as_f64(), 0.125);
+    assert_eq!(r.to_string(), "1/8");
+
+    let r = SignedRational { numerator: -3, denominator: 2 };
+    assert_eq!(r.as_f64(), -1.5);
+    assert_eq!(r.to_string(), "-3/2");
+}
+
+#[test]
+fn test_jpeg_exif_multiple_values() {
+    let data = build_jpeg(&[exif_segment(&[vec![
+        (0x8769, Value::Ifd(vec![
+            (0x8827, Value::Short(vec![200, 400, 800]))
+        ]))
+    ]])]);
+
+    let md = Jpeg::load_from_buf(&data).unwrap();
+    assert_eq!(md.iso_speed, Some(200));
+}
+
+#[test]
+fn test_jpeg_exif_out_of_line_value() {
+    // value offsets are relative to the TIFF header, not to the file or the APP1 segment
+    let data = build_jpeg(&[
+        segment(0xe0, b"JFIF\0\x01\x01\x00\x00\x01\x00\x01\x00\x00"),
+        exif_segment(&[vec![
+            (0x010f, Value::Ascii("NIKON CORPORATION")),
+            (0x0110, Value::Ascii("D3"))
+        ]])
+    ]);
+
+    let md = Jpeg::load_from_buf(&data).unwrap();
+    assert_eq!(md.make, Some("NIKON CORPORATION".to_owned()));
+    assert_eq!(md.model, Some("D3".to_owned()));
+}
+
+#[test]
+fn test_jpeg_segment_size_too_small() {
+    for &size in &[0u8, 1] {
+        let data = [0xff, 0xd8, 0xff, 0xe0, 0x00, size, 0xff, 0xd9];
+        match Jpeg::load_from_buf(&data) {
+            Err(Error::InvalidFormat(_)) => {}
+            r => panic!("unexpected result: {:?}", r)
+        }
+    }
+}
+
+#[test]
+fn test_jpeg_jfif_density() {
+    let data = build_jpeg(&[segment(0xe0, b"JFIF\0\x01\x02\x01\x00\x48\x00\x48\x00\x00")]);
+
+    let md = Jpeg::load_from_buf(&data).unwrap();
+    assert_eq!(md.density, Some(jpeg::Density {
+        x: 72,
+        y: 72,
+        unit: jpeg::DensityUnit::DotsPerInch
+    }));
+}
+
+#[test]
+fn test_jpeg_coding() {
+    let codings = [
+        (0xc0, jpeg::Coding::Baseline),
+        (0xc1, jpeg::Coding::ExtendedSequential),
+        (0xc2, jpeg::Coding::Progressive),
+        (0xc3, jpeg::Coding::Lossless),
+        (0xc5, jpeg::Coding::DifferentialSequential),
+        (0xc6, jpeg::Coding::DifferentialProgressive),
+        (0xc7, jpeg::Coding::DifferentialLossless),
+        (0xc9, jpeg::Coding::ExtendedSequentialArithmetic),
+        (0xca, jpeg::Coding::ProgressiveArithmetic),
+        (0xcb, jpeg::Coding::LosslessArithmetic),
+        (0xcd, jpeg::Coding::DifferentialSequentialArithmetic),
+        (0xce, jpeg::Coding::DifferentialProgressiveArithmetic),
+        (0xcf, jpeg::Coding::DifferentialLosslessArithmetic)
+    ];
+
+    for &(sof, coding) in &codings {
+        let md = Jpeg::load_from_buf(&build_jpeg_with_sof(sof, &[])).unwrap();
+        assert_eq!(md.coding, coding);
+        assert_eq!(md.dimensions, JPEG_DIM);
+    }
+
+    // DHT, JPG and DAC markers are in the same range, but they are not frame headers
+    let not_frame_headers = [segment(0xc4, &[0; 17]), segment(0xc8, &[0; 9]), segment(0xcc, &[0, 0x10])];
+    let md = Jpeg::load_from_buf(&build_jpeg(&not_frame_headers)).unwrap();
+    assert_eq!(md.coding, jpeg::Coding::Baseline);
+    assert_eq!(md.dimensions, JPEG_DIM);
+
+    // sample files: owlet.jpg is baseline, progressive.jpg is a 16x16 grayscale image of
+    // four flat blocks encoded in two scans, DC and AC coefficients
+    let samples = [
+        ("tests/images/owlet.jpg", jpeg::Coding::Baseline, OWLET_DIM, 3),
+        ("tests/images/progressive.jpg", jpeg::Coding::Progressive, Dimensions { width: 16, height: 16 }, 1)
+    ];
+    for &(path, coding, dimensions, num_components) in &samples {
+        let md = Jpeg::load_from_file(path).unwrap();
+        assert_eq!(md.coding, coding, "{}", path);
+        assert_eq!(md.dimensions, dimensions, "{}", path);
+        assert_eq!(md.num_components, num_components, "{}", path);
+        assert_eq!(md.bits_per_sample, 8, "{}", path);
+    }
+
+    let data = read_file("tests/images/progressive.jpg");
+    let markers: Vec<_> = jpeg::segments(&data[..]).map(|s| s.unwrap().marker).collect();
+    assert_eq!(markers, vec![0xd8, 0xe0, 0xdb, 0xc2, 0xc4, 0xc4]);
+    assert_eq!(immeta::load_from_buf(&data).unwrap().format(), immeta::Format::Jpeg);
+}
+
+#[test]
+fn test_jpeg_grayscale() {
+    let mut data = vec![0xff, 0xd8];
+    data.extend(segment(0xc0, &[8, 0x01, 0xe0, 0x02, 0x80, 1, 1, 0x11, 0]));
+    data.extend(&[0xff, 0xd9]);
+
+    let md = Jpeg::load_from_buf(&data).unwrap();
+    assert_eq!(md.dimensions, JPEG_DIM);
+    assert_eq!(md.bits_per_sample, 8);
+    assert_eq!(md.num_components, 1);
+    assert!(md.is_grayscale());
+}
+
+fn adobe_segment(transform: u8) -> Vec<u8> {
+    segment(0xee, &[b'A', b'd', b'o', b'b', b'e', 0, 100, 0, 0, 0, 0, transform])
+}
+
+#[test]
+fn test_jpeg_color_space() {
+    // a 4-component CMYK image as written by Adobe software
+    let mut data = vec![0xff, 0xd8];
+    data.extend(adobe_segment(0));
+    data.extend(segment(0xc0, &[8, 0x01, 0xe0, 0x02, 0x80, 4, 1, 0x11, 0, 2, 0x11, 0, 3, 0x11, 0, 4, 0x11, 0]));
+    data.extend(&[0xff, 0xd9]);
+
+    let md = Jpeg::load_from_buf(&data).unwrap();
+    assert_eq!(md.num_components, 4);
+    assert_eq!(md.adobe_transform, Some(0));
+    assert_eq!(md.color_space(), jpeg::ColorSpace::Cmyk);
+
+    // SOI, APP14 marker and size precede the payload whose last byte is the transform
+    data[2 + 2 + 2 + 11] = 2;
+    let md = Jpeg::load_from_buf(&data).unwrap();
+    assert_eq!(md.adobe_transform, Some(2));
+    assert_eq!(md.color_space(), jpeg::ColorSpace::Ycck);
+
+    let md = Jpeg::load_from_buf(&build_jpeg(&[adobe_segment(0)])).unwrap();
+    assert_eq!(md.color_space(), jpeg::ColorSpace::Rgb);
+
+    let md = Jpeg::load_from_buf(&build_jpeg(&[])).unwrap();
+    assert_eq!(md.adobe_transform, None);
+    assert_eq!(md.color_space(), jpeg::ColorSpace::YCbCr);
+}
+
+fn iptc_dataset(dataset: u8, value: &[u8]) -> Vec<u8> {
+    let mut data = vec![0x1c, 2, dataset];
+    put_u16(&mut data, value.len() as u16);
+    data.extend_from_slice(value);
+    data
+}
+
+fn photoshop_resource(id: u16, name: &[u8], value: &[u8]) -> Vec<u8> {
+    let mut data = b"8BIM".to_vec();
+    put_u16(&mut data, id);
+    data.push(name.len() as u8);
+    data.extend_from_slice(name);
+    if name.len() & 1 == 0 {
+        data.push(0);
+    }
+    put_u32(&mut data, value.len() as u32);
+    data.extend_from_slice(value);
+    if value.len() & 1 == 1 {
+        data.push(0);
+    }
+    data
+}
+
+#[test]
+fn test_jpeg_iptc() {
+    let mut iptc = vec![0x1c, 1, 90, 0, 3, 0x1b, b'%', b'G'];
+    iptc.extend(iptc_dataset(25, b"owl"));
+    iptc.extend(iptc_dataset(25, "b\u{fc}ro".as_bytes()));
+    iptc.extend(iptc_dataset(120, b"An owlet in the forest"));
+    iptc.extend(iptc_dataset(80, b"Jane Doe"));
+    iptc.extend(iptc_dataset(110, b"Caf\xe9 Photos"));
+
+    let mut resources = photoshop_resource(0x03ed, b"", &[0; 16]);
+    resources.extend(photoshop_resource(0x0404, b"IPTC", &iptc));
+    let mut data = b"Photoshop 3.0\0".to_vec();
+    data.extend_from_slice(&resources[..20]);
+    let mut continuation = b"Photoshop 3.0\0".to_vec();
+    continuation.extend_from_slice(&resources[20..]);
+
+    let md = Jpeg::load_from_buf(&build_jpeg(&[segment(0xed, &data), segment(0xed, &continuation)])).unwrap();
+    assert_eq!(md.iptc, Some(jpeg::Iptc {
+        keywords: vec!["owl".to_owned(), "b\u{fc}ro".to_owned()],
+        caption: Some("An owlet in the forest".to_owned()),
+        byline: Some("Jane Doe".to_owned()),
+        credit: Some("Caf\u{e9} Photos".to_owned())
+    }));
+
+    let md = Jpeg::load_from_buf(&build_jpeg(&[segment(0xed, b"Photoshop 3.0\0")])).unwrap();
+    assert_eq!(md.iptc, None);
+
+    let md = Jpeg::load_from_buf(&build_jpeg(&[])).unwrap();
+    assert_eq!(md.iptc, None);
+}
+
+#[test]
+fn test_jpeg_exif_thumbnail() {
+    let thumbnail = build_jpeg(&[]);
+    let data = build_jpeg(&[exif_segment(&[
+        vec![
+            (0x0112, Value::Short(vec![1]))
+        ],
+        vec![
+            (0x0103, Value::Short(vec![6])),
+            (0x0201, Value::Offset(thumbnail.clone())),
+            (0x0202, Value::Long(vec![thumbnail.len() as u32]))
+        ]
+    ])]);
+
+    let md = Jpeg::load_from_buf(&data).unwrap();
+    let t = md.thumbnail().expect("no thumbnail");
+    assert_eq!(&t[..2], &[0xff, 0xd8]);
+    assert_eq!(t, &thumbnail[..]);
+
+    let md = Jpeg::load_from_buf(&build_jpeg(&[])).unwrap();
+    assert!(md.thumbnail().is_none());
+}
+
+#[test]
+fn test_jpeg_load_with_options() {
+    let thumbnail = build_jpeg(&[]);
+    let mut xmp = b"http://ns.adobe.com/xap/1.0/\0".to_vec();
+    xmp.extend(b"<x:xmpmeta/>");
+    let data = build_jpeg(&[
+        exif_segment(&[
+            vec![
+                (0x0112, Value::Short(vec![6])),
+                (0x8825, Value::Ifd(vec![
+                    (0x0001, Value::Ascii("N")),
+                    (0x0002, Value::Rational(vec![(55, 1), (45, 1), (0, 1)])),
+                    (0x0003, Value::Ascii("E")),
+                    (0x0004, Value::Rational(vec![(37, 1), (37, 1), (0, 1)]))
+                ]))
+            ],
+            vec![
+                (0x0201, Value::Offset(thumbnail.clone())),
+                (0x0202, Value::Long(vec![thumbnail.len() as u32]))
+            ]
+        ]),
+        segment(0xe1, &xmp)
+    ]);
+
+    let md = jpeg::Metadata::load_with_options(&mut &data[..], jpeg::Options::default()).unwrap();
+    assert_eq!(md, Jpeg::load_from_buf(&data).unwrap());
+    assert_eq!(md.orientation, Some(jpeg::Orientation::Rotate90));
+    assert!(md.gps.is_some());
+    assert!(md.thumbnail().is_some());
+    assert!(md.has_xmp);
+
+    let options = jpeg::Options { parse_exif: false, ..jpeg::Options::default() };
+    let md = jpeg::Metadata::load_with_options(&mut &data[..], options).unwrap();
+    assert_eq!(md.dimensions, JPEG_DIM);
+    // the segment is still detected
+    assert!(md.has_exif);
+    assert!(md.exif().is_none());
+    assert_eq!(md.orientation, None);
+    assert_eq!(md.oriented_dimensions(), JPEG_DIM);
+    assert!(md.gps.is_none());
+    assert!(md.thumbnail().is_none());
+    assert!(md.has_xmp);
+
+    let options = jpeg::Options { parse_gps: false, parse_thumbnail: false, parse_xmp: false, ..jpeg::Options::default() };
+    let md = jpeg::Metadata::load_with_options(&mut &data[..], options).unwrap();
+    assert_eq!(md.orientation, Some(jpeg::Orientation::Rotate90));
+    assert!(md.gps.is_none());
+    assert!(md.thumbnail().is_none());
+    assert!(md.has_xmp);
+    assert_eq!(md.xmp, None);
+
+    // APP1 segments are not read at all
+    let options = jpeg::Options { parse_exif: false, parse_xmp: false, ..jpeg::Options::default() };
+    let md = jpeg::Metadata::load_with_options(&mut &data[..], options).unwrap();
+    assert_eq!(md.dimensions, JPEG_DIM);
+    assert!(!md.has_exif);
+    assert!(!md.has_xmp);
+}
+
+#[test]
+fn test_generic_thumbnail() {
+    // an 80x60 grayscale image
+    let mut thumbnail = vec![0xff, 0xd8];
+    thumbnail.extend(segment(0xc0, &[8, 0, 60, 0, 80, 1, 1, 0x11, 0]));
+    thumbnail.extend(&[0xff, 0xd9]);
+    let data = build_jpeg(&[exif_segment(&[
+        vec![(0x0112, Value::Short(vec![1]))],
+        vec![
+            (0x0201, Value::Offset(thumbnail.clone())),
+            (0x0202, Value::Long(vec![thumbnail.len() as u32]))
+        ]
+    ])]);
+
+    let t = immeta::load_from_buf(&data).unwrap().thumbnail().expect("no thumbnail");
+    assert_eq!(t, immeta::Thumbnail {
+        format: immeta::Format::Jpeg,
+        dimensions: Some(Dimensions { width: 80, height: 60 }),
+        data: thumbnail
+    });
+
+    assert!(immeta::load_from_buf(&build_jpeg(&[])).unwrap().thumbnail().is_none());
+    assert!(immeta::load_from_buf(&build_png(&[])).unwrap().thumbnail().is_none());
+}
+
+/// A reader which fails on every read.
+struct FailingReader;
+
+impl Read for FailingReader {
+    fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+        Err(io::Error::new(io::ErrorKind::PermissionDenied, "read failed"))
+    }
+}
+
+#[test]
+fn test_error_source() {
+    use std::error::Error as StdError;
+
+    let e = Jpeg::load(&mut FailingReader).unwrap_err();
+    match e {
+        Error::Io(_) => {}
+        ref e => panic!("unexpected error: {}", e)
+    }
+    assert_eq!(e.to_string(), "I/O error: read failed");
+    let source = e.source().unwrap().downcast_ref::<io::Error>().unwrap();
+    assert_eq!(source.kind(), io::ErrorKind::PermissionDenied);
+
+    let boxed: Box<dyn StdError> = Box::new(e);
+    assert!(boxed.source().is_some());
+
+    let e = Png::load_from_buf(b"not a png").unwrap_err();
+    assert!(e.source().is_none());
+}
+
+#[test]
+fn test_orientation_conversions() {
+    for n in 1..9 {
+        assert_eq!(jpeg::Orientation::from_exif(n).unwrap().to_exif(), n);
+    }
+    assert_eq!(jpeg::Orientation::from_exif(0), None);
+    assert_eq!(jpeg::Orientation::from_exif(9), None);
+
+    // the types are shared by all formats and are re-exported from the jpeg module
+    assert_eq!(jpeg::Orientation::Rotate90, immeta::Orientation::Rotate90);
+    assert_eq!(jpeg::Rotation::Rotate180, immeta::Rotation::Rotate180);
+
+    assert_eq!(jpeg::Orientation::Rotate90.steps(), (jpeg::Rotation::Rotate90, false));
+    assert_eq!(jpeg::Orientation::Transpose.steps(), (jpeg::Rotation::Rotate270, true));
+
+    // the matrix must agree with mirroring followed by rotation
+    let apply = |m: [f32; 6], (x, y): (f32, f32)| (m[0]*x + m[2]*y + m[4], m[1]*x + m[3]*y + m[5]);
+    for n in 1..9 {
+        let o = jpeg::Orientation::from_exif(n).unwrap();
+        let (rotation, mirror) = o.steps();
+        for &p in &[(0.0, 0.0), (1.0, 0.0), (0.25, 0.75)] {
+            let (x, y) = if mirror { (1.0 - p.0, p.1) } else { p };
+            let expected = match rotation {
+                jpeg::Rotation::None => (x, y),
+                jpeg::Rotation::Rotate90 => (1.0 - y, x),
+                jpeg::Rotation::Rotate180 => (1.0 - x, 1.0 - y),
+                jpeg::Rotation::Rotate270 => (y, 1.0 - x)
+            };
+            assert_eq!(apply(o.transform_matrix(), p), expected, "{}", o);
+        }
+    }
+}
+
+#[test]
+fn test_dimensions_helpers() {
+    let d = Dimensions { width: 640, height: 480 };
+    assert_eq!(d.aspect_ratio(), 640.0 / 480.0);
+    assert_eq!(d.area(), 307200);
+    assert_eq!(d.rotate90(), Dimensions { width: 480, height: 640 });
+    assert_eq!(d.rotate90().rotate90(), d);
+    assert_eq!(Dimensions { width: 100000, height: 100000 }.area(), 10000000000);
+
+    // landscape image into a square box
+    assert_eq!(d.scaled_to_fit(Dimensions { width: 100, height: 100 }),
+               Dimensions { width: 100, height: 75 });
+    // portrait image into a square box
+    assert_eq!(Dimensions { width: 480, height: 640 }.scaled_to_fit(Dimensions { width: 100, height: 100 }),
+               Dimensions { width: 75, height: 100 });
+    // scaling up, limited by height
+    assert_eq!(d.scaled_to_fit(Dimensions { width: 2000, height: 960 }),
+               Dimensions { width: 1280, height: 960 });
+    // rounding of the scaled side
+    assert_eq!(Dimensions { width: 3, height: 2 }.scaled_to_fit(Dimensions { width: 10, height: 10 }),
+               Dimensions { width: 10, height: 7 });
+    // extreme sizes don't overflow
+    let huge = Dimensions { width: u32::MAX, height: u32::MAX };
+    assert_eq!(Dimensions { width: u32::MAX, height: u32::MAX - 1 }.scaled_to_fit(huge),
+               Dimensions { width: u32::MAX, height: u32::MAX - 1 });
+    assert_eq!(Dimensions { width: u32::MAX - 1, height: u32::MAX }.scaled_to_fit(huge),
+               Dimensions { width: u32::MAX - 1, height: u32::MAX });
+    assert_eq!(Dimensions { width: 1, height: u32::MAX }.scaled_to_fit(Dimensions { width: u32::MAX, height: 1 }),
+               Dimensions { width: 0, height: 1 });
+    assert_eq!(Dimensions { width: 5, height: 0 }.scaled_to_fit(Dimensions { width: 0, height: 0 }),
+               Dimensions { width: 0, height: 0 });
+}
+
+#[test]
+fn test_dimensions_pixel_cmp() {
+    use std::cmp::Ordering;
+
+    let small = Dimensions { width: 320, height: 240 };
+    let large = Dimensions { width: 640, height: 480 };
+    let tall = Dimensions { width: 480, height: 640 };
+    let strip = Dimensions { width: 76800, height: 4 };
+
+    assert_eq!(small.pixel_cmp(&large), Ordering::Less);
+    assert_eq!(large.pixel_cmp(&small), Ordering::Greater);
+    // equal area, different shape
+    assert_eq!(large.pixel_cmp(&tall), Ordering::Equal);
+    assert_eq!(small.pixel_cmp(&strip), Ordering::Less);
+
+    assert_eq!(immeta::max_by_pixels(vec![small, large, tall]), Some(large));
+    assert_eq!(immeta::max_by_pixels(vec![small, tall, large]), Some(tall));
+    assert_eq!(immeta::max_by_pixels(vec![small]), Some(small));
+    assert_eq!(immeta::max_by_pixels(vec![]), None);
+}
+
+#[test]
+fn test_date_time_parse() {
+    let expected = DateTime { year: 2015, month: 10, day: 9, hour: 17, minute: 4, second: 59, nanosecond: 0 };
+    assert_eq!(DateTime::parse("2015:10:09 17:04:59").unwrap(), expected);
+    assert_eq!(DateTime::parse("2015:10:09 17:04:59\0").unwrap(), expected);
+
+    for s in &["2015-10-09 17:04:59", "2015:13:09 17:04:59", "    :  :     :  :  ", "2015:10:09"] {
+        match DateTime::parse(s) {
+            Err(Error::InvalidFormat(_)) => {}
+            r => panic!("unexpected result for {:?}: {:?}", s, r)
+        }
+    }
+}
+
+#[test]
+fn test_jpeg_exif_date_time() {
+    let data = build_jpeg(&[exif_segment(&[vec![
+        (0x0132, Value::Ascii("2015:10:09 17:04:59"))
+    ]])]);
+
+    let md = Jpeg::load_from_buf(&data).unwrap();
+    assert_eq!(md.date_time_raw, Some("2015:10:09 17:04:59".to_owned()));
+    assert_eq!(md.date_time, Some(DateTime { year: 2015, month: 10, day: 9, hour: 17, minute: 4, second: 59, nanosecond: 0 }));
+    assert_eq!(md.date_time_original, None);
+    assert_eq!(md.date_time_digitized, None);
+}
+
+#[test]
+fn test_jpeg_exif_date_time_original() {
+    let data = build_jpeg(&[exif_segment(&[vec![
+        (0x0132, Value::Ascii("2015:10:09 17:04:59")),
+        (0x8769, Value::Ifd(vec![
+            (0x9003, Value::Ascii("2015:08:01 06:30:00")),
+            (0x9004, Value::Ascii("2015:08:02 10:00:00"))
+        ]))
+    ]])]);
+
+    let md = Jpeg::load_from_buf(&data).unwrap();
+    assert_eq!(md.date_time_original, Some(DateTime { year: 2015, month: 8, day: 1, hour: 6, minute: 30, second: 0, nanosecond: 0 }));
+    assert_eq!(md.date_time_digitized, Some(DateTime { year: 2015, month: 8, day: 2, hour: 10, minute: 0, second: 0, nanosecond: 0 }));
+    assert!(md.date_time_original != md.date_time);
+
+    // invalid values are ignored
+    let data = build_jpeg(&[exif_segment(&[vec![
+        (0x8769, Value::Ifd(vec![(0x9003, Value::Ascii("    :  :     :  :  "))]))
+    ]])]);
+    assert_eq!(Jpeg::load_from_buf(&data).unwrap().date_time_original, None);
+}
+
+#[test]
+fn test_jpeg_exif_version() {
+    let data = build_jpeg(&[exif_segment(&[vec![
+        (0x8769, Value::Ifd(vec![
+            (0x9000, Value::Undefined(b"0230".to_vec())),
+            (0xa000, Value::Undefined(b"0100".to_vec()))
+        ]))
+    ]])]);
+    let md = Jpeg::load_from_buf(&data).unwrap();
+    assert_eq!(md.exif_version, Some("2.30".to_owned()));
+    assert_eq!(md.flashpix_version, Some("1.00".to_owned()));
+
+    // malformed values are ignored
+    let data = build_jpeg(&[exif_segment(&[vec![
+        (0x8769, Value::Ifd(vec![(0x9000, Value::Undefined(b"2.3".to_vec()))]))
+    ]])]);
+    let md = Jpeg::load_from_buf(&data).unwrap();
+    assert_eq!(md.exif_version, None);
+    assert_eq!(md.flashpix_version, None);
+}
+
+#[test]
+fn test_jpeg_exif_dimensions() {
+    let md = Jpeg::load_from_buf(&build_jpeg(&[exif_segment(&[vec![
+        (0x8769, Value::Ifd(vec![(0xa002, Value::Short(vec![640])), (0xa003, Value::Long(vec![480]))]))
+    ]])])).unwrap();
+    assert_eq!(md.exif_dimensions, Some(JPEG_DIM));
+    assert!(md.dimensions_consistent());
+
+    // the image was cropped without updating EXIF
+    let md = Jpeg::load_from_buf(&build_jpeg(&[exif_segment(&[vec![
+        (0x8769, Value::Ifd(vec![(0xa002, Value::Long(vec![4000])), (0xa003, Value::Long(vec![3000]))]))
+    ]])])).unwrap();
+    assert_eq!(md.exif_dimensions, Some(Dimensions { width: 4000, height: 3000 }));
+    assert_eq!(md.dimensions, JPEG_DIM);
+    assert!(!md.dimensions_consistent());
+
+    let md = Jpeg::load_from_buf(&build_jpeg(&[exif_segment(&[vec![
+        (0x8769, Value::Ifd(vec![(0xa002, Value::Long(vec![4000]))]))
+    ]])])).unwrap();
+    assert_eq!(md.exif_dimensions, None);
+    assert!(md.dimensions_consistent());
+}
+
+#[test]
+fn test_jpeg_exif_modes() {
+    let load = |tags: Vec<(u16, Value)>| {
+        Jpeg::load_from_buf(&build_jpeg(&[exif_segment(&[vec![(0x8769, Value::Ifd(tags))]])])).unwrap()
+    };
+
+    let md = load(vec![
+        (0x8822, Value::Short(vec![3])),
+        (0x9207, Value::Short(vec![5])),
+        (0x9209, Value::Short(vec![0x59])),
+        (0xa403, Value::Short(vec![1]))
+    ]);
+    assert_eq!(md.exposure_program, Some(jpeg::ExposureProgram::AperturePriority));
+    assert_eq!(md.metering_mode, Some(jpeg::MeteringMode::Pattern));
+    assert_eq!(md.white_balance, Some(jpeg::WhiteBalance::Manual));
+    // fired in auto mode with red-eye reduction
+    assert_eq!(md.flash, Some(jpeg::Flash {
+        fired: true,
+        return_detected: None,
+        mode: jpeg::FlashMode::Auto,
+        has_flash_function: true,
+        red_eye_reduction: true,
+        raw: 0x59
+    }));
+
+    let md = load(vec![
+        (0x8822, Value::Short(vec![0])),
+        (0x9207, Value::Short(vec![255])),
+        (0x9209, Value::Short(vec![0x10])),
+        (0xa403, Value::Short(vec![0]))
+    ]);
+    assert_eq!(md.exposure_program, Some(jpeg::ExposureProgram::NotDefined));
+    assert_eq!(md.metering_mode, Some(jpeg::MeteringMode::Other));
+    assert_eq!(md.white_balance, Some(jpeg::WhiteBalance::Auto));
+    let flash = md.flash.unwrap();
+    assert!(!flash.fired);
+    assert_eq!(flash.mode, jpeg::FlashMode::Off);
+
+    // return light detected; a camera without a flash
+    assert_eq!(load(vec![(0x9209, Value::Short(vec![0x0f]))]).flash.unwrap().return_detected, Some(true));
+    assert_eq!(load(vec![(0x9209, Value::Short(vec![0x05]))]).flash.unwrap().return_detected, Some(false));
+    assert!(!load(vec![(0x9209, Value::Short(vec![0x20]))]).flash.unwrap().has_flash_function);
+
+    // values which are not defined by the standard
+    let md = load(vec![
+        (0x8822, Value::Short(vec![9])),
+        (0x9207, Value::Short(vec![0])),
+        (0xa403, Value::Short(vec![7]))
+    ]);
+    assert_eq!(md.exposure_program, Some(jpeg::ExposureProgram::Unknown(9)));
+    assert_eq!(md.metering_mode, Some(jpeg::MeteringMode::Unknown(0)));
+    assert_eq!(md.white_balance, Some(jpeg::WhiteBalance::Unknown(7)));
+    assert_eq!(md.flash, None);
+}
+
+#[test]
+fn test_jpeg_exif_sub_sec_time() {
+    let burst = |sub_sec: &'static str| build_jpeg(&[exif_segment(&[vec![
+        (0x0132, Value::Ascii("2015:10:09 17:04:59")),
+        (0x8769, Value::Ifd(vec![
+            (0x9003, Value::Ascii("2015:08:01 06:30:00")),
+            (0x9004, Value::Ascii("2015:08:01 06:30:00")),
+            (0x9290, Value::Ascii("5")),
+            (0x9291, Value::Ascii(sub_sec))
+        ]))
+    ]])]);
+
+    let first = Jpeg::load_from_buf(&burst("25")).unwrap();
+    assert_eq!(first.date_time_original,
+               Some(DateTime { year: 2015, month: 8, day: 1, hour: 6, minute: 30, second: 0, nanosecond: 250_000_000 }));
+    assert_eq!(first.date_time.unwrap().nanosecond, 500_000_000);
+    assert_eq!(first.date_time_digitized.unwrap().nanosecond, 0);
+
+    let second = Jpeg::load_from_buf(&burst("750")).unwrap();
+    assert_eq!(second.date_time_original.unwrap().nanosecond, 750_000_000);
+    assert!(first.date_time_original < second.date_time_original);
+
+    assert_eq!(Jpeg::load_from_buf(&burst("1234567891")).unwrap().date_time_original.unwrap().nanosecond,
+               123_456_789);
+    assert_eq!(Jpeg::load_from_buf(&burst("12 ")).unwrap().date_time_original.unwrap().nanosecond,
+               120_000_000);
+    // invalid values are ignored
+    assert_eq!(Jpeg::load_from_buf(&burst("x1")).unwrap().date_time_original.unwrap().nanosecond, 0);
+}
+
+#[test]
+fn test_date_time_order() {
+    let dt = DateTime::parse("2015:10:09 17:04:59").unwrap();
+    assert!(dt < DateTime { nanosecond: 1, ..dt });
+    assert!(DateTime { nanosecond: 999_999_999, ..dt } < DateTime::parse("2015:10:09 17:05:00").unwrap());
+    assert!(DateTime { nanosecond: 999_999_999, ..dt } < DateTime::parse("2016:01:01 00:00:00").unwrap());
+}
+
+#[test]
+fn test_jpeg_oriented_dimensions() {
+    let rotated = Dimensions { width: 480, height: 640 };
+    let orientations = [
+        (1, jpeg::Orientation::Normal, JPEG_DIM),
+        (2, jpeg::Orientation::MirrorHorizontal, JPEG_DIM),
+        (3, jpeg::Orientation::Rotate180, JPEG_DIM),
+        (4, jpeg::Orientation::MirrorVertical, JPEG_DIM),
+        (5, jpeg::Orientation::Transpose, rotated),
+        (6, jpeg::Orientation::Rotate90, rotated),
+        (7, jpeg::Orientation::Transverse, rotated),
+        (8, jpeg::Orientation::Rotate270, rotated)
+    ];
+
+    for &(n, orientation, dimensions) in &orientations {
+        let data = build_jpeg(&[exif_segment(&[vec![(0x0112, Value::Short(vec![n]))]])]);
+        let md = Jpeg::load_from_buf(&data).unwrap();
+        assert_eq!(md.orientation, Some(orientation));
+        assert_eq!(md.dimensions, JPEG_DIM);
+        assert_eq!(md.oriented_dimensions(), dimensions);
+    }
+
+    let md = Jpeg::load_from_buf(&build_jpeg(&[])).unwrap();
+    assert_eq!(md.orientation, None);
+    assert_eq!(md.oriented_dimensions(), JPEG_DIM);
+}
+
+#[test]
+fn test_jpeg_orientation_as_long() {
+    let data = build_jpeg(&[exif_segment(&[vec![
+        (0x0112, Value::Long(vec![6])),
+        (0x8769, Value::Ifd(vec![(0x8827, Value::Long(vec![400]))]))
+    ]])]);
+    let md = Jpeg::load_from_buf(&data).unwrap();
+    assert_eq!(md.orientation, Some(jpeg::Orientation::Rotate90));
+    assert_eq!(md.iso_speed, Some(400));
+
+    // values of an unexpected datatype or out of range are ignored
+    let data = build_jpeg(&[exif_segment(&[vec![
+        (0x010f, Value::Ascii("Canon")),
+        (0x0112, Value::Long(vec![0x10006])),
+        (0x8769, Value::Ifd(vec![(0x8827, Value::Rational(vec![(400, 1)]))]))
+    ]])]);
+    let md = Jpeg::load_from_buf(&data).unwrap();
+    assert_eq!(md.make, Some("Canon".to_owned()));
+    assert_eq!(md.orientation, None);
+    assert_eq!(md.iso_speed, None);
+}
+
+#[test]
+fn test_jpeg_orientation_fallback() {
+    // orientation only in IFD1
+    let data = build_jpeg(&[exif_segment(&[
+        vec![(0x010f, Value::Ascii("Canon"))],
+        vec![(0x0112, Value::Short(vec![8]))]
+    ])]);
+    let md = Jpeg::load_from_buf(&data).unwrap();
+    assert_eq!(md.make, Some("Canon".to_owned()));
+    assert_eq!(md.orientation, Some(jpeg::Orientation::Rotate270));
+
+    // orientation in the Exif sub-IFD is preferred to IFD1
+    let data = build_jpeg(&[exif_segment(&[
+        vec![(0x8769, Value::Ifd(vec![(0x0112, Value::Short(vec![3]))]))],
+        vec![(0x0112, Value::Short(vec![8]))]
+    ])]);
+    let md = Jpeg::load_from_buf(&data).unwrap();
+    assert_eq!(md.orientation, Some(jpeg::Orientation::Rotate180));
+
+    // orientation in IFD0 is preferred to all other IFDs
+    let data = build_jpeg(&[exif_segment(&[
+        vec![(0x0112, Value::Short(vec![6]))],
+        vec![(0x0112, Value::Short(vec![8]))]
+    ])]);
+    let md = Jpeg::load_from_buf(&data).unwrap();
+    assert_eq!(md.orientation, Some(jpeg::Orientation::Rotate90));
+}
+
+#[test]
+fn test_jpeg_exif_resolution() {
+    let dpi = Rational { numerator: 300, denominator: 1 };
+    let data = build_jpeg(&[exif_segment(&[vec![
+        (0x011a, Value::Rational(vec![(300, 1)])),
+        (0x011b, Value::Rational(vec![(300, 1)])),
+        (0x0128, Value::Short(vec![2]))
+    ]])]);
+    let md = Jpeg::load_from_buf(&data).unwrap();
+    assert_eq!(md.resolution, Some(jpeg::Resolution { x: dpi, y: dpi, unit: jpeg::ResolutionUnit::Inch }));
+
+    // inches are assumed without the unit tag
+    let data = build_jpeg(&[exif_segment(&[vec![
+        (0x011a, Value::Rational(vec![(300, 1)])),
+        (0x011b, Value::Rational(vec![(300, 1)]))
+    ]])]);
+    let md = Jpeg::load_from_buf(&data).unwrap();
+    assert_eq!(md.resolution.unwrap().unit, jpeg::ResolutionUnit::Inch);
+
+    let data = build_jpeg(&[exif_segment(&[vec![
+        (0x011a, Value::Rational(vec![(118, 1)])),
+        (0x011b, Value::Rational(vec![(59, 1)])),
+        (0x0128, Value::Short(vec![3]))
+    ]])]);
+    let md = Jpeg::load_from_buf(&data).unwrap();
+    assert_eq!(md.resolution, Some(jpeg::Resolution {
+        x: Rational { numerator: 118, denominator: 1 },
+        y: Rational { numerator: 59, denominator: 1 },
+        unit: jpeg::ResolutionUnit::Centimeter
+    }));
+
+    // the unit may also be stored as a LONG
+    let data = build_jpeg(&[exif_segment(&[vec![
+        (0x011a, Value::Rational(vec![(118, 1)])),
+        (0x011b, Value::Rational(vec![(118, 1)])),
+        (0x0128, Value::Long(vec![3]))
+    ]])]);
+    let md = Jpeg::load_from_buf(&data).unwrap();
+    assert_eq!(md.resolution.unwrap().unit, jpeg::ResolutionUnit::Centimeter);
+
+    // both values are required
+    let data = build_jpeg(&[exif_segment(&[vec![(0x011a, Value::Rational(vec![(300, 1)]))]])]);
+    let md = Jpeg::load_from_buf(&data).unwrap();
+    assert_eq!(md.resolution, None);
+}
+
+#[test]
+fn test_jpeg_attribution() {
+    let data = build_jpeg(&[exif_segment(&[vec![
+        (0x0131, Value::Ascii("Owl Editor 1.0")),
+        (0x013b, Value::Ascii("Jane Doe")),
+        (0x8298, Value::Ascii("Copyright 2015 Jane Doe"))
+    ]])]);
+    let md = Jpeg::load_from_buf(&data).unwrap();
+    assert_eq!(md.software, Some("Owl Editor 1.0".to_owned()));
+    assert_eq!(md.artist, Some("Jane Doe".to_owned()));
+    assert_eq!(md.copyright, Some(jpeg::Copyright {
+        photographer: Some("Copyright 2015 Jane Doe".to_owned()),
+        editor: None
+    }));
+
+    let copyright = |value| {
+        let data = build_jpeg(&[exif_segment(&[vec![(0x8298, Value::Ascii(value))]])]);
+        Jpeg::load_from_buf(&data).unwrap().copyright
+    };
+    assert_eq!(copyright("Jane Doe\0Owl Edits"), Some(jpeg::Copyright {
+        photographer: Some("Jane Doe".to_owned()),
+        editor: Some("Owl Edits".to_owned())
+    }));
+    // editor copyright only
+    assert_eq!(copyright(" \0Owl Edits"), Some(jpeg::Copyright {
+        photographer: None,
+        editor: Some("Owl Edits".to_owned())
+    }));
+    assert_eq!(copyright(""), None);
+
+    let md = Jpeg::load_from_buf(&build_jpeg(&[])).unwrap();
+    assert_eq!((md.software, md.artist, md.copyright), (None, None, None));
+}
+
+#[test]
+fn test_jpeg_maker_note() {
+    let note = b"Nikon\0\x02\x10\x00\x00MM\x00\x2a\x00\x00\x00\x08\xff\xff".to_vec();
+    let data = build_jpeg(&[exif_segment(&[vec![
+        (0x8769, Value::Ifd(vec![
+            (0x829a, Value::Rational(vec![(1, 250)])),
+            (0x927c, Value::Undefined(note.clone()))
+        ]))
+    ]])]);
+
+    let md = Jpeg::load_from_buf(&data).unwrap();
+    assert_eq!(md.maker_note, Some(note));
+    assert_eq!(md.exposure_time, Some(Rational { numerator: 1, denominator: 250 }));
+
+    let md = Jpeg::load_from_buf(&build_jpeg(&[])).unwrap();
+    assert_eq!(md.maker_note, None);
+}
+
+#[test]
+fn test_jpeg_user_comment() {
+    let user_comment = |value: Vec<u8>| {
+        let data = build_jpeg(&[exif_segment(&[vec![
+            (0x8769, Value::Ifd(vec![(0x9286, Value::Undefined(value))]))
+        ]])]);
+        Jpeg::load_from_buf(&data).unwrap().user_comment
+    };
+
+    assert_eq!(user_comment(b"ASCII\0\0\0Owls at dusk".to_vec()), Some("Owls at dusk".to_owned()));
+    assert_eq!(user_comment(b"\0\0\0\0\0\0\0\0caf\xc3\xa9    ".to_vec()), Some("caf\u{e9}".to_owned()));
+    assert_eq!(user_comment(b"ASCII\0\0\0caf\xe9".to_vec()), Some("caf\u{fffd}".to_owned()));
+
+    // EXIF data is big-endian in tests
+    let mut unicode = b"UNICODE\0".to_vec();
+    for unit in "Сова \u{1f989}".encode_utf16() {
+        unicode.extend(&[(unit >> 8) as u8, unit as u8]);
+    }
+    unicode.extend(&[0, 0]);
+    assert_eq!(user_comment(unicode), Some("Сова \u{1f989}".to_owned()));
+
+    assert_eq!(user_comment(b"JIS\0\0\0\0\0\x30\x21".to_vec()), None);
+    assert_eq!(user_comment(b"ASCII\0\0\0        ".to_vec()), None);
+    assert_eq!(user_comment(b"ASCII".to_vec()), None);
+}
+
+#[test]
+fn test_jpeg_loadable_metadata_load_from_buf() {
+    use immeta::LoadableMetadata;
+
+    let data = build_jpeg(&[segment(0xfe, b"from a buffer")]);
+    let md = jpeg::Metadata::load_from_buf(&data).unwrap();
+    assert_eq!(md.dimensions, JPEG_DIM);
+    assert_eq!(md.comments, vec!["from a buffer".to_owned()]);
+
+    assert!(jpeg::Metadata::load_from_buf(&data[..10]).is_err());
+}
+
+#[test]
+fn test_jpeg_comments() {
+    let data = build_jpeg(&[
+        segment(0xfe, b"Photo by Owl"),
+        segment(0xfe, b"caf\xe9")
+    ]);
+
+    let md = Jpeg::load_from_buf(&data).unwrap();
+    assert_eq!(md.comments, vec!["Photo by Owl".to_owned(), "caf\u{fffd}".to_owned()]);
+
+    let md = Jpeg::load_from_buf(&build_jpeg(&[])).unwrap();
+    assert!(md.comments.is_empty());
+}
+
+fn icc_segment(seq_no: u8, count: u8, data: &[u8]) -> Vec<u8> {
+    let mut payload = b"ICC_PROFILE\0".to_vec();
+    payload.push(seq_no);
+    payload.push(count);
+    payload.extend(data);
+    segment(0xe2, &payload)
+}
+
+#[test]
+fn test_jpeg_icc_profile() {
+    let data = build_jpeg(&[
+        icc_segment(1, 2, b"first chunk, "),
+        segment(0xe2, b"unrelated APP2"),
+        icc_segment(2, 2, b"second chunk")
+    ]);
+    let md = Jpeg::load_from_buf(&data).unwrap();
+    assert_eq!(md.icc_profile, Some(b"first chunk, second chunk".to_vec()));
+
+    let md = Jpeg::load_from_buf(&build_jpeg(&[])).unwrap();
+    assert_eq!(md.icc_profile, None);
+}
+
+#[test]
+fn test_jpeg_icc_profile_invalid_chunks() {
+    // missing chunk
+    let data = build_jpeg(&[icc_segment(1, 2, b"first")]);
+    assert_eq!(Jpeg::load_from_buf(&data).unwrap().icc_profile, None);
+
+    // out of order
+    let data = build_jpeg(&[icc_segment(2, 2, b"second"), icc_segment(1, 2, b"first")]);
+    assert_eq!(Jpeg::load_from_buf(&data).unwrap().icc_profile, None);
+
+    // inconsistent count
+    let data = build_jpeg(&[icc_segment(1, 2, b"first"), icc_segment(2, 3, b"second")]);
+    assert_eq!(Jpeg::load_from_buf(&data).unwrap().icc_profile, None);
+}
+
+#[test]
+fn test_jpeg_xmp() {
+    let packet = "<x:xmpmeta xmlns:x=\"adobe:ns:meta/\"><rdf:RDF/></x:xmpmeta>";
+    let mut xmp_payload = b"http://ns.adobe.com/xap/1.0/\0".to_vec();
+    xmp_payload.extend(packet.as_bytes());
+
+    let data = build_jpeg(&[
+        exif_segment(&[vec![(0x0110, Value::Ascii("Owl Cam\0"))]]),
+        segment(0xe1, &xmp_payload)
+    ]);
+
+    let md = Jpeg::load_from_buf(&data).unwrap();
+    assert_eq!(md.model, Some("Owl Cam".to_owned()));
+    assert_eq!(md.xmp, Some(packet.to_owned()));
+
+    let md = Jpeg::load_from_buf(&build_jpeg(&[])).unwrap();
+    assert_eq!(md.xmp, None);
+}
+
+/// Wraps a reader and counts the number of bytes read through it.
+struct CountingReader<R> {
+    inner: R,
+    count: usize
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = try!(self.inner.read(buf));
+        self.count += n;
+        Ok(n)
+    }
+}
+
+impl<R: Seek> Seek for CountingReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+#[test]
+fn test_load_reads_bounded_amount() {
+    let mut data = vec![0xff, 0xd8];
+    data.extend(segment(0xc0, &[8, 0x01, 0xe0, 0x02, 0x80, 1, 1, 0x11, 0]));
+    data.extend(segment(0xda, &[1, 1, 0, 0, 63, 0]));
+    data.extend(vec![0x55; 4 * 1024 * 1024]);
+    data.extend(&[0xff, 0xd9]);
+
+    let mut r = CountingReader { inner: Cursor::new(&data[..]), count: 0 };
+    let md = immeta::load(&mut r).unwrap();
+    assert_eq!(md.dimensions(), JPEG_DIM);
+    assert!(r.count < 64 * 1024, "read {} bytes", r.count);
+}
+
+/// A reader which delivers at most a few bytes at a time and fails on any seek.
+struct StreamingReader<R> {
+    inner: R
+}
+
+impl<R: Read> Read for StreamingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = std::cmp::min(buf.len(), 3);
+        self.inner.read(&mut buf[..len])
+    }
+}
+
+impl<R> Seek for StreamingReader<R> {
+    #[allow(clippy::io_other_error)]
+    fn seek(&mut self, _: SeekFrom) -> io::Result<u64> {
+        Err(io::Error::new(io::ErrorKind::Other, "stream is not seekable"))
+    }
+}
+
+#[test]
+fn test_jpeg_streaming_reader() {
+    let data = build_jpeg(&[
+        segment(0xfe, b"streamed"),
+        exif_segment(&[vec![
+            (0x0110, Value::Ascii("Owl Cam")),
+            (0x8769, Value::Ifd(vec![(0x829a, Value::Rational(vec![(1, 250)]))]))
+        ]])
+    ]);
+
+    let mut r = StreamingReader { inner: &data[..] };
+    let md = Jpeg::load(&mut r).unwrap();
+    assert_eq!(md.dimensions, JPEG_DIM);
+    assert_eq!(md.comments, vec!["streamed".to_owned()]);
+    assert_eq!(md.model, Some("Owl Cam".to_owned()));
+    assert_eq!(md.exposure_time, Some(Rational { numerator: 1, denominator: 250 }));
+
+    let md = jpeg::Metadata::load_from_reader(StreamingReader { inner: &data[..] }).unwrap();
+    assert_eq!(md.dimensions, JPEG_DIM);
+    assert_eq!(md.comments, vec!["streamed".to_owned()]);
+    assert_eq!(md.model, Some("Owl Cam".to_owned()));
+}
+
+#[test]
+fn test_jpeg_stops_at_sos() {
+    let mut data = vec![0xff, 0xd8];
+    data.extend(segment(0xc0, &[8, 0x01, 0xe0, 0x02, 0x80, 1, 1, 0x11, 0]));
+    data.extend(segment(0xfe, b"after SOF"));
+    data.extend(segment(0xda, &[1, 1, 0, 0, 63, 0]));
+    // invalid segment in the scan data which must not be looked at
+    data.extend(&[0x12, 0xff, 0xe1, 0x00, 0x01, 0x34]);
+
+    let md = Jpeg::load_from_buf(&data).unwrap();
+    assert_eq!(md.dimensions, JPEG_DIM);
+    assert_eq!(md.comments, vec!["after SOF".to_owned()]);
+}
+
+#[test]
+fn test_jpeg_sos_without_sof() {
+    let mut data = vec![0xff, 0xd8];
+    data.extend(segment(0xda, &[1, 1, 0, 0, 63, 0]));
+    data.extend(segment(0xc0, &[8, 0x01, 0xe0, 0x02, 0x80, 1, 1, 0x11, 0]));
+    data.extend(&[0xff, 0xd9]);
+
+    match Jpeg::load_from_buf(&data) {
+        Err(Error::InvalidFormat(_)) => {}
+        other => panic!("unexpected result: {:?}", other)
+    }
+}
+
+#[test]
+fn test_jpeg_exif_zeroth_ifd_offset() {
+    // IFD0 is preceded by 8 reserved bytes which do not form a valid IFD
+    let mut payload = b"Exif\0\0MM\x00\x2a".to_vec();
+    put_u32(&mut payload, 16);
+    payload.extend(&[0xff; 8]);
+    put_u16(&mut payload, 1);
+    put_u16(&mut payload, 0x0112);
+    put_u16(&mut payload, 3);
+    put_u32(&mut payload, 1);
+    payload.extend(&[0, 6, 0, 0]);
+    put_u32(&mut payload, 0);
+
+    let md = Jpeg::load_from_buf(&build_jpeg(&[segment(0xe1, &payload)])).unwrap();
+    assert_eq!(md.exif().unwrap().zeroth_ifd_offset(), 16);
+    assert_eq!(md.orientation, Some(jpeg::Orientation::Rotate90));
+}
+
+#[test]
+fn test_jpeg_exif_structure() {
+    let data = build_jpeg(&[exif_segment(&[vec![
+        (0x010f, Value::Ascii("Canon")),
+        (0x0112, Value::Short(vec![6])),
+        (0x8769, Value::Ifd(vec![
+            (0x829a, Value::Rational(vec![(1, 250)])),
+            (0x9000, Value::Undefined(b"0230".to_vec()))
+        ]))
+    ]])]);
+    let md = Jpeg::load_from_buf(&data).unwrap();
+    let exif = md.exif().unwrap();
+
+    assert_eq!(exif.byte_order(), tiff::ByteOrder::BigEndian);
+    assert_eq!(exif.zeroth_ifd_offset(), 8);
+    assert_eq!(exif.ifds().len(), 1);
+    assert!(exif.gps_ifd().is_none());
+
+    let dump = |ifd: &tiff::Ifd| {
+        ifd.tags().iter().map(|t| (t.id(), t.datatype(), t.raw().len())).collect::<Vec<_>>()
+    };
+    assert_eq!(dump(&exif.ifds()[0]), vec![
+        (0x010f, tiff::TagDatatype::Ascii, 6),
+        (0x0112, tiff::TagDatatype::Short, 2),
+        (0x8769, tiff::TagDatatype::Long, 4)
+    ]);
+    assert_eq!(dump(exif.exif_ifd().unwrap()), vec![
+        (0x829a, tiff::TagDatatype::Rational, 8),
+        (0x9000, tiff::TagDatatype::Undefined, 4)
+    ]);
+
+    let md = Jpeg::load_from_buf(&build_jpeg(&[])).unwrap();
+    assert!(md.exif().is_none());
+}
+
+#[test]
+fn test_jpeg_app1_without_exif() {
+    let mut xmp_payload = b"http://ns.adobe.com/xap/1.0/\0".to_vec();
+    xmp_payload.extend(b"<x:xmpmeta/>");
+    let data = build_jpeg(&[
+        segment(0xe1, &xmp_payload),
+        segment(0xe1, b"some other data")
+    ]);
+
+    let md = Jpeg::load_from_buf(&data).unwrap();
+    assert!(md.exif().is_none());
+    assert_eq!(md.xmp, Some("<x:xmpmeta/>".to_owned()));
+}
+
+#[test]
+fn test_jpeg_has_exif() {
+    let data = build_jpeg(&[exif_segment(&[vec![(0xabcd, Value::Short(vec![1]))]])]);
+    let md = Jpeg::load_from_buf(&data).unwrap();
+    assert!(md.has_exif);
+    assert!(!md.has_xmp);
+    assert_eq!(md.orientation, None);
+    assert_eq!(md.make, None);
+
+    let mut xmp = b"http://ns.adobe.com/xap/1.0/\0".to_vec();
+    xmp.extend(b"<x:xmpmeta/>");
+    let md = Jpeg::load_from_buf(&build_jpeg(&[segment(0xe1, &xmp)])).unwrap();
+    assert!(!md.has_exif);
+    assert!(md.has_xmp);
+}
+
+#[test]
+fn test_jpeg_strip_metadata() {
+    let mut xmp = b"http://ns.adobe.com/xap/1.0/\0".to_vec();
+    xmp.extend(b"<x:xmpmeta/>");
+    let mut data = build_jpeg(&[
+        segment(0xe0, b"JFIF\0\x01\x02\x01\x00\x48\x00\x48\x00\x00"),
+        exif_segment(&[vec![(0x010f, Value::Ascii("Canon"))]]),
+        segment(0xe1, &xmp),
+        segment(0xed, b"Photoshop 3.0\0"),
+        segment(0xfe, b"secret")
+    ]);
+    // replace EOI with a scan
+    data.truncate(data.len() - 2);
+    data.extend(segment(0xda, &[1, 1, 0, 0, 0x3f, 0]));
+    let scan = [0x12, 0xff, 0x00, 0x34, 0xff, 0xd0, 0x56, 0xff, 0xd9];
+    data.extend(&scan);
+
+    let mut stripped = Vec::new();
+    jpeg::strip_metadata(&data[..], &mut stripped).unwrap();
+    assert!(stripped.len() < data.len());
+    assert!(stripped.ends_with(&scan));
+
+    let md = Jpeg::load_from_buf(&stripped).unwrap();
+    assert_eq!(md.dimensions, JPEG_DIM);
+    assert!(md.density.is_some());
+    assert!(!md.has_exif);
+    assert!(!md.has_xmp);
+    assert_eq!(md.make, None);
+    assert!(md.comments.is_empty());
+    let markers: Vec<_> = jpeg::segments(&stripped[..]).map(|s| s.unwrap().marker).collect();
+    assert_eq!(markers, vec![0xd8, 0xe0, 0xc0]);
+
+    let mut out = Vec::new();
+    assert!(jpeg::strip_metadata(&b"not a jpeg"[..], &mut out).is_err());
+}
+
+#[test]
+fn test_jpeg_set_orientation() {
+    let mut data = build_jpeg(&[
+        exif_segment(&[vec![
+            (0x010f, Value::Ascii("Canon")),
+            (0x0112, Value::Short(vec![6]))
+        ]]),
+        segment(0xfe, b"kept")
+    ]);
+    data.truncate(data.len() - 2);
+    data.extend(segment(0xda, &[1, 1, 0, 0, 0x3f, 0]));
+    let scan = [0x12, 0xff, 0x00, 0x34, 0xff, 0xd9];
+    data.extend(&scan);
+    assert_eq!(Jpeg::load_from_buf(&data).unwrap().orientation, Some(jpeg::Orientation::Rotate90));
+
+    let mut out = Vec::new();
+    jpeg::set_orientation(&data[..], &mut out, jpeg::Orientation::Normal).unwrap();
+    assert_eq!(out.len(), data.len());
+    assert!(out.ends_with(&scan));
+
+    let md = Jpeg::load_from_buf(&out).unwrap();
+    assert_eq!(md.orientation, Some(jpeg::Orientation::Normal));
+    assert_eq!(md.make, Some("Canon".to_owned()));
+    assert_eq!(md.comments, vec!["kept".to_owned()]);
+
+    let mut again = Vec::new();
+    jpeg::set_orientation(&out[..], &mut again, jpeg::Orientation::Rotate270).unwrap();
+    assert_eq!(Jpeg::load_from_buf(&again).unwrap().orientation, Some(jpeg::Orientation::Rotate270));
+
+    // the tag may also be stored as a LONG
+    let data = build_jpeg(&[exif_segment(&[vec![(0x0112, Value::Long(vec![6]))]])]);
+    let mut out = Vec::new();
+    jpeg::set_orientation(&data[..], &mut out, jpeg::Orientation::Rotate180).unwrap();
+    assert_eq!(out.len(), data.len());
+    let md = Jpeg::load_from_buf(&out).unwrap();
+    assert_eq!(md.orientation, Some(jpeg::Orientation::Rotate180));
+    assert_eq!(md.exif().unwrap().ifds()[0].get(0x0112).unwrap().get_long().unwrap(), 3);
+
+    // nothing is written if there is no tag to rewrite
+    let no_orientation = build_jpeg(&[exif_segment(&[vec![(0x010f, Value::Ascii("Canon"))]])]);
+    for data in &[no_orientation, build_jpeg(&[])] {
+        let mut out = Vec::new();
+        assert!(jpeg::set_orientation(&data[..], &mut out, jpeg::Orientation::Normal).is_err());
+        assert!(out.is_empty());
+    }
+}
+
+#[test]
+fn test_jpeg_standalone_markers() {
+    // TEM and a stray RST marker have no payload and must not be read as segments
+    let mut data = build_jpeg(&[vec![0xff, 0x01], segment(0xfe, b"after TEM"), vec![0xff, 0xd3]]);
+    data.truncate(data.len() - 2);
+    data.extend(segment(0xda, &[1, 1, 0, 0, 0x3f, 0]));
+    // entropy-coded data with stuffed bytes and restart markers
+    let scan = [0x12, 0xff, 0x00, 0x34, 0xff, 0xd0, 0x56, 0xff, 0x00, 0xff, 0xd1, 0x78, 0xff, 0xd9];
+    data.extend(&scan);
+
+    let md = Jpeg::load_from_buf(&data).unwrap();
+    assert_eq!(md.dimensions, JPEG_DIM);
+    assert_eq!(md.comments, vec!["after TEM".to_owned()]);
+
+    let segments: Vec<_> = jpeg::segments(&data[..]).map(|s| s.unwrap()).collect();
+    let markers: Vec<_> = segments.iter().map(|s| s.marker).collect();
+    assert_eq!(markers, vec![0xd8, 0x01, 0xfe, 0xd3, 0xc0]);
+    assert!(segments[1].data.is_empty());
+    assert!(segments[3].data.is_empty());
+
+    let mut stripped = Vec::new();
+    jpeg::strip_metadata(&data[..], &mut stripped).unwrap();
+    assert!(stripped.ends_with(&scan));
+    let markers: Vec<_> = jpeg::segments(&stripped[..]).map(|s| s.unwrap().marker).collect();
+    assert_eq!(markers, vec![0xd8, 0x01, 0xd3, 0xc0]);
+    assert_eq!(Jpeg::load_from_buf(&stripped).unwrap().dimensions, JPEG_DIM);
+}
+
+#[test]
+fn test_jpeg_truncated_app1() {
+    let mut data = build_jpeg(&[exif_segment(&[vec![(0x010f, Value::Ascii("Canon"))]])]);
+    // cut the stream in the middle of the APP1 segment, right after the EXIF identifier
+    data.truncate(2 + 4 + 6);
+
+    match Jpeg::load_from_buf(&data) {
+        Err(Error::UnexpectedEndOfFile(_)) => {}
+        Err(e) => panic!("unexpected error: {}", e),
+        Ok(_) => panic!("truncated APP1 segment was accepted")
+    }
+
+    // the segment claims more bytes than remain, although the TIFF header and IFD0 are complete
+    let segment = exif_segment(&[vec![(0x010f, Value::Ascii("Canon"))]]);
+    let mut data = vec![0xff, 0xd8];
+    data.extend(&segment);
+    data.extend(&[0; 8]);
+    let declared_size = segment.len() as u16 - 2 + 100;
+    data[4..6].copy_from_slice(&[(declared_size >> 8) as u8, declared_size as u8]);
+    match Jpeg::load_from_buf(&data) {
+        Err(ref e @ Error::UnexpectedEndOfFile(_)) => assert!(e.to_string().contains("APP1 segment")),
+        Err(e) => panic!("unexpected error: {}", e),
+        Ok(_) => panic!("truncated APP1 segment was accepted")
+    }
+}
+
+#[test]
+fn test_jpeg_exif_tags() {
+    let data = build_jpeg(&[exif_segment(&[vec![
+        (0x010f, Value::Ascii("Canon")),
+        (0x013b, Value::Ascii("Jane Doe")),
+        (0x8769, Value::Ifd(vec![
+            (0x829a, Value::Rational(vec![(1, 250)])),
+            (0xa002, Value::Long(vec![640])),
+            (0xc4a5, Value::Undefined(b"PrintIM".to_vec()))
+        ])),
+        (0x8825, Value::Ifd(vec![
+            (0x0001, Value::Ascii("N"))
+        ]))
+    ]])]);
+    let md = Jpeg::load_from_buf(&data).unwrap();
+
+    assert_eq!(md.exif_tags.get(&0x013b), Some(&jpeg::ExifValue::Ascii("Jane Doe".to_owned())));
+    assert_eq!(md.exif_tags.get(&0x010f), Some(&jpeg::ExifValue::Ascii("Canon".to_owned())));
+    assert_eq!(md.exif_tags.get(&0x829a),
+               Some(&jpeg::ExifValue::Rationals(vec![Rational { numerator: 1, denominator: 250 }])));
+    assert_eq!(md.exif_tags.get(&0xa002), Some(&jpeg::ExifValue::Longs(vec![640])));
+    assert_eq!(md.exif_tags.get(&0xc4a5), Some(&jpeg::ExifValue::Bytes(b"PrintIM".to_vec())));
+    // GPS tags are not mixed into the map
+    assert_eq!(md.exif_tags.get(&0x0001), None);
+
+    let md = Jpeg::load_from_buf(&build_jpeg(&[])).unwrap();
+    assert!(md.exif_tags.is_empty());
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_jpeg_serde_round_trip() {
+    let data = build_jpeg(&[exif_segment(&[vec![
+        (0x0110, Value::Ascii("Owl Cam\0")),
+        (0x0112, Value::Short(vec![6]))
+    ]])]);
+    let md = Jpeg::load_from_buf(&data).unwrap();
+
+    let json = serde_json::to_value(&md).unwrap();
+    assert_eq!(json["dimensions"], serde_json::json!({ "width": 640, "height": 480 }));
+    assert_eq!(json["orientation"], serde_json::json!("Rotate90"));
+    assert_eq!(json["model"], serde_json::json!("Owl Cam"));
+
+    let md2: jpeg::Metadata = serde_json::from_value(json).unwrap();
+    assert_eq!(md2, md);
+}
+
+#[test]
+fn test_display() {
+    assert_eq!(JPEG_DIM.to_string(), "640x480");
+
+    let cases = [
+        (jpeg::Orientation::Normal, "normal"),
+        (jpeg::Orientation::MirrorHorizontal, "mirror horizontal"),
+        (jpeg::Orientation::Rotate180, "rotate 180°"),
+        (jpeg::Orientation::MirrorVertical, "mirror vertical"),
+        (jpeg::Orientation::Transpose, "mirror horizontal and rotate 270° CW"),
+        (jpeg::Orientation::Rotate90, "rotate 90° CW"),
+        (jpeg::Orientation::Transverse, "mirror horizontal and rotate 90° CW"),
+        (jpeg::Orientation::Rotate270, "rotate 270° CW")
+    ];
+    for &(orientation, expected) in &cases {
+        assert_eq!(orientation.to_string(), expected);
+    }
+}
+
+#[test]
+fn test_jpeg_fill_bytes() {
+    let mut data = vec![0xff, 0xd8, 0xff, 0xff];
+    data.extend(segment(0xfe, b"padded"));
+    data.extend(&[0xff, 0xff, 0xff]);
+    data.extend(segment(0xc0, &[8, 0x01, 0xe0, 0x02, 0x80, 1, 1, 0x11, 0]));
+    data.extend(&[0xff, 0xff, 0xd9]);
+
+    let md = Jpeg::load_from_buf(&data).unwrap();
+    assert_eq!(md.dimensions, JPEG_DIM);
+    assert_eq!(md.comments, vec!["padded".to_owned()]);
+}
+
+#[test]
+fn test_jpeg_exif_offset_past_end() {
+    // ASCII tag with 16 bytes of value at offset 0x1000, far beyond the end of the EXIF data
+    let mut payload = b"Exif\0\0MM\x00\x2a\x00\x00\x00\x08".to_vec();
+    payload.extend(&[0x00, 0x01]);
+    payload.extend(&[0x01, 0x10, 0x00, 0x02, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00, 0x10, 0x00]);
+    payload.extend(&[0x00, 0x00, 0x00, 0x00]);
+
+    // the broken field is skipped
+    let md = Jpeg::load_from_buf(&build_jpeg(&[segment(0xe1, &payload)])).unwrap();
+    assert_eq!(md.dimensions, JPEG_DIM);
+    assert_eq!(md.model, None);
+    assert!(md.exif().unwrap().ifds()[0].tags().is_empty());
+
+    // zeroth IFD offset beyond the end of the EXIF data, the EXIF segment is ignored
+    let payload = b"Exif\0\0MM\x00\x2a\x00\x00\x10\x00".to_vec();
+    let md = Jpeg::load_from_buf(&build_jpeg(&[segment(0xe1, &payload)])).unwrap();
+    assert_eq!(md.dimensions, JPEG_DIM);
+    assert!(md.exif().is_none());
+}
+
+#[test]
+fn test_jpeg_corrupt_exif_header() {
+    let payloads: [&[u8]; 3] = [
+        b"Exif\0\0XX\x00\x2a\x00\x00\x00\x08",  // invalid byte order
+        b"Exif\0\0MM\x00\x17\x00\x00\x00\x08",  // invalid magic number
+        b"Exif\0\0MM"                             // truncated header
+    ];
+    for payload in &payloads {
+        let data = build_jpeg(&[segment(0xe1, payload), segment(0xfe, b"after exif")]);
+
+        let md = Jpeg::load_from_buf(&data).unwrap();
+        assert_eq!(md.dimensions, JPEG_DIM);
+        assert!(md.exif().is_none());
+        assert!(md.has_exif);
+        assert_eq!(md.comments, vec!["after exif".to_owned()]);
+
+        let md = immeta::load_from_buf(&data).unwrap();
+        assert_eq!(md.format(), immeta::Format::Jpeg);
+        assert_eq!(md.dimensions(), JPEG_DIM);
+    }
+}
+
+#[test]
+fn test_jpeg_corrupt_exif_tag() {
+    let mut payload = b"Exif\0\0MM\x00\x2a\x00\x00\x00\x08".to_vec();
+    payload.extend(&[0x00, 0x03]);
+    // Make, "Owl" stored inline
+    payload.extend(&[0x01, 0x0f, 0x00, 0x02, 0x00, 0x00, 0x00, 0x04, b'O', b'w', b'l', 0x00]);
+    // DateTime, 20 bytes of value at offset 0x1000, far beyond the end of the EXIF data
+    payload.extend(&[0x01, 0x32, 0x00, 0x02, 0x00, 0x00, 0x00, 0x14, 0x00, 0x00, 0x10, 0x00]);
+    // Orientation, rotated by 90 degrees
+    payload.extend(&[0x01, 0x12, 0x00, 0x03, 0x00, 0x00, 0x00, 0x01, 0x00, 0x06, 0x00, 0x00]);
+    payload.extend(&[0x00, 0x00, 0x00, 0x00]);
+
+    let md = Jpeg::load_from_buf(&build_jpeg(&[segment(0xe1, &payload)])).unwrap();
+    assert_eq!(md.dimensions, JPEG_DIM);
+    assert_eq!(md.make, Some("Owl".to_owned()));
+    assert_eq!(md.orientation, Some(jpeg::Orientation::Rotate90));
+    assert_eq!(md.date_time_raw, None);
+    assert_eq!(md.date_time, None);
+}
+
+#[test]
+fn test_webp_animation() {
+    fn frame(duration: u32) -> Vec<u8> {
+        let mut f = vec![0, 0, 0, 0, 0, 0, 0x0f, 0, 0, 0x0f, 0, 0];
+        f.extend(&[duration as u8, (duration >> 8) as u8, (duration >> 16) as u8, 0]);
+        f.extend(b"VP8L\x05\x00\x00\x00\x2f\x0f\xc0\x03\x00\x00");
+        f
+    }
+
+    let data = build_webp(&[
+        (b"VP8X", &[0x02, 0, 0, 0, 0x0f, 0, 0, 0x0f, 0, 0]),
+        (b"ANIM", &[0xff, 0x80, 0x00, 0xff, 3, 0]),
+        (b"ANMF", &frame(100)),
+        (b"ANMF", &frame(250)),
+        (b"ANMF", &frame(0x012345))
+    ]);
+
+    let md = Webp::load_from_buf(&data).unwrap();
+    assert_eq!(md.dimensions(), Dimensions { width: 16, height: 16 });
+
+    match md {
+        webp::Metadata::VP8X(md) => {
+            assert!(md.has_animation);
+            assert!(md.is_lossless);
+            assert_eq!(md.animation, Some(webp::AnimationInfo {
+                background_color: [0xff, 0x80, 0x00, 0xff],
+                loop_count: 3,
+                frame_count: 3,
+                total_duration_ms: 350 + 0x012345
+            }));
+        }
+        md => panic!("unexpected metadata: {:?}", md)
+    }
+}
+
+fn png_chunk(chunk_type: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    put_u32(&mut out, data.len() as u32);
+    out.extend(chunk_type);
+    out.extend(data);
+    put_u32(&mut out, 0);  // CRC is not checked by default
+    out
+}
+
+/// Builds a 640x480 8-bit RGB PNG with the given chunks between IHDR and IDAT.
+fn build_png(chunks: &[Vec<u8>]) -> Vec<u8> {
+    let mut out = b"\x89PNG\r\n\x1a\n".to_vec();
+    out.extend(png_chunk(b"IHDR", &[0, 0, 0x02, 0x80, 0, 0, 0x01, 0xe0, 8, 2, 0, 0, 0]));
+    for c in chunks {
+        out.extend(c);
+    }
+    out.extend(png_chunk(b"IDAT", &[0x78, 0x9c, 0x03, 0x00, 0x00, 0x00, 0x00, 0x01]));
+    out.extend(png_chunk(b"IEND", &[]));
+    out
+}
+
+#[test]
+fn test_png_verify_crc() {
+    let verify = png::Options { verify_crc: true };
+    let data = read_file("tests/images/owlet.png");
+    let md = png::Metadata::load_with_options(&mut &data[..], verify).unwrap();
+    assert_eq!(md.dimensions, OWLET_DIM);
+
+    // CRC of IHDR and CRC of IEND, which is otherwise skipped
+    let ihdr_crc = 8 + 8 + 13;
+    let iend_crc = data.len() - 4;
+    for &(pos, chunk) in &[(ihdr_crc, "IHDR"), (iend_crc, "IEND")] {
+        let mut corrupted = data.clone();
+        corrupted[pos] ^= 0xff;
+
+        assert!(Png::load_from_buf(&corrupted).is_ok());
+        match png::Metadata::load_with_options(&mut &corrupted[..], verify) {
+            Err(Error::InvalidFormat(ref msg)) => assert_eq!(*msg, format!("CRC mismatch in chunk {}", chunk)),
+            r => panic!("unexpected result: {:?}", r)
+        }
+    }
+}
+
+#[test]
+fn test_png_physical_dimensions() {
+    // 11811 pixels per meter is 300 DPI
+    let data = build_png(&[
+        png_chunk(b"gAMA", &[0, 0, 0xb1, 0x8f]),
+        png_chunk(b"pHYs", &[0, 0, 0x2e, 0x23, 0, 0, 0x2e, 0x23, 1])
+    ]);
+
+    let md = Png::load_from_buf(&data).unwrap();
+    assert_eq!(md.dimensions, JPEG_DIM);
+    let phys = md.physical_dimensions.unwrap();
+    assert_eq!(phys, png::PhysicalDimensions { x_ppu: 11811, y_ppu: 11811, unit: png::Unit::Meter });
+    let (x_dpi, y_dpi) = phys.dpi().unwrap();
+    assert_eq!(x_dpi.round(), 300.0);
+    assert_eq!(y_dpi.round(), 300.0);
+
+    let data = build_png(&[png_chunk(b"pHYs", &[0, 0, 0, 2, 0, 0, 0, 1, 0])]);
+    let phys = Png::load_from_buf(&data).unwrap().physical_dimensions.unwrap();
+    assert_eq!(phys.unit, png::Unit::Unknown);
+    assert_eq!(phys.dpi(), None);
+
+    let md = Png::load_from_buf(&build_png(&[])).unwrap();
+    assert_eq!(md.physical_dimensions, None);
+}
+
+fn be_u32s(values: &[u32]) -> Vec<u8> {
+    values.iter().flat_map(|&v| vec![(v >> 24) as u8, (v >> 16) as u8, (v >> 8) as u8, v as u8]).collect()
+}
+
+#[test]
+fn test_png_gamma() {
+    let data = build_png(&[
+        png_chunk(b"gAMA", &be_u32s(&[100000])),
+        png_chunk(b"cHRM", &be_u32s(&[31270, 32900, 64000, 33000, 30000, 60000, 15000, 6000]))
+    ]);
+
+    let md = Png::load_from_buf(&data).unwrap();
+    assert_eq!(md.gamma, Some(1.0));
+    assert_eq!(md.srgb_intent, None);
+    let chrm = png::Chromaticities {
+        white_x: 31270, white_y: 32900,
+        red_x: 64000, red_y: 33000,
+        green_x: 30000, green_y: 60000,
+        blue_x: 15000, blue_y: 6000
+    };
+    assert_eq!(md.chromaticities, Some(chrm));
+    assert_eq!(md.effective_gamma(), Some(1.0));
+    assert_eq!(md.effective_chromaticities(), Some(chrm));
+
+    let md = Png::load_from_buf(&build_png(&[])).unwrap();
+    assert_eq!(md.gamma, None);
+    assert_eq!(md.chromaticities, None);
+    assert_eq!(md.effective_gamma(), None);
+    assert_eq!(md.effective_chromaticities(), None);
+}
+
+#[test]
+fn test_png_srgb() {
+    let data = build_png(&[
+        png_chunk(b"sRGB", &[1]),
+        png_chunk(b"gAMA", &be_u32s(&[100000])),
+        png_chunk(b"cHRM", &be_u32s(&[1, 2, 3, 4, 5, 6, 7, 8]))
+    ]);
+
+    let md = Png::load_from_buf(&data).unwrap();
+    assert_eq!(md.srgb_intent, Some(png::SrgbIntent::RelativeColorimetric));
+    assert_eq!(md.gamma, Some(1.0));
+    // sRGB chunk overrides gAMA and cHRM
+    assert_eq!(md.effective_gamma(), Some(0.45455));
+    let chrm = md.effective_chromaticities().unwrap();
+    assert_eq!((chrm.white_x, chrm.white_y), (31270, 32900));
+    assert_eq!((chrm.blue_x, chrm.blue_y), (15000, 6000));
+
+    let md = Png::load_from_buf(&build_png(&[png_chunk(b"sRGB", &[4])])).unwrap();
+    assert_eq!(md.srgb_intent, None);
+}
+
+#[cfg(feature = "flate2")]
+#[test]
+fn test_png_text() {
+    let mut ztxt = b"Description\0\0".to_vec();
+    ztxt.extend(&[
+        0x78, 0x9c, 0x73, 0x54, 0x28, 0x4b, 0x2d, 0xaa, 0x54, 0xc8, 0xc9, 0xcf, 0x4b, 0x57,
+        0x48, 0x49, 0x2d, 0x4e, 0x2e, 0xca, 0x2c, 0x28, 0xc9, 0xcc, 0xcf, 0x53, 0xc8, 0x4f,
+        0x53, 0x48, 0x04, 0x92, 0xe5, 0x39, 0x00, 0xca, 0xf6, 0x0c, 0x12
+    ]);
+    let mut itxt_compressed = b"Title\0\x01\x00ru\0\xd0\x9d\xd0\xb0\xd0\xb7\xd0\xb2\xd0\xb0\xd0\xbd\xd0\xb8\xd0\xb5\0".to_vec();
+    itxt_compressed.extend(&[
+        0x78, 0x9c, 0xbb, 0xb0, 0xf0, 0xc2, 0xbe, 0x0b, 0x9b, 0x2e, 0x6c, 0x00, 0x00, 0x1b,
+        0x2b, 0x06, 0x02
+    ]);
+
+    let mut data = build_png(&[
+        png_chunk(b"tEXt", b"Author\0Jos\xe9"),
+        png_chunk(b"zTXt", &ztxt),
+        png_chunk(b"iTXt", b"Comment\0\x00\x00\0\0plain \xe2\x9c\x93")
+    ]);
+    // text chunks may also follow the image data
+    let iend = data.len() - 12;
+    data.truncate(iend);
+    data.extend(png_chunk(b"iTXt", &itxt_compressed));
+    data.extend(png_chunk(b"IEND", &[]));
+
+    let md = Png::load_from_buf(&data).unwrap();
+    assert_eq!(md.text, vec![
+        png::Text {
+            keyword: "Author".to_owned(),
+            value: "José".to_owned(),
+            compressed: false,
+            language: None
+        },
+        png::Text {
+            keyword: "Description".to_owned(),
+            value: "A very long description of an owl".to_owned(),
+            compressed: true,
+            language: None
+        },
+        png::Text {
+            keyword: "Comment".to_owned(),
+            value: "plain ✓".to_owned(),
+            compressed: false,
+            language: None
+        },
+        png::Text {
+            keyword: "Title".to_owned(),
+            value: "Сова".to_owned(),
+            compressed: true,
+            language: Some("ru".to_owned())
+        }
+    ]);
+}
+
+/// Wraps data into a zlib stream made of uncompressed deflate blocks.
+#[cfg(feature = "flate2")]
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let mut blocks = data.chunks(0xffff).peekable();
+    if data.is_empty() {
+        out.extend(&[0x01, 0x00, 0x00, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        let len = block.len() as u16;
+        out.push(if blocks.peek().is_none() { 1 } else { 0 });
+        out.extend(&[len as u8, (len >> 8) as u8, !len as u8, (!len >> 8) as u8]);
+        out.extend(block);
+    }
+    let (mut a, mut b) = (1u32, 0u32);
+    for &x in data {
+        a = (a + x as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    put_u32(&mut out, (b << 16) | a);
+    out
+}
+
+#[cfg(feature = "flate2")]
+#[test]
+fn test_png_text_size_limit() {
+    fn ztxt(keyword: &[u8], len: usize) -> Vec<u8> {
+        let mut data = keyword.to_vec();
+        data.extend(&[0, 0]);
+        data.extend(zlib_stored(&vec![b'a'; len]));
+        png_chunk(b"zTXt", &data)
+    }
+
+    let data = build_png(&[
+        ztxt(b"Small", 16),
+        ztxt(b"Limit", 1024 * 1024),
+        ztxt(b"Huge", 1024 * 1024 + 1),
+        png_chunk(b"tEXt", b"After\0kept")
+    ]);
+    let md = Png::load_from_buf(&data).unwrap();
+    let keywords: Vec<_> = md.text.iter().map(|t| &t.keyword[..]).collect();
+    assert_eq!(keywords, vec!["Small", "Limit", "After"]);
+    assert_eq!(md.text[0].value, "a".repeat(16));
+    assert_eq!(md.text[1].value.len(), 1024 * 1024);
+}
+
+fn fctl_chunk(seq_no: u32, delay_num: u16, delay_den: u16) -> Vec<u8> {
+    let mut data = Vec::new();
+    for &n in &[seq_no, 640, 480, 0, 0] {
+        put_u32(&mut data, n);
+    }
+    put_u16(&mut data, delay_num);
+    put_u16(&mut data, delay_den);
+    data.extend(&[0, 0]);
+    png_chunk(b"fcTL", &data)
+}
+
+#[test]
+fn test_png_animation() {
+    let mut data = build_png(&[
+        png_chunk(b"acTL", &[0, 0, 0, 3, 0, 0, 0, 0]),
+        fctl_chunk(0, 1, 10)
+    ]);
+    // the remaining frames follow the default image
+    let iend = data.len() - 12;
+    data.truncate(iend);
+    data.extend(fctl_chunk(1, 25, 0));
+    data.extend(png_chunk(b"fdAT", &[0, 0, 0, 2, 0x78, 0x9c, 0x03, 0x00, 0x00, 0x00, 0x00, 0x01]));
+    data.extend(fctl_chunk(3, 1, 4));
+    data.extend(png_chunk(b"fdAT", &[0, 0, 0, 4, 0x78, 0x9c, 0x03, 0x00, 0x00, 0x00, 0x00, 0x01]));
+    data.extend(png_chunk(b"IEND", &[]));
+
+    let md = Png::load_from_buf(&data).unwrap();
+    assert_eq!(md.animation, Some(png::AnimationInfo {
+        num_frames: 3,
+        num_plays: 0,
+        total_duration: Duration::from_millis(100 + 250 + 250)
+    }));
+
+    let md = Png::load_from_buf(&build_png(&[])).unwrap();
+    assert_eq!(md.animation, None);
+}
+
+fn build_png_with_ihdr(bit_depth: u8, color_type: u8, interlace_method: u8) -> Vec<u8> {
+    let mut out = b"\x89PNG\r\n\x1a\n".to_vec();
+    out.extend(png_chunk(b"IHDR", &[
+        0, 0, 0x02, 0x80, 0, 0, 0x01, 0xe0, bit_depth, color_type, 0, 0, interlace_method
+    ]));
+    out.extend(png_chunk(b"IEND", &[]));
+    out
+}
+
+#[test]
+fn test_png_exif() {
+    let exif = build_tiff(&[vec![
+        (0x010f, Value::Ascii("Canon")),
+        (0x0110, Value::Ascii("EOS 5D")),
+        (0x0112, Value::Short(vec![6])),
+        (0x0132, Value::Ascii("2016:01:02 03:04:05"))
+    ]]);
+    let md = Png::load_from_buf(&build_png(&[png_chunk(b"eXIf", &exif)])).unwrap();
+    assert_eq!(md.orientation, Some(jpeg::Orientation::Rotate90));
+    assert_eq!(md.make, Some("Canon".to_owned()));
+    assert_eq!(md.model, Some("EOS 5D".to_owned()));
+    assert_eq!(md.date_time_raw, Some("2016:01:02 03:04:05".to_owned()));
+    assert!(md.date_time.is_some());
+
+    // malformed EXIF data is ignored
+    let md = Png::load_from_buf(&build_png(&[png_chunk(b"eXIf", b"garbage")])).unwrap();
+    assert_eq!(md.orientation, None);
+    assert_eq!(md.make, None);
+}
+
+#[test]
+fn test_png_transparency() {
+    let mut data = build_png_with_ihdr(8, 3, 0);
+    let iend = data.split_off(data.len() - 12);
+    data.extend(png_chunk(b"PLTE", &[0, 0, 0, 255, 255, 255, 255, 0, 0]));
+    data.extend(png_chunk(b"tRNS", &[0, 128]));
+    data.extend(iend);
+
+    let md = Png::load_from_buf(&data).unwrap();
+    assert_eq!(md.color_type, png::ColorType::Indexed);
+    assert_eq!(md.palette_len, Some(3));
+    assert_eq!(md.transparency, Some(png::Transparency::Palette(vec![0, 128])));
+    assert!(md.has_transparency());
+
+    let md = Png::load_from_buf(&build_png_with_ihdr(8, 6, 0)).unwrap();
+    assert_eq!(md.color_type, png::ColorType::RgbAlpha);
+    assert_eq!(md.palette_len, None);
+    assert_eq!(md.transparency, None);
+    assert!(md.has_transparency());
+
+    let data = build_png(&[png_chunk(b"tRNS", &[0, 1, 0, 2, 0, 3])]);
+    let md = Png::load_from_buf(&data).unwrap();
+    assert_eq!(md.transparency, Some(png::Transparency::Rgb(1, 2, 3)));
+    assert!(md.has_transparency());
+
+    let md = Png::load_from_buf(&build_png(&[])).unwrap();
+    assert!(!md.has_transparency());
+}
+
+#[test]
+fn test_png_color_types() {
+    let cases = [
+        (1, 0, png::ColorType::Grayscale, 1),
+        (16, 0, png::ColorType::Grayscale, 16),
+        (16, 2, png::ColorType::Rgb, 48),
+        (4, 3, png::ColorType::Indexed, 4),
+        (8, 4, png::ColorType::GrayscaleAlpha, 16),
+        (8, 6, png::ColorType::RgbAlpha, 32)
+    ];
+    for &(bit_depth, color_type, expected_type, expected_depth) in &cases {
+        let md = Png::load_from_buf(&build_png_with_ihdr(bit_depth, color_type, 1)).unwrap();
+        assert_eq!(md.color_type, expected_type);
+        assert_eq!(md.bit_depth, bit_depth);
+        assert_eq!(md.color_depth, expected_depth);
+        assert_eq!(md.interlace_method, png::InterlaceMethod::Adam7);
+        assert!(md.is_interlaced());
+    }
+
+    // illegal combinations of bit depth and color type
+    for &(bit_depth, color_type) in &[(16, 3), (4, 2), (2, 6), (3, 0), (8, 5)] {
+        match Png::load_from_buf(&build_png_with_ihdr(bit_depth, color_type, 0)) {
+            Err(Error::InvalidFormat(_)) => {}
+            r => panic!("unexpected result for {}/{}: {:?}", bit_depth, color_type, r)
+        }
+    }
+}
+
+/// An image descriptor of a 1x1 frame with a minimal image data.
+const GIF_FRAME: &'static [u8] = b"\x2c\x00\x00\x00\x00\x01\x00\x01\x00\x00\x02\x02\x44\x01\x00";
+
+/// Builds a 1x1 GIF89a image without color tables consisting of the given blocks.
+fn build_gif(blocks: &[&[u8]]) -> Vec<u8> {
+    let mut out = b"GIF89a\x01\x00\x01\x00\x00\x00\x00".to_vec();
+    for b in blocks {
+        out.extend(*b);
+    }
+    out.push(0x3b);
+    out
+}
+
+#[test]
+fn test_gif_loop_count() {
+    let data = build_gif(&[b"\x21\xff\x0bNETSCAPE2.0\x03\x01\x05\x00\x00", GIF_FRAME, GIF_FRAME]);
+    let md = Gif::load_from_buf(&data).unwrap();
+    assert_eq!(md.loop_count, Some(5));
+
+    let data = build_gif(&[b"\x21\xff\x0bImageMagick\x05gamma\x00", GIF_FRAME]);
+    let md = Gif::load_from_buf(&data).unwrap();
+    assert_eq!(md.loop_count, None);
+}
+
+#[test]
+fn test_gif_frame_delays() {
+    fn gce(delay: u16) -> Vec<u8> {
+        vec![0x21, 0xf9, 0x04, 0x00, delay as u8, (delay >> 8) as u8, 0x00, 0x00]
+    }
+
+    let (gce1, gce2, gce3) = (gce(10), gce(0), gce(300));
+    let data = build_gif(&[&gce1, GIF_FRAME, &gce2, GIF_FRAME, &gce3, GIF_FRAME]);
+
+    let md = Gif::load_from_buf(&data).unwrap();
+    assert_eq!(md.frame_count, 3);
+    assert_eq!(md.frame_delays, vec![10, 0, 300]);
+    assert_eq!(md.total_duration(), Duration::from_millis(3100));
+
+    // frames without graphic control extension have no delay
+    let data = build_gif(&[&gce1, GIF_FRAME, GIF_FRAME]);
+    let md = Gif::load_from_buf(&data).unwrap();
+    assert_eq!(md.frame_delays, vec![10, 0]);
+
+    // the first three frames of drop.gif, with the delays changed to 10, 0 and 250
+    let md = Gif::load_from_file("tests/images/drop3.gif").unwrap();
+    assert_eq!(md.dimensions, DROP_DIM);
+    assert_eq!(md.frame_count, 3);
+    assert_eq!(md.frames_number(), 3);
+    assert_eq!(md.frame_delays, vec![10, 0, 250]);
+    assert_eq!(md.total_duration(), Duration::from_millis(2600));
+    assert_eq!(md.loop_count, Some(0));
+}
+
+#[test]
+fn test_gif_comments() {
+    let data = build_gif(&[
+        b"\x21\xfe\x05Photo\x04 by \x03Owl\x00",
+        GIF_FRAME,
+        b"\x21\xfe\x04caf\xe9\x00"
+    ]);
+
+    let md = Gif::load_from_buf(&data).unwrap();
+    assert_eq!(md.comments, vec!["Photo by Owl".to_owned(), "caf\u{fffd}".to_owned()]);
+}
+
+#[test]
+fn test_generic_dimensions() {
+    let cases = [
+        ("tests/images/owlet.jpg", OWLET_DIM),
+        ("tests/images/owlet.png", OWLET_DIM),
+        ("tests/images/owlet.gif", OWLET_DIM),
+        ("tests/images/drop.gif", DROP_DIM),
+        ("tests/images/cherry.webp", CHERRY_DIM)
+    ];
+    for &(path, dim) in &cases {
+        let md = immeta::load(&mut File::open(path).unwrap()).unwrap();
+        assert_eq!(md.dimensions(), dim, "{}", path);
+    }
+}
+
+#[test]
+fn test_generic_display_dimensions() {
+    let data = build_jpeg(&[exif_segment(&[vec![(0x0112, Value::Short(vec![6]))]])]);
+    let md = immeta::load_from_buf(&data).unwrap();
+    assert_eq!(md.dimensions(), JPEG_DIM);
+    assert_eq!(md.display_dimensions(), Dimensions { width: 480, height: 640 });
+
+    let md = immeta::load_from_buf(&build_jpeg(&[])).unwrap();
+    assert_eq!(md.display_dimensions(), JPEG_DIM);
+
+    let md = immeta::load_from_buf(&build_png(&[])).unwrap();
+    assert_eq!(md.display_dimensions(), md.dimensions());
+}
+
+#[test]
+fn test_generic_format() {
+    let cases = [
+        ("tests/images/owlet.jpg", immeta::Format::Jpeg),
+        ("tests/images/owlet.png", immeta::Format::Png),
+        ("tests/images/owlet.gif", immeta::Format::Gif),
+        ("tests/images/drop.gif", immeta::Format::Gif),
+        ("tests/images/cherry.webp", immeta::Format::Webp)
+    ];
+    for &(path, format) in &cases {
+        let md = immeta::load_from_file(path).unwrap();
+        assert_eq!(md.format(), format, "{}", path);
+        assert_eq!(md.mime_type(), format.mime_type());
+    }
+}
+
+#[test]
+fn test_generic_mime_type() {
+    let vp8l_bits: u32 = 639 | 479 << 14;
+    let vp8l = [0x2f, vp8l_bits as u8, (vp8l_bits >> 8) as u8, (vp8l_bits >> 16) as u8,
+                (vp8l_bits >> 24) as u8, 0, 0];
+    let cases = [
+        (build_jpeg(&[]), "image/jpeg"),
+        (build_png(&[]), "image/png"),
+        (build_gif(&[GIF_FRAME]), "image/gif"),
+        (build_webp(&[(b"VP8L", &vp8l)]), "image/webp"),
+        (build_bmp(INFO_HEADER_SIZE, 640, 480, 24, 0), "image/bmp")
+    ];
+    for &(ref data, mime_type) in &cases {
+        assert_eq!(immeta::load_from_buf(data).unwrap().mime_type(), mime_type);
+    }
+}
+
+#[test]
+fn test_guess_format() {
+    let vp8l_bits: u32 = 639 | 479 << 14;
+    let vp8l = [0x2f, vp8l_bits as u8, (vp8l_bits >> 8) as u8, (vp8l_bits >> 16) as u8,
+                (vp8l_bits >> 24) as u8, 0, 0];
+    let cases = [
+        (build_jpeg(&[]), immeta::Format::Jpeg),
+        (build_png(&[]), immeta::Format::Png),
+        (build_gif(&[GIF_FRAME]), immeta::Format::Gif),
+        (build_webp(&[(b"VP8L", &vp8l)]), immeta::Format::Webp),
+        (build_bmp(INFO_HEADER_SIZE, 640, 480, 24, 0), immeta::Format::Bmp)
+    ];
+    for &(ref data, format) in &cases {
+        assert_eq!(immeta::guess_format(&data[..16]), Some(format));
+        assert_eq!(immeta::guess_format(&data[..12]), Some(format));
+    }
+
+    assert_eq!(immeta::guess_format(b"RIFF\x10\x00\x00\x00WAVEfmt "), None);
+    assert_eq!(immeta::guess_format(b"RIFF\x10\x00\x00\x00WE"), None);
+    assert_eq!(immeta::guess_format(b""), None);
+}
+
+#[test]
+fn test_format_magic() {
+    let vp8l_bits: u32 = 639 | 479 << 14;
+    let vp8l = [0x2f, vp8l_bits as u8, (vp8l_bits >> 8) as u8, (vp8l_bits >> 16) as u8,
+                (vp8l_bits >> 24) as u8, 0, 0];
+    let samples = [
+        (build_png(&[]), immeta::Format::Png),
+        (build_gif(&[GIF_FRAME]), immeta::Format::Gif),
+        (build_jpeg(&[]), immeta::Format::Jpeg),
+        (build_webp(&[(b"VP8L", &vp8l)]), immeta::Format::Webp),
+        (build_bmp(INFO_HEADER_SIZE, 640, 480, 24, 0), immeta::Format::Bmp),
+        (build_ico(1, &[(0, 0, icon_dib(32))]), immeta::Format::Ico),
+        (build_heif(&[b"heic"], 1, &[ispe_box(640, 480)], &[0, 0, 0, 1, 0, 1, 1, 1], 0), immeta::Format::Heif),
+        (build_heif(&[b"avif"], 1, &[ispe_box(640, 480)], &[0, 0, 0, 1, 0, 1, 1, 1], 0), immeta::Format::Avif),
+        (build_tiff(&[vec![(0x0100, Value::Short(vec![640]))]]), immeta::Format::Tiff)
+    ];
+    assert_eq!(samples.len(), immeta::Format::all().len());
+
+    for &(ref data, format) in &samples {
+        let matching: Vec<_> = immeta::Format::all().iter().cloned()
+            .filter(|f| f.magic().iter().any(|sig| {
+                sig.iter().all(|&(offset, bytes)| data[offset..].starts_with(bytes))
+            }))
+            .collect();
+        assert_eq!(matching, vec![format]);
+        assert_eq!(immeta::guess_format(data), Some(format));
+    }
+}
+
+#[test]
+fn test_load_from_buf_limited() {
+    // JPEG segment sizes are 16-bit, so a large amount of data is made of many
+    // maximum-sized APP1 segments instead of a single huge one
+    let app1 = segment(0xe1, &vec![0; 65533]);
+    let data = build_jpeg(&vec![app1; 20]);
+
+    match immeta::load_from_buf_limited(&data, 1024 * 1024) {
+        Err(immeta::Error::InvalidFormat(ref s)) => assert_eq!(s, "exceeded read limit"),
+        Err(e) => panic!("unexpected error: {}", e),
+        Ok(_) => panic!("read limit was not enforced")
+    }
+
+    let md = immeta::load_from_buf_limited(&data, 2 * 1024 * 1024).unwrap();
+    assert_eq!(md.dimensions(), JPEG_DIM);
+}
+
+#[test]
+fn test_load_from_buf_limited_exact_size() {
+    let tiff = build_tiff(&[vec![(0x0100, Value::Short(vec![640])), (0x0101, Value::Short(vec![480]))]]);
+    let md = immeta::load_from_buf_limited(&tiff, tiff.len()).unwrap();
+    assert_eq!(md.format(), immeta::Format::Tiff);
+    assert_eq!(md.dimensions(), JPEG_DIM);
+
+    let jpeg = build_jpeg(&[segment(0xfe, b"limited")]);
+    let md = immeta::load_from_buf_limited(&jpeg, jpeg.len()).unwrap();
+    assert_eq!(md.format(), immeta::Format::Jpeg);
+    assert_eq!(md.dimensions(), JPEG_DIM);
+}
+
+const INFO_HEADER_SIZE: u32 = 40;
+const V5_HEADER_SIZE: u32 = 124;
+
+/// Builds a BMP file header followed by a DIB header of the given size; all fields not
+/// passed as arguments are zero.
+fn build_bmp(header_size: u32, width: i32, height: i32, bits_per_pixel: u16, compression: u32) -> Vec<u8> {
+    let mut dib = Vec::new();
+    put_u32_le(&mut dib, header_size);
+    put_u32_le(&mut dib, width as u32);
+    put_u32_le(&mut dib, height as u32);
+    dib.extend(&[1, 0, bits_per_pixel as u8, (bits_per_pixel >> 8) as u8]);
+    put_u32_le(&mut dib, compression);
+    dib.resize(header_size as usize, 0);
+
+    let mut out = b"BM".to_vec();
+    put_u32_le(&mut out, 14 + dib.len() as u32);
+    put_u32_le(&mut out, 0);
+    put_u32_le(&mut out, 14 + dib.len() as u32);
+    out.extend(dib);
+    out
+}
+
+#[test]
+fn test_bmp() {
+    let md = Bmp::load_from_buf(&build_bmp(INFO_HEADER_SIZE, 640, 480, 24, 0)).unwrap();
+    assert_eq!(md.dimensions, JPEG_DIM);
+    assert_eq!(md.bits_per_pixel, 24);
+    assert_eq!(md.compression, bmp::Compression::Rgb);
+    assert!(!md.top_down);
+
+    let md = Bmp::load_from_buf(&build_bmp(V5_HEADER_SIZE, 640, 480, 32, 3)).unwrap();
+    assert_eq!(md.dimensions, JPEG_DIM);
+    assert_eq!(md.bits_per_pixel, 32);
+    assert_eq!(md.compression, bmp::Compression::Bitfields);
+
+    let md = immeta::load_from_buf(&build_bmp(INFO_HEADER_SIZE, 640, 480, 8, 1)).unwrap();
+    let md = md.into::<Bmp>().ok().unwrap();
+    assert_eq!(md.compression, bmp::Compression::Rle8);
+}
+
+#[test]
+fn test_bmp_top_down() {
+    let md = Bmp::load_from_buf(&build_bmp(INFO_HEADER_SIZE, 640, -480, 24, 0)).unwrap();
+    assert_eq!(md.dimensions, JPEG_DIM);
+    assert!(md.top_down);
+}
+
+#[test]
+fn test_bmp_core_header() {
+    let mut data = b"BM".to_vec();
+    data.extend(&[0; 12]);
+    put_u32_le(&mut data, 12);
+    data.extend(&[0x80, 0x02, 0xe0, 0x01, 1, 0, 8, 0]);
+
+    let md = Bmp::load_from_buf(&data).unwrap();
+    assert_eq!(md.dimensions, JPEG_DIM);
+    assert_eq!(md.bits_per_pixel, 8);
+    assert_eq!(md.compression, bmp::Compression::Rgb);
+}
+
+#[test]
+fn test_bmp_invalid() {
+    let cases = [
+        build_bmp(INFO_HEADER_SIZE, 640, 480, 24, 7),
+        build_bmp(32, 640, 480, 24, 0),
+        build_bmp(INFO_HEADER_SIZE, -640, 480, 24, 0),
+        build_bmp(INFO_HEADER_SIZE, 640, 480, 24, 0)[..20].to_vec()
+    ];
+    for data in &cases {
+        assert!(Bmp::load_from_buf(data).is_err());
+    }
+}
+
+/// Builds an icon file of the given type; each image is given as `(width, height, data)` and
+/// image data is laid out in the reverse order of directory entries.
+fn build_ico(kind: u16, images: &[(u8, u8, Vec<u8>)]) -> Vec<u8> {
+    let mut out = vec![0, 0, kind as u8, 0, images.len() as u8, 0];
+    let mut offset = 6 + 16 * images.len() as u32 + images.iter().map(|i| i.2.len() as u32).sum::<u32>();
+    for &(width, height, ref data) in images {
+        offset -= data.len() as u32;
+        out.extend(&[width, height, 0, 0, 1, 0, 2, 0]);
+        put_u32_le(&mut out, data.len() as u32);
+        put_u32_le(&mut out, offset);
+    }
+    for image in images.iter().rev() {
+        out.extend(&image.2);
+    }
+    out
+}
+
+/// Returns a DIB header of the given bit count, as stored in icon files.
+fn icon_dib(bits_per_pixel: u16) -> Vec<u8> {
+    build_bmp(INFO_HEADER_SIZE, 32, 64, bits_per_pixel, 0)[14..].to_vec()
+}
+
+#[test]
+fn test_ico() {
+    let data = build_ico(1, &[
+        (16, 16, icon_dib(4)),
+        (32, 32, icon_dib(32)),
+        (0, 0, build_png_with_ihdr(8, 6, 0))
+    ]);
+
+    let md = Ico::load_from_buf(&data).unwrap();
+    assert_eq!(md.kind, ico::Kind::Icon);
+    assert_eq!(md.entries, vec![
+        ico::Entry { width: 16, height: 16, bit_count: 4, is_png: false, hotspot: None },
+        ico::Entry { width: 32, height: 32, bit_count: 32, is_png: false, hotspot: None },
+        ico::Entry { width: 256, height: 256, bit_count: 32, is_png: true, hotspot: None }
+    ]);
+    assert_eq!(md.dimensions(), Dimensions { width: 256, height: 256 });
+
+    let md = immeta::load_from_buf(&data).unwrap();
+    assert_eq!(md.mime_type(), "image/x-icon");
+    assert_eq!(md.dimensions(), Dimensions { width: 256, height: 256 });
+    assert_eq!(immeta::guess_format(&data), Some(immeta::Format::Ico));
+}
+
+#[test]
+fn test_cur() {
+    let md = Ico::load_from_buf(&build_ico(2, &[(32, 32, icon_dib(1))])).unwrap();
+    assert_eq!(md.kind, ico::Kind::Cursor);
+    assert_eq!(md.entries, vec![
+        ico::Entry { width: 32, height: 32, bit_count: 1, is_png: false, hotspot: Some((1, 2)) }
+    ]);
+}
+
+#[test]
+fn test_ico_invalid() {
+    let mut overlapping = build_ico(1, &[(16, 16, icon_dib(4))]);
+    overlapping[18] = 10;  // image offset inside the directory
+
+    let cases = [
+        build_ico(1, &[]),
+        build_ico(3, &[(16, 16, icon_dib(4))]),
+        build_ico(1, &[(16, 16, b"not an image".to_vec())]),
+        build_ico(1, &[(16, 16, icon_dib(4))])[..30].to_vec(),
+        overlapping
+    ];
+    for data in &cases {
+        assert!(Ico::load_from_buf(data).is_err());
+    }
+}
+
+fn iso_box(box_type: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    put_u32(&mut out, data.len() as u32 + 8);
+    out.extend(box_type);
+    out.extend(data);
+    out
+}
+
+fn full_box(box_type: &[u8], version: u8, flags: u8, data: &[u8]) -> Vec<u8> {
+    let mut body = vec![version, 0, 0, flags];
+    body.extend(data);
+    iso_box(box_type, &body)
+}
+
+fn ispe_box(width: u32, height: u32) -> Vec<u8> {
+    let mut data = Vec::new();
+    put_u32(&mut data, width);
+    put_u32(&mut data, height);
+    full_box(b"ispe", 0, 0, &data)
+}
+
+/// Builds a HEIF file whose meta box contains the given primary item ID, item properties
+/// and the contents of a version 0 ipma box.
+fn build_heif(brands: &[&[u8]], primary_item: u16, properties: &[Vec<u8>], ipma: &[u8], ipma_flags: u8) -> Vec<u8> {
+    let mut ftyp = brands[0].to_vec();
+    ftyp.extend(&[0, 0, 0, 0]);
+    for brand in &brands[1..] {
+        ftyp.extend(*brand);
+    }
+
+    let mut pitm = Vec::new();
+    put_u16(&mut pitm, primary_item);
+    let mut ipco = Vec::new();
+    for p in properties {
+        ipco.extend(p);
+    }
+    let mut iprp = iso_box(b"ipco", &ipco);
+    iprp.extend(full_box(b"ipma", 0, ipma_flags, ipma));
+
+    let mut meta = full_box(b"hdlr", 0, 0, b"\0\0\0\0pict\0\0\0\0\0\0\0\0\0\0\0\0\0");
+    meta.extend(full_box(b"pitm", 0, 0, &pitm));
+    meta.extend(iso_box(b"iprp", &iprp));
+
+    let mut out = iso_box(b"ftyp", &ftyp);
+    out.extend(full_box(b"meta", 0, 0, &meta));
+    out.extend(iso_box(b"mdat", &[0; 16]));
+    out
+}
+
+#[test]
+fn test_avif() {
+    // item 1 is associated with properties 1 (av1C) and 2 (ispe)
+    let data = build_heif(
+        &[b"avif", b"mif1", b"miaf"], 1,
+        &[iso_box(b"av1C", &[0x81, 0, 0, 0]), ispe_box(640, 480)],
+        &[0, 0, 0, 1, 0, 1, 2, 0x81, 2], 0
+    );
+
+    let md = Heif::load_from_buf(&data).unwrap();
+    assert_eq!(md, heif::Metadata { brand: heif::Brand::Avif, dimensions: JPEG_DIM });
+
+    let md = immeta::load_from_buf(&data).unwrap();
+    assert_eq!(md.format(), immeta::Format::Avif);
+    assert_eq!(md.mime_type(), "image/avif");
+    assert_eq!(md.dimensions(), JPEG_DIM);
+    assert_eq!(immeta::guess_format(&data), Some(immeta::Format::Avif));
+}
+
+#[test]
+fn test_heic() {
+    // item 1 is a thumbnail with property 1, the primary item 2 has properties 3 and 2;
+    // the ipma box uses 16-bit property indices
+    let data = build_heif(
+        &[b"mif1", b"heic"], 2,
+        &[ispe_box(320, 240), ispe_box(4032, 3024), iso_box(b"hvcC", &[1])],
+        &[0, 0, 0, 2, 0, 1, 1, 0x80, 1, 0, 2, 2, 0x80, 3, 0, 2], 1
+    );
+
+    let md = Heif::load_from_buf(&data).unwrap();
+    assert_eq!(md.brand, heif::Brand::Mif1);
+    assert_eq!(md.dimensions, Dimensions { width: 4032, height: 3024 });
+
+    let mut heic = data.clone();
+    heic[8..12].copy_from_slice(b"msf1");
+    let md = Heif::load_from_buf(&heic).unwrap();
+    assert_eq!(md.brand, heif::Brand::Heic);
+
+    let md = immeta::load_from_buf(&data).unwrap();
+    assert_eq!(md.format(), immeta::Format::Heif);
+    assert_eq!(md.mime_type(), "image/heif");
+    assert_eq!(md.dimensions(), Dimensions { width: 4032, height: 3024 });
+}
+
+#[test]
+fn test_heif_invalid() {
+    let properties = [ispe_box(640, 480)];
+    let cases = [
+        // unsupported brand
+        build_heif(&[b"isom", b"mp41"], 1, &properties, &[0, 0, 0, 1, 0, 1, 1, 1], 0),
+        // no properties associated with the primary item
+        build_heif(&[b"avif"], 2, &properties, &[0, 0, 0, 1, 0, 1, 1, 1], 0),
+        // property index out of range
+        build_heif(&[b"avif"], 1, &properties, &[0, 0, 0, 1, 0, 1, 1, 2], 0),
+        // truncated meta box
+        build_heif(&[b"avif"], 1, &properties, &[0, 0, 0, 1, 0, 1, 1, 1], 0)[..60].to_vec(),
+        iso_box(b"ftyp", b"avif\0\0\0\0")
+    ];
+    for data in &cases {
+        assert!(Heif::load_from_buf(data).is_err());
+    }
+}
+
+#[test]
+fn test_generic_from_buf() {
+    let avif = build_heif(&[b"avif"], 1, &[ispe_box(640, 480)], &[0, 0, 0, 1, 0, 1, 1, 1], 0);
+    let cases = [
+        (read_file("tests/images/owlet.jpg"), immeta::Format::Jpeg, OWLET_DIM),
+        (read_file("tests/images/owlet.png"), immeta::Format::Png, OWLET_DIM),
+        (read_file("tests/images/drop.gif"), immeta::Format::Gif, DROP_DIM),
+        (read_file("tests/images/cherry.webp"), immeta::Format::Webp, CHERRY_DIM),
+        (build_bmp(INFO_HEADER_SIZE, 640, 480, 24, 0), immeta::Format::Bmp, JPEG_DIM),
+        (build_ico(1, &[(0, 0, icon_dib(32))]), immeta::Format::Ico, Dimensions { width: 256, height: 256 }),
+        (avif, immeta::Format::Avif, JPEG_DIM)
+    ];
+    for &(ref data, format, dimensions) in &cases {
+        let md = immeta::GenericMetadata::from_buf(data).unwrap();
+        assert_eq!(md.format(), format);
+        assert_eq!(md.dimensions(), dimensions);
+    }
+
+    match immeta::GenericMetadata::from_buf(b"definitely not an image") {
+        Err(Error::UnsupportedFormat { ref magic }) if magic == b"definite" => {}
+        r => panic!("unexpected result: {:?}", r.map(|md| md.format()))
+    }
+    // errors of the decoder of the detected format are returned
+    let png = read_file("tests/images/owlet.png");
+    match immeta::GenericMetadata::from_buf(&png[..20]) {
+        Err(Error::UnexpectedEndOfFile(_)) => {}
+        r => panic!("unexpected result: {:?}", r.map(|md| md.format()))
+    }
+}
+
+#[test]
+fn test_generic_metadata_accessors() {
+    let tiff = build_tiff(&[vec![(0x0100, Value::Short(vec![640])), (0x0101, Value::Short(vec![480]))]]);
+    let cases = [
+        (build_bmp(INFO_HEADER_SIZE, 640, 480, 24, 0), "image/bmp"),
+        (read_file("tests/images/cherry.webp"), "image/webp"),
+        (tiff, "image/tiff")
+    ];
+    for &(ref data, mime_type) in &cases {
+        // consumers which only need common properties do not match on the enum at all
+        let md = immeta::load_from_buf(data).unwrap();
+        assert_eq!(md.mime_type(), mime_type);
+        assert_eq!(md.format().mime_type(), mime_type);
+        assert!(md.dimensions().width > 0);
+
+        // matching on specific formats requires a wildcard arm
+        match md {
+            immeta::GenericMetadata::Tiff(_) => assert_eq!(mime_type, "image/tiff"),
+            _ => assert!(mime_type != "image/tiff")
+        }
+    }
+}
+
+#[test]
+fn test_large_dimensions() {
+    let expected = Dimensions { width: 40000, height: 30000 };
+    assert_eq!(Dimensions::from((40000u16, 30000u16)), expected);
+    assert_eq!(Dimensions::from((40000u32, 30000u32)), expected);
+    assert_eq!(Dimensions::from((100000u32, 1u8)), Dimensions { width: 100000, height: 1 });
+
+    let data = build_tiff(&[vec![(0x0100, Value::Long(vec![40000])), (0x0101, Value::Short(vec![30000]))]]);
+    let md = immeta::load_from_buf(&data).unwrap();
+    assert_eq!(md.dimensions(), expected);
+    assert_eq!(md.dimensions().area(), 1_200_000_000);
+}
+
+#[test]
+fn test_unsupported_format() {
+    // RAR 5 archive signature followed by some data
+    let rar = b"Rar!\x1a\x07\x01\x00\x33\x92\xb5\xe5\x0a\x01\x05\x06\x00\x05\x01\x01\x80\x80\x00";
+
+    let e = immeta::load_from_buf(rar).map(|md| md.format()).unwrap_err();
+    match e {
+        Error::UnsupportedFormat { ref magic } => assert_eq!(&magic[..], &rar[..8]),
+        ref e => panic!("unexpected error: {:?}", e)
+    }
+    assert_eq!(e.to_string(), "unsupported format, magic: [52, 61, 72, 21, 1a, 07, 01, 00]");
+
+    match immeta::GenericMetadata::from_buf(rar) {
+        Err(Error::UnsupportedFormat { ref magic }) => assert_eq!(&magic[..], &rar[..8]),
+        r => panic!("unexpected result: {:?}", r.map(|md| md.format()))
+    }
+
+    // short inputs are reported as is
+    match immeta::load_from_buf(b"Rar") {
+        Err(Error::UnsupportedFormat { ref magic }) => assert_eq!(magic, b"Rar"),
+        r => panic!("unexpected result: {:?}", r.map(|md| md.format()))
+    }
+}
+
+#[test]
+fn test_tiff() {
+    let data = build_tiff(&[vec![
+        (0x0100, Value::Short(vec![640])),
+        (0x0101, Value::Long(vec![480])),
+        (0x0112, Value::Short(vec![6]))
+    ]]);
+
+    let md = Tiff::load_from_buf(&data).unwrap();
+    assert_eq!(md, tiff::Metadata {
+        dimensions: JPEG_DIM,
+        byte_order: tiff::ByteOrder::BigEndian,
+        orientation: Some(jpeg::Orientation::Rotate90)
+    });
+    assert_eq!(md.oriented_dimensions(), Dimensions { width: 480, height: 640 });
+
+    let md = immeta::load_from_buf(&data).unwrap();
+    assert_eq!(md.format(), immeta::Format::Tiff);
+    assert_eq!(md.mime_type(), "image/tiff");
+    assert_eq!(md.dimensions(), JPEG_DIM);
+    assert_eq!(md.display_dimensions(), Dimensions { width: 480, height: 640 });
+    assert_eq!(immeta::guess_format(&data), Some(immeta::Format::Tiff));
+    assert_eq!(immeta::GenericMetadata::from_buf(&data).unwrap().format(), immeta::Format::Tiff);
+
+    let md = Tiff::load_from_buf(&build_tiff(&[vec![
+        (0x0100, Value::Long(vec![640])),
+        (0x0101, Value::Short(vec![480]))
+    ]])).unwrap();
+    assert_eq!(md.orientation, None);
+    assert_eq!(md.oriented_dimensions(), JPEG_DIM);
+
+    // image length is missing
+    assert!(Tiff::load_from_buf(&build_tiff(&[vec![(0x0100, Value::Short(vec![640]))]])).is_err());
+    assert!(Tiff::load_from_buf(b"MM\0\x2b").is_err());
+}
+
+/// An asynchronous reader which is not ready on every other poll.
+#[cfg(feature = "tokio")]
+struct SlowAsyncReader<'a> {
+    data: &'a [u8],
+    ready: bool
+}
+
+#[cfg(feature = "tokio")]
+impl<'a> tokio::io::AsyncRead for SlowAsyncReader<'a> {
+    fn poll_read(mut self: std::pin::Pin<&mut Self>, cx: &mut std::task::Context,
+                 buf: &mut tokio::io::ReadBuf) -> std::task::Poll<io::Result<()>> {
+        self.ready = !self.ready;
+        if !self.ready {
+            cx.waker().wake_by_ref();
+            return std::task::Poll::Pending;
+        }
+        let n = std::cmp::min(self.data.len(), std::cmp::min(buf.remaining(), 1000));
+        buf.put_slice(&self.data[..n]);
+        self.data = &self.data[n..];
+        std::task::Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(feature = "tokio")]
+#[test]
+fn test_load_from_async_read() {
+    let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+
+    // metadata is far enough from the start of the stream to require several reads
+    let app1 = segment(0xe1, &vec![0; 65533]);
+    let mut segments = vec![app1; 3];
+    segments.push(exif_segment(&[vec![(0x0110, Value::Ascii("Owl Cam"))]]));
+    let data = build_jpeg(&segments);
+
+    let mut r = SlowAsyncReader { data: &data, ready: false };
+    let md = runtime.block_on(immeta::load_from_async_read(&mut r)).unwrap();
+    assert_eq!(md.dimensions(), JPEG_DIM);
+    assert_eq!(md.as_ref::<Jpeg>().unwrap().model, Some("Owl Cam".to_owned()));
+
+    let mut r = &build_png(&[])[..];
+    let md = runtime.block_on(immeta::load_from_async_read(&mut r)).unwrap();
+    assert_eq!(md.format(), immeta::Format::Png);
+
+    let mut r = &data[..100000];
+    match runtime.block_on(immeta::load_from_async_read(&mut r)) {
+        Err(Error::UnexpectedEndOfFile(_)) => {}
+        Err(e) => panic!("unexpected error: {}", e),
+        Ok(_) => panic!("truncated image was accepted")
+    }
 
-    println!("{:?}", md);
+    let mut r: &[u8] = b"definitely not an image";
+    assert!(runtime.block_on(immeta::load_from_async_read(&mut r)).is_err());
 }